@@ -0,0 +1,339 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::services::filename::FilenameTemplate;
+use crate::services::format_ranking::{default_codec_preference, VideoCodec};
+use crate::services::key_store::ApiKeyEntry;
+use crate::services::segments::SegmentCategory;
+
+const DEFAULT_CONFIG_PATH: &str = "vidsaver.json";
+
+/// Runtime configuration, loaded from the JSON file named by `VIDSAVER_CONFIG`
+/// (or `vidsaver.json` in the working directory when present).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub cors: CorsConfig,
+    pub downloads: DownloadConfig,
+    pub formats: FormatConfig,
+    pub transcripts: TranscriptConfig,
+    pub jobs: JobConfig,
+    pub loudness: LoudnessConfig,
+    pub transcode: TranscodeConfig,
+    pub animations: AnimationConfig,
+    pub segments: SegmentConfig,
+    pub live: LiveConfig,
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Reject requests without a valid API key. Off by default so a fresh
+    /// checkout keeps working with the bundled frontend.
+    pub enabled: bool,
+    /// Keys declared inline in the config file.
+    pub keys: Vec<ApiKeyEntry>,
+    /// JSON file holding keys managed through the admin endpoints.
+    pub keys_file: Option<PathBuf>,
+    /// Admin key taken from `VIDSAVER_ADMIN_KEY`, never read from the file.
+    #[serde(skip)]
+    pub bootstrap_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Proxies (addresses or CIDR ranges) whose `X-Forwarded-For` is trusted.
+    pub trusted_proxies: Vec<String>,
+    /// Budget for the inspect routes (`/api/video-info`,
+    /// `/api/quality-options`, `/api/format-preview`, `/api/transcript`,
+    /// `/api/thumbnails` and `/api/thumbnail`) and for polling, fetching,
+    /// stopping and deleting jobs.
+    pub metadata: BucketConfig,
+    /// Budget for `/api/download` and for starting jobs with `POST /api/jobs`
+    /// and `POST /api/jobs/:id/storyboard`.
+    pub download: BucketConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trusted_proxies: Vec::new(),
+            metadata: BucketConfig {
+                capacity: 30,
+                refill_per_minute: 30,
+            },
+            download: BucketConfig {
+                capacity: 5,
+                refill_per_minute: 5,
+            },
+        }
+    }
+}
+
+/// A token bucket holding up to `capacity` requests, refilled continuously at
+/// `refill_per_minute`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct BucketConfig {
+    pub capacity: u32,
+    pub refill_per_minute: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Exact origins (`https://app.example.com`), wildcard subdomains
+    /// (`https://*.example.com`) or `*` for any origin.
+    pub allowed_origins: Vec<String>,
+    /// HTTP methods, or `*` for any.
+    pub allowed_methods: Vec<String>,
+    /// Request headers, or `*` for any.
+    pub allowed_headers: Vec<String>,
+    /// Response headers readable by browser code in addition to the ones
+    /// VidSaver always exposes.
+    pub expose_headers: Vec<String>,
+    pub allow_credentials: bool,
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["*".to_string()],
+            allowed_methods: vec!["*".to_string()],
+            allowed_headers: vec!["*".to_string()],
+            expose_headers: Vec::new(),
+            allow_credentials: false,
+            max_age_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// Default name for downloaded files, e.g. `{uploader} - {title} [{id}].{ext}`.
+    pub filename_template: FilenameTemplate,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FormatConfig {
+    /// Video codecs in order of preference when picking one format per
+    /// resolution (`av1`, `vp9`, `h264`, `hevc`).
+    pub codec_preference: Vec<VideoCodec>,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            codec_preference: default_codec_preference(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TranscriptConfig {
+    /// How long a fetched transcript is served from memory.
+    pub cache_ttl_secs: u64,
+    /// Transcripts kept at once; 0 disables the cache.
+    pub cache_max_entries: usize,
+}
+
+impl Default for TranscriptConfig {
+    fn default() -> Self {
+        Self {
+            cache_ttl_secs: 3600,
+            cache_max_entries: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JobConfig {
+    /// Jobs downloading or processing at once; the rest wait in the queue.
+    pub max_running: usize,
+    /// Jobs kept at once, including finished ones whose file is uncollected.
+    pub max_jobs: usize,
+    /// How long a finished job and its file are kept.
+    pub result_ttl_secs: u64,
+    /// Total size of the files kept for finished jobs; the oldest are
+    /// dropped early to stay under it.
+    pub max_retained_bytes: u64,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            max_running: 2,
+            max_jobs: 50,
+            result_ttl_secs: 3600,
+            max_retained_bytes: 2_000_000_000,
+        }
+    }
+}
+
+/// Defaults for loudness normalization when a request leaves them out.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct LoudnessConfig {
+    /// Integrated loudness in LUFS.
+    pub target_lufs: f64,
+    /// Maximum true peak in dBTP.
+    pub true_peak: f64,
+    /// Loudness range in LU.
+    pub loudness_range: f64,
+}
+
+impl Default for LoudnessConfig {
+    fn default() -> Self {
+        Self {
+            target_lufs: -16.0,
+            true_peak: -1.5,
+            loudness_range: 11.0,
+        }
+    }
+}
+
+/// Encoder settings for transcoded downloads.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TranscodeConfig {
+    /// x264/x265 speed preset, `ultrafast` to `veryslow`; slower presets give
+    /// smaller files for the same quality.
+    pub speed_preset: String,
+    /// Constant rate factor for H.264; lower is better quality.
+    pub crf: u8,
+    /// Constant rate factor for HEVC, whose scale sits about 5 above H.264's.
+    pub hevc_crf: u8,
+    /// Encoder threads; 0 lets ffmpeg pick.
+    pub threads: usize,
+}
+
+impl Default for TranscodeConfig {
+    fn default() -> Self {
+        Self {
+            speed_preset: "veryfast".to_string(),
+            crf: 23,
+            hevc_crf: 28,
+            threads: 0,
+        }
+    }
+}
+
+/// Limits and defaults for `gif` and `webp` downloads.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AnimationConfig {
+    /// Longest clip that can be animated, in seconds.
+    pub max_duration_secs: f64,
+    /// Largest animation returned, in bytes.
+    pub max_filesize: u64,
+    pub max_width: u32,
+    pub max_fps: u32,
+    pub default_width: u32,
+    pub default_fps: u32,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            max_duration_secs: 15.0,
+            max_filesize: 20_000_000,
+            max_width: 1280,
+            max_fps: 30,
+            default_width: 480,
+            default_fps: 12,
+        }
+    }
+}
+
+/// Where segments to skip are looked up.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SegmentConfig {
+    /// Base URL of a SponsorBlock-compatible server.
+    pub api_url: String,
+    /// Categories acted on when a request names none.
+    pub categories: Vec<SegmentCategory>,
+    pub timeout_secs: u64,
+}
+
+impl Default for SegmentConfig {
+    fn default() -> Self {
+        Self {
+            api_url: "https://sponsor.ajay.app".to_string(),
+            categories: vec![SegmentCategory::Sponsor, SegmentCategory::Selfpromo, SegmentCategory::Interaction],
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// Limits on live stream recordings.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct LiveConfig {
+    /// Longest recording, in seconds.
+    pub max_duration_secs: f64,
+    /// Furthest ahead a scheduled stream may be to be waited for.
+    pub max_wait_secs: u64,
+    /// How often yt-dlp checks whether a waited-for stream has started.
+    pub wait_retry_secs: u64,
+    /// Bitrate assumed, in kbit/s, for a stream whose formats do not report
+    /// one when working out how long a recording fits in
+    /// `jobs.max_retained_bytes`.
+    pub assumed_bitrate_kbps: f64,
+}
+
+impl Default for LiveConfig {
+    fn default() -> Self {
+        Self {
+            max_duration_secs: 14400.0,
+            max_wait_secs: 86400,
+            wait_retry_secs: 60,
+            assumed_bitrate_kbps: 8000.0,
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn load() -> Result<Self> {
+        let path = match std::env::var("VIDSAVER_CONFIG") {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => {
+                let default = PathBuf::from(DEFAULT_CONFIG_PATH);
+                default.exists().then_some(default)
+            }
+        };
+
+        let mut config = match &path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.source = path;
+
+        // Allow bootstrapping an admin key without writing a config file
+        if let Ok(key) = std::env::var("VIDSAVER_ADMIN_KEY") {
+            config.auth.enabled = true;
+            config.auth.bootstrap_key = Some(key);
+        }
+
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        serde_json::from_str(&raw)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+}
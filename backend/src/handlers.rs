@@ -1,44 +1,213 @@
 use axum::{
-    extract::Json,
-    http::{header, StatusCode},
+    body::Bytes,
+    extract::{Extension, Json, Path, State},
+    http::{header, HeaderName, StatusCode},
     response::{IntoResponse, Response},
 };
-use crate::services::youtube_service;
+use crate::error::ApiError;
+use crate::locale::Locale;
+use crate::services::content_type;
+use crate::services::filename::content_disposition;
+use crate::services::jobs::JobView;
+use crate::services::key_store::{ApiKeyEntry, ApiKeyIdentity, ApiKeySummary, KeyStoreError};
+use crate::services::storyboard::StoryboardOptions;
+use crate::services::transcript::TranscriptFormat;
+use crate::services::youtube_service::{self, RankingOptions};
+use crate::state::AppState;
 use crate::types::*;
 
-pub async fn get_video_info(Json(request): Json<VideoInfoRequest>) -> Result<Json<VideoInfo>, AppError> {
-    let video_info = youtube_service::extract_video_info(&request.url).await?;
-    Ok(Json(video_info))
+pub async fn get_video_info(
+    locale: Locale,
+    Json(request): Json<VideoInfoRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let video_info = youtube_service::extract_video_info(&request.url, locale).await?;
+    Ok((locale.headers(), Json(video_info)))
 }
 
-pub async fn get_quality_options(Json(request): Json<QualityOptionsRequest>) -> Result<Json<QualityOptions>, AppError> {
-    let quality_options = youtube_service::extract_quality_options(&request.url).await?;
-    Ok(Json(quality_options))
+pub async fn get_quality_options(
+    State(state): State<AppState>,
+    locale: Locale,
+    Json(request): Json<QualityOptionsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let ranking = RankingOptions {
+        codec_preference: request
+            .codec_preference
+            .unwrap_or_else(|| state.config.formats.codec_preference.clone()),
+        include_alternatives: request.include_alternatives,
+    };
+    let quality_options = youtube_service::extract_quality_options(&request.url, locale, &ranking).await?;
+    Ok((locale.headers(), Json(quality_options)))
 }
 
-pub async fn download_video(Json(request): Json<DownloadRequest>) -> Result<Response, AppError> {
-    let (file_data, filename) = youtube_service::download_video(request).await?;
-    
-    let content_type = match filename.split('.').last() {
-        Some("mp4") => "video/mp4",
-        Some("webm") => "video/webm",
-        Some("mp3") => "audio/mpeg",
-        Some("m4a") => "audio/mp4",
-        _ => "application/octet-stream",
+pub async fn get_transcript(
+    State(state): State<AppState>,
+    Json(request): Json<TranscriptRequest>,
+) -> Result<Response, AppError> {
+    let (transcript, cached) =
+        youtube_service::extract_transcript(&request.url, &request.language, &state.transcripts).await?;
+    let cache_status = if cached { "hit" } else { "miss" };
+
+    let response = match request.format {
+        TranscriptFormat::Json => {
+            ([(HeaderName::from_static("x-cache"), cache_status)], Json(transcript.as_ref())).into_response()
+        }
+        TranscriptFormat::Text | TranscriptFormat::Markdown => {
+            let (body, content_type) = match request.format {
+                TranscriptFormat::Markdown => (transcript.to_markdown(request.timestamps), "text/markdown; charset=utf-8"),
+                _ => (transcript.to_text(request.timestamps), "text/plain; charset=utf-8"),
+            };
+            (
+                [(header::CONTENT_TYPE, content_type), (HeaderName::from_static("x-cache"), cache_status)],
+                body,
+            )
+                .into_response()
+        }
     };
 
-    let response = Response::builder()
+    Ok(response)
+}
+
+pub async fn get_thumbnails(Json(request): Json<VideoInfoRequest>) -> Result<Json<Vec<ThumbnailInfo>>, AppError> {
+    Ok(Json(youtube_service::list_thumbnails(&request.url).await?))
+}
+
+pub async fn get_thumbnail(Json(request): Json<ThumbnailRequest>) -> Result<Response, AppError> {
+    let (data, filename) = youtube_service::fetch_thumbnail(&request).await?;
+    Ok(file_response(data, &filename, request.format.mime()))
+}
+
+pub async fn preview_format(
+    State(state): State<AppState>,
+    locale: Locale,
+    Json(request): Json<FormatPreviewRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let preview = youtube_service::preview_format(request, &state.config, locale).await?;
+    Ok((locale.headers(), Json(preview)))
+}
+
+pub async fn download_video(
+    State(state): State<AppState>,
+    Json(request): Json<DownloadRequest>,
+) -> Result<Response, AppError> {
+    let output = youtube_service::download_video(request, &state.config, None).await?;
+    let content_type = content_type::detect(&output.data, &output.filename).to_string();
+    Ok(file_response(output.data, &output.filename, &content_type))
+}
+
+pub async fn create_job(
+    State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
+    Json(request): Json<DownloadRequest>,
+) -> Result<Response, AppError> {
+    let job = state.jobs.create(identity.as_deref())?;
+    let view = job.view();
+    tokio::spawn(youtube_service::run_download_job(job, request, state.config.clone()));
+
+    let location = format!("/api/jobs/{}", view.id);
+    Ok((StatusCode::ACCEPTED, [(header::LOCATION, location)], Json(view)).into_response())
+}
+
+pub async fn get_job(
+    State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
+    Path(id): Path<String>,
+) -> Result<Json<JobView>, AppError> {
+    Ok(Json(state.jobs.get(&id, identity.as_deref())?))
+}
+
+pub async fn get_job_file(
+    State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let (data, result) = state.jobs.file(&id, identity.as_deref())?;
+    Ok(file_response(data, &result.filename, &result.content_type))
+}
+
+/// Build a storyboard from a finished job's video rather than downloading it
+/// again.
+pub async fn create_job_storyboard(
+    State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
+    Path(id): Path<String>,
+    Json(options): Json<StoryboardOptions>,
+) -> Result<Response, AppError> {
+    let (data, result) = state.jobs.file(&id, identity.as_deref())?;
+    if !result.content_type.starts_with("video/") {
+        return Err(ApiError::Conflict(format!("Job {} produced {}, not a video", id, result.content_type)).into());
+    }
+    let (data, filename) = youtube_service::storyboard_from_file(&data, &result.filename, &options).await?;
+    let content_type = content_type::detect(&data, &filename).to_string();
+    Ok(file_response(data, &filename, &content_type))
+}
+
+pub async fn delete_job(
+    State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    state.jobs.remove(&id, identity.as_deref())?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Finish a live recording early. The job keeps running while the file is
+/// finalized, so this answers `202 Accepted`.
+pub async fn stop_job(
+    State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let view = state.jobs.stop(&id, identity.as_deref())?;
+    Ok((StatusCode::ACCEPTED, Json(view)).into_response())
+}
+
+fn file_response(data: impl Into<Bytes>, filename: &str, content_type: &str) -> Response {
+    let data = data.into();
+    Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, content_type)
-        .header(
-            header::CONTENT_DISPOSITION,
-            format!("attachment; filename=\"{}\"", filename),
-        )
-        .header(header::CONTENT_LENGTH, file_data.len())
-        .body(axum::body::Body::from(file_data))
-        .unwrap();
+        .header(header::CONTENT_DISPOSITION, content_disposition(filename))
+        .header(header::CONTENT_LENGTH, data.len())
+        .body(axum::body::Body::from(data))
+        .unwrap()
+}
 
-    Ok(response)
+pub async fn list_api_keys(State(state): State<AppState>) -> Json<Vec<ApiKeySummary>> {
+    Json(state.keys.list())
+}
+
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    Json(request): Json<CreateApiKeyRequest>,
+) -> Result<Json<ApiKeyEntry>, AppError> {
+    let entry = state
+        .keys
+        .create(&request.name, request.scopes)
+        .map_err(key_store_error)?;
+    Ok(Json(entry))
+}
+
+pub async fn revoke_api_key(State(state): State<AppState>, Path(name): Path<String>) -> Result<StatusCode, AppError> {
+    state
+        .keys
+        .revoke(&name)
+        .map_err(key_store_error)?;
+    println!("API key revoked: {}", name);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn key_store_error(error: KeyStoreError) -> AppError {
+    match error {
+        KeyStoreError::NotFound(_) => ApiError::NotFound(error.to_string()).into(),
+        KeyStoreError::Conflict(_) => ApiError::Conflict(error.to_string()).into(),
+        KeyStoreError::Invalid(message) => ApiError::BadRequest(message).into(),
+        KeyStoreError::Io(error) => AppError(error),
+    }
+}
+
+pub async fn reload_api_keys(State(state): State<AppState>) -> Result<Json<ReloadKeysResponse>, AppError> {
+    let keys = state.keys.reload()?;
+    Ok(Json(ReloadKeysResponse { keys }))
 }
 
 // Error handling
@@ -47,6 +216,10 @@ pub struct AppError(anyhow::Error);
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Some(error) = self.0.downcast_ref::<ApiError>() {
+            return (error.status(), error.to_string()).into_response();
+        }
+
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Something went wrong: {}", self.0),
@@ -0,0 +1,427 @@
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::{header, request::Parts, HeaderName},
+};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Locales with bundled translations. Requests for anything else fall back
+/// to English.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Fr,
+    Es,
+    Pt,
+    Ja,
+}
+
+fn round_decimal(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+/// Units for relative dates, smallest first.
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 6] = [Locale::En, Locale::De, Locale::Fr, Locale::Es, Locale::Pt, Locale::Ja];
+
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+            Locale::Es => "es",
+            Locale::Pt => "pt",
+            Locale::Ja => "ja",
+        }
+    }
+
+    /// Headers for a response rendered in this locale. The language comes
+    /// from `Accept-Language`, so caches have to key on it.
+    pub fn headers(self) -> [(HeaderName, &'static str); 2] {
+        [(header::CONTENT_LANGUAGE, self.tag()), (header::VARY, "Accept-Language")]
+    }
+
+    /// Match a BCP 47 tag on its primary language (`pt-BR` → `pt`).
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        Self::ALL.into_iter().find(|locale| locale.tag() == primary)
+    }
+
+    /// Pick the best bundled locale from an `Accept-Language` header,
+    /// honouring q-values.
+    pub fn negotiate(accept_language: &str) -> Option<Self> {
+        let mut candidates: Vec<(f32, usize, Locale)> = accept_language
+            .split(',')
+            .enumerate()
+            .filter_map(|(position, entry)| {
+                let mut parts = entry.split(';');
+                let locale = Self::from_tag(parts.next()?)?;
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+                (quality > 0.0).then_some((quality, position, locale))
+            })
+            .collect();
+
+        // Highest quality wins; ties keep header order
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        candidates.first().map(|(_, _, locale)| *locale)
+    }
+
+    fn decimal_separator(self) -> char {
+        match self {
+            Locale::En | Locale::Ja => '.',
+            _ => ',',
+        }
+    }
+
+    /// One decimal place, dropped when it is zero (`1.0K` → `1K`).
+    fn format_decimal(self, value: f64) -> String {
+        let rounded = round_decimal(value);
+        let text = if rounded.fract() == 0.0 {
+            format!("{}", rounded as u64)
+        } else {
+            format!("{:.1}", rounded)
+        };
+        text.replace('.', &self.decimal_separator().to_string())
+    }
+
+    /// Compact number formatting, e.g. `1.4B` / `1,4 Mrd.` / `14億`.
+    pub fn format_compact(self, value: u64) -> String {
+        // (threshold, suffix), largest first
+        let steps: &[(f64, &str)] = match self {
+            Locale::En => &[(1e9, "B"), (1e6, "M"), (1e3, "K")],
+            Locale::De => &[(1e9, " Mrd."), (1e6, " Mio."), (1e3, " Tsd.")],
+            Locale::Fr => &[(1e9, " Md"), (1e6, " M"), (1e3, " k")],
+            Locale::Es => &[(1e9, " mil M"), (1e6, " M"), (1e3, " mil")],
+            Locale::Pt => &[(1e9, " bi"), (1e6, " mi"), (1e3, " mil")],
+            // Japanese groups by 10^4 (万) and 10^8 (億)
+            Locale::Ja => &[(1e8, "億"), (1e4, "万")],
+        };
+
+        let value_f = value as f64;
+        for (i, (threshold, suffix)) in steps.iter().enumerate() {
+            if value_f < *threshold {
+                continue;
+            }
+            // 999,950 rounds up to 1000K, which reads better as 1M
+            if let Some((larger, larger_suffix)) = i.checked_sub(1).map(|larger| steps[larger]) {
+                if round_decimal(value_f / threshold) * threshold >= larger {
+                    return format!("{}{}", self.format_decimal(value_f / larger), larger_suffix);
+                }
+            }
+            return format!("{}{}", self.format_decimal(value_f / threshold), suffix);
+        }
+        value.to_string()
+    }
+
+    pub fn format_views(self, views: u64) -> String {
+        let count = self.format_compact(views);
+        match self {
+            Locale::En if views == 1 => "1 view".to_string(),
+            Locale::En => format!("{} views", count),
+            Locale::De => format!("{} Aufrufe", count),
+            Locale::Fr if views <= 1 => format!("{} vue", count),
+            Locale::Fr => format!("{} vues", count),
+            Locale::Es if views == 1 => "1 visualización".to_string(),
+            Locale::Es => format!("{} visualizaciones", count),
+            Locale::Pt if views == 1 => "1 visualização".to_string(),
+            Locale::Pt => format!("{} visualizações", count),
+            Locale::Ja => format!("{}回視聴", count),
+        }
+    }
+
+    /// Binary file sizes with localized unit names (French uses octets).
+    pub fn format_file_size(self, bytes: u64) -> String {
+        let units: [&str; 5] = match self {
+            Locale::Fr => ["o", "Ko", "Mo", "Go", "To"],
+            _ => ["B", "KB", "MB", "GB", "TB"],
+        };
+
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        // Compare what will be shown, so 1023.96 KB becomes 1 MB, not 1024 KB
+        while round_decimal(size) >= 1024.0 && unit < units.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+
+        let number = if unit == 0 {
+            bytes.to_string()
+        } else {
+            self.format_decimal(size)
+        };
+        format!("{} {}", number, units[unit])
+    }
+
+    /// Video length, cut to whole seconds: a clock (`1:02:03`) where that is
+    /// how lengths are written, otherwise units (`1 h 02 min 03 s`).
+    pub fn format_duration(self, seconds: f64) -> String {
+        // Saturates, and NaN becomes 0
+        let total = seconds.max(0.0) as u64;
+        let (hours, minutes, secs) = (total / 3600, total % 3600 / 60, total % 60);
+        match self {
+            Locale::En | Locale::De | Locale::Es | Locale::Pt if hours > 0 => {
+                format!("{}:{:02}:{:02}", hours, minutes, secs)
+            }
+            Locale::En | Locale::De | Locale::Es | Locale::Pt => format!("{}:{:02}", minutes, secs),
+            Locale::Fr if hours > 0 => format!("{} h {:02} min {:02} s", hours, minutes, secs),
+            Locale::Fr => format!("{} min {:02} s", minutes, secs),
+            Locale::Ja if hours > 0 => format!("{}時間{}分{}秒", hours, minutes, secs),
+            Locale::Ja => format!("{}分{}秒", minutes, secs),
+        }
+    }
+
+    /// `upload_date` from yt-dlp (`YYYYMMDD`) relative to today.
+    pub fn format_upload_date(self, date: &str) -> Option<String> {
+        let uploaded = days_from_yyyymmdd(date)?;
+        let today = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64 / 86_400;
+        Some(self.format_days_ago(today - uploaded))
+    }
+
+    fn format_days_ago(self, days: i64) -> String {
+        if days <= 0 {
+            return match self {
+                Locale::En => "today",
+                Locale::De => "heute",
+                Locale::Fr => "aujourd'hui",
+                Locale::Es => "hoy",
+                Locale::Pt => "hoje",
+                Locale::Ja => "今日",
+            }
+            .to_string();
+        }
+
+        let (n, unit) = match days {
+            1..=6 => (days, Unit::Day),
+            7..=29 => (days / 7, Unit::Week),
+            30..=364 => (days / 30, Unit::Month),
+            _ => (days / 365, Unit::Year),
+        };
+        self.format_ago(n, unit)
+    }
+
+    fn format_ago(self, n: i64, unit: Unit) -> String {
+        let one = n == 1;
+        match self {
+            Locale::En => {
+                let word = match unit {
+                    Unit::Day => "day",
+                    Unit::Week => "week",
+                    Unit::Month => "month",
+                    Unit::Year => "year",
+                };
+                format!("{} {}{} ago", n, word, if one { "" } else { "s" })
+            }
+            Locale::De => {
+                // Dative plural after "vor"
+                let word = match (unit, one) {
+                    (Unit::Day, true) => "Tag",
+                    (Unit::Day, false) => "Tagen",
+                    (Unit::Week, true) => "Woche",
+                    (Unit::Week, false) => "Wochen",
+                    (Unit::Month, true) => "Monat",
+                    (Unit::Month, false) => "Monaten",
+                    (Unit::Year, true) => "Jahr",
+                    (Unit::Year, false) => "Jahren",
+                };
+                format!("vor {} {}", n, word)
+            }
+            Locale::Fr => {
+                let word = match (unit, one) {
+                    (Unit::Day, true) => "jour",
+                    (Unit::Day, false) => "jours",
+                    (Unit::Week, true) => "semaine",
+                    (Unit::Week, false) => "semaines",
+                    (Unit::Month, _) => "mois",
+                    (Unit::Year, true) => "an",
+                    (Unit::Year, false) => "ans",
+                };
+                format!("il y a {} {}", n, word)
+            }
+            Locale::Es => {
+                let word = match (unit, one) {
+                    (Unit::Day, true) => "día",
+                    (Unit::Day, false) => "días",
+                    (Unit::Week, true) => "semana",
+                    (Unit::Week, false) => "semanas",
+                    (Unit::Month, true) => "mes",
+                    (Unit::Month, false) => "meses",
+                    (Unit::Year, true) => "año",
+                    (Unit::Year, false) => "años",
+                };
+                format!("hace {} {}", n, word)
+            }
+            Locale::Pt => {
+                let word = match (unit, one) {
+                    (Unit::Day, true) => "dia",
+                    (Unit::Day, false) => "dias",
+                    (Unit::Week, true) => "semana",
+                    (Unit::Week, false) => "semanas",
+                    (Unit::Month, true) => "mês",
+                    (Unit::Month, false) => "meses",
+                    (Unit::Year, true) => "ano",
+                    (Unit::Year, false) => "anos",
+                };
+                format!("há {} {}", n, word)
+            }
+            Locale::Ja => {
+                let word = match unit {
+                    Unit::Day => "日前",
+                    Unit::Week => "週間前",
+                    Unit::Month => "か月前",
+                    Unit::Year => "年前",
+                };
+                format!("{}{}", n, word)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LocaleQuery {
+    lang: Option<String>,
+}
+
+/// Resolves the response locale from `?lang=`, then `Accept-Language`,
+/// then English.
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Locale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let query = Query::<LocaleQuery>::from_request_parts(parts, state).await.ok();
+        if let Some(locale) = query.and_then(|q| q.0.lang).and_then(|lang| Locale::from_tag(&lang)) {
+            return Ok(locale);
+        }
+
+        Ok(parts
+            .headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Locale::negotiate)
+            .unwrap_or_default())
+    }
+}
+
+/// Days since the Unix epoch for a `YYYYMMDD` date (proleptic Gregorian).
+fn days_from_yyyymmdd(date: &str) -> Option<i64> {
+    if date.len() != 8 || !date.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let year: i64 = date[..4].parse().ok()?;
+    let month: i64 = date[4..6].parse().ok()?;
+    let day: i64 = date[6..].parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Howard Hinnant's days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_numbers_use_locale_suffixes() {
+        assert_eq!(Locale::En.format_compact(1_400_000_000), "1.4B");
+        assert_eq!(Locale::En.format_compact(999), "999");
+        assert_eq!(Locale::En.format_compact(2_000), "2K");
+        assert_eq!(Locale::De.format_compact(2_300_000), "2,3 Mio.");
+        assert_eq!(Locale::Fr.format_compact(15_500), "15,5 k");
+        assert_eq!(Locale::Ja.format_compact(1_400_000_000), "14億");
+        assert_eq!(Locale::Ja.format_compact(23_000), "2.3万");
+    }
+
+    #[test]
+    fn compact_numbers_move_up_when_rounding_reaches_the_next_unit() {
+        assert_eq!(Locale::En.format_compact(999_950), "1M");
+        assert_eq!(Locale::En.format_compact(999_949), "999.9K");
+        assert_eq!(Locale::En.format_compact(999_999_999), "1B");
+        assert_eq!(Locale::De.format_compact(999_960), "1 Mio.");
+        assert_eq!(Locale::Ja.format_compact(99_999_999), "1億");
+        // Nothing above billions to move up to
+        assert_eq!(Locale::En.format_compact(999_999_999_999), "1000B");
+    }
+
+    #[test]
+    fn views_are_localized() {
+        assert_eq!(Locale::En.format_views(1_400_000_000), "1.4B views");
+        assert_eq!(Locale::En.format_views(1), "1 view");
+        assert_eq!(Locale::De.format_views(1_500), "1,5 Tsd. Aufrufe");
+        assert_eq!(Locale::Es.format_views(42), "42 visualizaciones");
+        assert_eq!(Locale::Ja.format_views(50_000), "5万回視聴");
+    }
+
+    #[test]
+    fn file_sizes_are_binary_and_localized() {
+        assert_eq!(Locale::En.format_file_size(512), "512 B");
+        assert_eq!(Locale::En.format_file_size(89 * 1024 * 1024), "89 MB");
+        assert_eq!(Locale::De.format_file_size(1536), "1,5 KB");
+        assert_eq!(Locale::Fr.format_file_size(3 * 1024 * 1024 * 1024), "3 Go");
+        assert_eq!(Locale::En.format_file_size(1024 * 1024 - 1), "1 MB");
+        assert_eq!(Locale::En.format_file_size(1023), "1023 B");
+    }
+
+    #[test]
+    fn durations_are_localized() {
+        assert_eq!(Locale::En.format_duration(213.0), "3:33");
+        assert_eq!(Locale::En.format_duration(3723.9), "1:02:03");
+        assert_eq!(Locale::De.format_duration(59.0), "0:59");
+        assert_eq!(Locale::Pt.format_duration(36_000.0), "10:00:00");
+        assert_eq!(Locale::Fr.format_duration(213.0), "3 min 33 s");
+        assert_eq!(Locale::Fr.format_duration(3723.0), "1 h 02 min 03 s");
+        assert_eq!(Locale::Ja.format_duration(213.0), "3分33秒");
+        assert_eq!(Locale::Ja.format_duration(3723.0), "1時間2分3秒");
+        assert_eq!(Locale::En.format_duration(-1.0), "0:00");
+        assert_eq!(Locale::En.format_duration(f64::NAN), "0:00");
+    }
+
+    #[test]
+    fn relative_dates_pick_the_largest_unit() {
+        assert_eq!(Locale::En.format_days_ago(0), "today");
+        assert_eq!(Locale::En.format_days_ago(1), "1 day ago");
+        assert_eq!(Locale::En.format_days_ago(15), "2 weeks ago");
+        assert_eq!(Locale::De.format_days_ago(400), "vor 1 Jahr");
+        assert_eq!(Locale::Fr.format_days_ago(90), "il y a 3 mois");
+        assert_eq!(Locale::Pt.format_days_ago(3), "há 3 dias");
+        assert_eq!(Locale::Ja.format_days_ago(730), "2年前");
+    }
+
+    #[test]
+    fn parses_yt_dlp_dates() {
+        assert_eq!(days_from_yyyymmdd("19700101"), Some(0));
+        assert_eq!(days_from_yyyymmdd("20091025"), Some(14_542));
+        assert_eq!(days_from_yyyymmdd("2009-10-25"), None);
+        assert_eq!(days_from_yyyymmdd("20091325"), None);
+    }
+
+    #[test]
+    fn negotiates_accept_language() {
+        assert_eq!(Locale::negotiate("de-DE,de;q=0.9,en;q=0.8"), Some(Locale::De));
+        assert_eq!(Locale::negotiate("zh-CN, fr;q=0.5, en;q=0.7"), Some(Locale::En));
+        assert_eq!(Locale::negotiate("pt-BR"), Some(Locale::Pt));
+        assert_eq!(Locale::negotiate("ja;q=0, es"), Some(Locale::Es));
+        assert_eq!(Locale::negotiate("zh-CN"), None);
+    }
+}
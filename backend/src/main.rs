@@ -1,38 +1,81 @@
 use axum::{
-    routing::{get, post},
+    middleware::from_fn_with_state,
+    routing::{delete, get, post},
     Router,
 };
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
-use tower_http::cors::{Any, CorsLayer};
-use tracing_subscriber;
+use std::net::SocketAddr;
 use std::process::Command;
 
+mod config;
+mod error;
 mod handlers;
+mod locale;
+mod middleware;
 mod services;
+mod state;
 mod types;
 
+use config::AppConfig;
 use handlers::*;
+use middleware::auth::{require_admin, require_download, require_inspect};
+use middleware::cors::cors_layer;
+use middleware::rate_limit::{limit_download, limit_metadata};
+use state::AppState;
 
 #[tokio::main]
 async fn main() {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
-    // Build our application with routes
-    let app = Router::new()
-        .route("/", get(health_check))
+    let config = AppConfig::load().expect("Failed to load configuration");
+    let cors = cors_layer(&config.cors).expect("Invalid CORS configuration");
+    let state = AppState::new(config).expect("Failed to load API keys");
+
+    // Routes are grouped by the API key scope they require. Route layers run
+    // outermost-last, so authentication happens before rate limiting and the
+    // limiter can key on the API key.
+    let inspect_routes = Router::new()
         .route("/api/video-info", post(get_video_info))
         .route("/api/quality-options", post(get_quality_options))
+        .route("/api/format-preview", post(preview_format))
+        .route("/api/transcript", post(get_transcript))
+        .route("/api/thumbnails", post(get_thumbnails))
+        .route("/api/thumbnail", post(get_thumbnail))
+        .route_layer(from_fn_with_state(state.clone(), limit_metadata))
+        .route_layer(from_fn_with_state(state.clone(), require_inspect));
+
+    let download_routes = Router::new()
         .route("/api/download", post(download_video))
-        .layer(
-            ServiceBuilder::new().layer(
-                CorsLayer::new()
-                    .allow_origin(Any)
-                    .allow_methods(Any)
-                    .allow_headers(Any),
-            ),
-        );
+        .route("/api/jobs", post(create_job))
+        .route("/api/jobs/:id/storyboard", post(create_job_storyboard))
+        .route_layer(from_fn_with_state(state.clone(), limit_download))
+        .route_layer(from_fn_with_state(state.clone(), require_download));
+
+    // Polling a job is cheap, so it draws on the metadata budget
+    let job_routes = Router::new()
+        .route("/api/jobs/:id", get(get_job).delete(delete_job))
+        .route("/api/jobs/:id/file", get(get_job_file))
+        .route("/api/jobs/:id/stop", post(stop_job))
+        .route_layer(from_fn_with_state(state.clone(), limit_metadata))
+        .route_layer(from_fn_with_state(state.clone(), require_download));
+
+    let admin_routes = Router::new()
+        .route("/api/admin/keys", get(list_api_keys).post(create_api_key))
+        .route("/api/admin/keys/reload", post(reload_api_keys))
+        .route("/api/admin/keys/:name", delete(revoke_api_key))
+        .route_layer(from_fn_with_state(state.clone(), require_admin));
+
+    // Build our application with routes
+    let app = Router::new()
+        .route("/", get(health_check))
+        .merge(inspect_routes)
+        .merge(download_routes)
+        .merge(job_routes)
+        .merge(admin_routes)
+        .with_state(state.clone())
+        .layer(ServiceBuilder::new().layer(cors));
 
     // Run it with hyper on the port Render provides
     let port = std::env::var("PORT").unwrap_or_else(|_| "3001".to_string());
@@ -44,7 +87,26 @@ async fn main() {
     println!("  GET  / - Health check");
     println!("  POST /api/video-info - Extract video metadata");
     println!("  POST /api/quality-options - Get available qualities");
+    println!("  POST /api/format-preview - Preview the formats a preset resolves to");
+    println!("  POST /api/transcript - Get a video's transcript as text, Markdown or JSON");
+    println!("  POST /api/thumbnails - List a video's thumbnail variants");
+    println!("  POST /api/thumbnail - Fetch a thumbnail as JPEG, PNG or WebP");
     println!("  POST /api/download - Download video/audio");
+    println!("  POST /api/jobs - Start a download in the background");
+    println!("  GET  /api/jobs/:id - Job status, progress and result");
+    println!("  GET  /api/jobs/:id/file - Collect a finished job's file");
+    println!("  POST /api/jobs/:id/stop - Finish a live recording early");
+    println!("  DELETE /api/jobs/:id - Cancel a job or discard its result");
+    println!("  GET  /api/admin/keys - List API keys");
+    println!("  POST /api/admin/keys - Create an API key");
+    println!("  POST /api/admin/keys/reload - Reload API keys from disk");
+    println!("  DELETE /api/admin/keys/:name - Revoke an API key");
+
+    if state.keys.enabled() {
+        println!("🔒 API key authentication enabled");
+    } else {
+        println!("⚠️ API key authentication disabled; set auth.enabled or VIDSAVER_ADMIN_KEY to require keys");
+    }
 
     // Check if yt-dlp is available
     let yt_dlp_path = std::env::var("YT_DLP_PATH").unwrap_or_else(|_| "yt-dlp".to_string());
@@ -73,7 +135,9 @@ async fn main() {
         }
     }
 
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .unwrap();
 }
 
 async fn health_check() -> &'static str {
@@ -0,0 +1,62 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::ApiError;
+use crate::handlers::AppError;
+use crate::services::key_store::Scope;
+use crate::state::AppState;
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+pub async fn require_inspect(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    authorize(state, request, next, Scope::Inspect).await
+}
+
+pub async fn require_download(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    authorize(state, request, next, Scope::Download).await
+}
+
+pub async fn require_admin(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    authorize(state, request, next, Scope::Admin).await
+}
+
+async fn authorize(state: AppState, mut request: Request, next: Next, required: Scope) -> Response {
+    let identity = extract_api_key(request.headers()).and_then(|key| state.keys.authenticate(&key));
+
+    // With auth disabled everything but the admin API stays open, but a valid
+    // key is still attached so later layers can see who is calling.
+    if !state.keys.enabled() && required != Scope::Admin {
+        if let Some(identity) = identity {
+            request.extensions_mut().insert(identity);
+        }
+        return next.run(request).await;
+    }
+
+    let Some(identity) = identity else {
+        return AppError::from(ApiError::Unauthorized).into_response();
+    };
+
+    if !identity.grants(required) {
+        return AppError::from(ApiError::Forbidden(identity.name)).into_response();
+    }
+
+    request.extensions_mut().insert(identity);
+    next.run(request).await
+}
+
+/// Accept either `X-API-Key: <key>` or `Authorization: Bearer <key>`.
+fn extract_api_key(headers: &HeaderMap) -> Option<String> {
+    if let Some(key) = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()) {
+        return Some(key.trim().to_string());
+    }
+
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|key| key.trim().to_string())
+}
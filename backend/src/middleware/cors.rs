@@ -0,0 +1,249 @@
+use anyhow::{anyhow, Result};
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+use crate::config::CorsConfig;
+
+/// Headers browser code always needs to read: the download filename and the
+/// rate-limit quota.
+const ALWAYS_EXPOSED: [&str; 4] = [
+    "content-disposition",
+    "retry-after",
+    "x-ratelimit-limit",
+    "x-ratelimit-remaining",
+];
+
+/// An allowed origin from the config: either an exact origin or a
+/// `scheme://*.domain[:port]` pattern matching any subdomain.
+#[derive(Debug, Clone)]
+enum OriginPattern {
+    Exact(String),
+    Subdomain { scheme: String, suffix: String },
+}
+
+impl OriginPattern {
+    fn parse(raw: &str) -> Result<Self> {
+        let origin = raw.trim().trim_end_matches('/').to_ascii_lowercase();
+        let (scheme, host) = origin
+            .split_once("://")
+            .ok_or_else(|| anyhow!("CORS origin must include a scheme: {}", raw))?;
+
+        match host.strip_prefix("*.") {
+            Some(rest) if !rest.is_empty() && !rest.contains('*') => Ok(OriginPattern::Subdomain {
+                scheme: scheme.to_string(),
+                suffix: format!(".{}", rest),
+            }),
+            _ if host.contains('*') => Err(anyhow!("Unsupported wildcard in CORS origin: {}", raw)),
+            _ => Ok(OriginPattern::Exact(origin)),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        match self {
+            OriginPattern::Exact(expected) => origin == *expected,
+            OriginPattern::Subdomain { scheme, suffix } => match origin.split_once("://") {
+                Some((s, host)) => s == scheme && host.len() > suffix.len() && host.ends_with(suffix.as_str()),
+                None => false,
+            },
+        }
+    }
+}
+
+pub fn cors_layer(config: &CorsConfig) -> Result<CorsLayer> {
+    let wildcard = |values: &[String]| values.iter().any(|v| v.trim() == "*");
+
+    let allow_origin = if wildcard(&config.allowed_origins) {
+        if config.allow_credentials {
+            return Err(anyhow!("CORS credentials cannot be combined with a wildcard origin"));
+        }
+        AllowOrigin::any()
+    } else {
+        let patterns = config
+            .allowed_origins
+            .iter()
+            .map(|origin| OriginPattern::parse(origin))
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            origin
+                .to_str()
+                .map(|origin| patterns.iter().any(|pattern| pattern.matches(origin)))
+                .unwrap_or(false)
+        })
+    };
+
+    // Credentialed requests may not use `*`, so mirror the request instead
+    let allow_methods = if wildcard(&config.allowed_methods) {
+        if config.allow_credentials {
+            AllowMethods::mirror_request()
+        } else {
+            AllowMethods::any()
+        }
+    } else {
+        let methods = config
+            .allowed_methods
+            .iter()
+            .map(|m| {
+                Method::from_bytes(m.trim().to_ascii_uppercase().as_bytes())
+                    .map_err(|_| anyhow!("Invalid CORS method: {}", m))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowMethods::list(methods)
+    };
+
+    let allow_headers = if wildcard(&config.allowed_headers) {
+        if config.allow_credentials {
+            AllowHeaders::mirror_request()
+        } else {
+            AllowHeaders::any()
+        }
+    } else {
+        AllowHeaders::list(parse_headers(&config.allowed_headers)?)
+    };
+
+    let mut expose_headers = parse_headers(&config.expose_headers)?;
+    for name in ALWAYS_EXPOSED {
+        let name = HeaderName::from_static(name);
+        if !expose_headers.contains(&name) {
+            expose_headers.push(name);
+        }
+    }
+
+    let mut layer = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(allow_methods)
+        .allow_headers(allow_headers)
+        .expose_headers(expose_headers)
+        .allow_credentials(config.allow_credentials);
+
+    if let Some(max_age) = config.max_age_secs {
+        layer = layer.max_age(Duration::from_secs(max_age));
+    }
+
+    Ok(layer)
+}
+
+fn parse_headers(names: &[String]) -> Result<Vec<HeaderName>> {
+    names
+        .iter()
+        .map(|name| {
+            HeaderName::from_bytes(name.trim().to_ascii_lowercase().as_bytes())
+                .map_err(|_| anyhow!("Invalid CORS header name: {}", name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::Service;
+
+    fn matches(pattern: &str, origin: &str) -> bool {
+        OriginPattern::parse(pattern).unwrap().matches(origin)
+    }
+
+    fn config(origins: &[&str], credentials: bool) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            allow_credentials: credentials,
+            ..CorsConfig::default()
+        }
+    }
+
+    /// Send a preflight for a credentialed `PATCH` with a custom header.
+    async fn preflight(config: &CorsConfig, origin: &str) -> axum::http::HeaderMap {
+        let mut app = Router::new().route("/", get(|| async {})).layer(cors_layer(config).unwrap());
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/")
+            .header("origin", origin)
+            .header("access-control-request-method", "PATCH")
+            .header("access-control-request-headers", "x-api-key")
+            .body(Body::empty())
+            .unwrap();
+        app.call(request).await.unwrap().headers().clone()
+    }
+
+    #[test]
+    fn exact_origins_match_only_themselves() {
+        assert!(matches("https://app.example.com", "https://app.example.com"));
+        assert!(matches("HTTPS://App.Example.com/", "https://app.example.com"));
+        assert!(!matches("https://app.example.com", "https://app.example.com:8443"));
+        assert!(!matches("https://app.example.com", "https://other.example.com"));
+        assert!(!matches("https://app.example.com", "http://app.example.com"));
+    }
+
+    #[test]
+    fn wildcards_match_subdomains_only() {
+        assert!(matches("https://*.example.com", "https://app.example.com"));
+        assert!(matches("https://*.example.com", "https://a.b.example.com"));
+        // Neither the apex nor a lookalike domain
+        assert!(!matches("https://*.example.com", "https://example.com"));
+        assert!(!matches("https://*.example.com", "https://evil-example.com"));
+        assert!(!matches("https://*.example.com", "https://example.com.evil.com"));
+        assert!(!matches("https://*.example.com", "https://.example.com"));
+        // The scheme and port have to agree
+        assert!(!matches("https://*.example.com", "http://app.example.com"));
+        assert!(!matches("https://*.example.com", "https://app.example.com:8443"));
+        assert!(matches("https://*.example.com:8443", "https://app.example.com:8443"));
+        assert!(!matches("https://*.example.com", "app.example.com"));
+    }
+
+    #[test]
+    fn malformed_patterns_are_rejected() {
+        for raw in ["app.example.com", "https://*", "https://*.", "https://a.*.example.com", "https://*.*.example.com"] {
+            assert!(OriginPattern::parse(raw).is_err(), "'{}' should be rejected", raw);
+        }
+    }
+
+    #[test]
+    fn any_origin_cannot_send_credentials() {
+        assert!(cors_layer(&config(&["*"], true)).is_err());
+        assert!(cors_layer(&config(&["https://app.example.com", " * "], true)).is_err());
+        assert!(cors_layer(&config(&["*"], false)).is_ok());
+        assert!(cors_layer(&config(&["app.example.com"], false)).is_err());
+    }
+
+    #[tokio::test]
+    async fn credentials_mirror_methods_and_headers() {
+        let headers = preflight(&config(&["https://*.example.com"], true), "https://app.example.com").await;
+        assert_eq!(headers["access-control-allow-origin"], "https://app.example.com");
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(headers["access-control-allow-methods"], "PATCH");
+        assert_eq!(headers["access-control-allow-headers"], "x-api-key");
+    }
+
+    #[tokio::test]
+    async fn without_credentials_anything_goes() {
+        let headers = preflight(&CorsConfig::default(), "https://anywhere.test").await;
+        assert_eq!(headers["access-control-allow-origin"], "*");
+        assert_eq!(headers["access-control-allow-methods"], "*");
+        assert_eq!(headers["access-control-allow-headers"], "*");
+        assert!(headers.get("access-control-allow-credentials").is_none());
+    }
+
+    #[tokio::test]
+    async fn unlisted_origins_are_not_allowed() {
+        let headers = preflight(&config(&["https://*.example.com"], true), "https://evil-example.com").await;
+        assert!(headers.get("access-control-allow-origin").is_none());
+    }
+
+    #[tokio::test]
+    async fn download_headers_are_always_exposed() {
+        let cors = CorsConfig {
+            expose_headers: vec!["X-Custom".to_string(), "Retry-After".to_string()],
+            ..CorsConfig::default()
+        };
+        let mut app = Router::new().route("/", get(|| async {})).layer(cors_layer(&cors).unwrap());
+        let request = Request::builder().uri("/").header("origin", "https://a.test").body(Body::empty()).unwrap();
+        let response = app.call(request).await.unwrap();
+        let exposed = response.headers()["access-control-expose-headers"].to_str().unwrap().to_string();
+        let exposed: Vec<&str> = exposed.split(',').map(str::trim).collect();
+        assert_eq!(
+            exposed,
+            ["x-custom", "retry-after", "content-disposition", "x-ratelimit-limit", "x-ratelimit-remaining"]
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod auth;
+pub mod cors;
+pub mod rate_limit;
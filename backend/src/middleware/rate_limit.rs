@@ -0,0 +1,78 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::SocketAddr;
+
+use crate::services::key_store::ApiKeyIdentity;
+use crate::services::rate_limiter::{Decision, RateLimiter};
+use crate::state::AppState;
+
+const LIMIT_HEADER: &str = "x-ratelimit-limit";
+const REMAINING_HEADER: &str = "x-ratelimit-remaining";
+
+pub async fn limit_metadata(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    throttle(&state, &state.limits.metadata, request, next).await
+}
+
+pub async fn limit_download(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    throttle(&state, &state.limits.download, request, next).await
+}
+
+async fn throttle(state: &AppState, limiter: &RateLimiter, request: Request, next: Next) -> Response {
+    if !state.limits.enabled {
+        return next.run(request).await;
+    }
+
+    let client = client_key(state, &request);
+    match limiter.check(&client) {
+        Decision::Allowed { limit, remaining } => {
+            let mut response = next.run(request).await;
+            set_quota_headers(response.headers_mut(), limit, remaining);
+            response
+        }
+        Decision::Limited { limit, retry_after } => {
+            // Round up so clients never retry a moment too early
+            let retry_secs = retry_after.as_secs().saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+            println!("Rate limit exceeded for {} (retry in {}s)", client, retry_secs);
+
+            let mut response = (
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("Rate limit exceeded, retry in {} seconds", retry_secs),
+            )
+                .into_response();
+            let headers = response.headers_mut();
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_secs));
+            set_quota_headers(headers, limit, 0);
+            response
+        }
+    }
+}
+
+/// Authenticated callers share a budget per API key; everyone else is
+/// limited per client address.
+fn client_key(state: &AppState, request: &Request) -> String {
+    if let Some(identity) = request.extensions().get::<ApiKeyIdentity>() {
+        return format!("key:{}", identity.name);
+    }
+
+    let forwarded_for = request
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok());
+
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(peer)) => {
+            let ip = state.limits.trusted_proxies.client_ip(peer.ip(), forwarded_for);
+            format!("ip:{}", ip)
+        }
+        None => "ip:unknown".to_string(),
+    }
+}
+
+fn set_quota_headers(headers: &mut HeaderMap, limit: u32, remaining: u32) {
+    headers.insert(LIMIT_HEADER, HeaderValue::from(limit));
+    headers.insert(REMAINING_HEADER, HeaderValue::from(remaining));
+}
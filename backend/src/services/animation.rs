@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::AnimationConfig;
+use crate::error::ApiError;
+
+/// Animated image formats a clip can be turned into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Webp,
+}
+
+impl AnimationFormat {
+    /// The format for a `gif` or `webp` download type.
+    pub fn from_type(download_type: &str) -> Option<Self> {
+        match download_type {
+            "gif" => Some(AnimationFormat::Gif),
+            "webp" => Some(AnimationFormat::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn ext(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Error diffusion applied when mapping GIF frames onto the palette.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    None,
+    /// Ordered dithering; a regular pattern that compresses well
+    Bayer,
+    FloydSteinberg,
+    #[default]
+    Sierra2_4a,
+}
+
+impl Dither {
+    fn filter_value(self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            Dither::Bayer => "bayer",
+            Dither::FloydSteinberg => "floyd_steinberg",
+            Dither::Sierra2_4a => "sierra2_4a",
+        }
+    }
+}
+
+/// Output options for `gif` and `webp` downloads. Left-out fields use the
+/// configured defaults.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct AnimationOptions {
+    /// Output width in pixels; height follows the aspect ratio
+    pub width: Option<u32>,
+    pub fps: Option<u32>,
+    /// GIF palette size, 2-256
+    #[serde(rename = "maxColors")]
+    pub max_colors: Option<u32>,
+    /// GIF dithering
+    pub dither: Option<Dither>,
+    /// Build the GIF palette from the pixels that change between frames,
+    /// which favors moving subjects over a static background
+    #[serde(rename = "diffPalette", default)]
+    pub diff_palette: bool,
+    /// WebP quality, 0-100
+    pub quality: Option<u32>,
+}
+
+/// A checked animation job.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animation {
+    pub format: AnimationFormat,
+    pub width: u32,
+    pub fps: u32,
+    pub max_colors: u32,
+    pub dither: Dither,
+    pub diff_palette: bool,
+    pub quality: u32,
+    /// Longest clip, in seconds
+    pub max_duration: f64,
+    /// Largest output, in bytes
+    pub max_filesize: u64,
+}
+
+impl Animation {
+    pub fn resolve(format: AnimationFormat, options: &AnimationOptions, config: &AnimationConfig) -> Result<Self, ApiError> {
+        let gif_only = options.max_colors.is_some() || options.dither.is_some() || options.diff_palette;
+        if format == AnimationFormat::Webp && gif_only {
+            return Err(ApiError::BadRequest("maxColors, dither and diffPalette only apply to gif".to_string()));
+        }
+        if format == AnimationFormat::Gif && options.quality.is_some() {
+            return Err(ApiError::BadRequest("quality only applies to webp".to_string()));
+        }
+
+        let animation = Self {
+            format,
+            width: options.width.unwrap_or(config.default_width),
+            fps: options.fps.unwrap_or(config.default_fps),
+            max_colors: options.max_colors.unwrap_or(256),
+            dither: options.dither.unwrap_or_default(),
+            diff_palette: options.diff_palette,
+            quality: options.quality.unwrap_or(75),
+            max_duration: config.max_duration_secs,
+            max_filesize: config.max_filesize,
+        };
+
+        if !(16..=config.max_width).contains(&animation.width) {
+            return Err(ApiError::BadRequest(format!("width {} is outside 16-{}", animation.width, config.max_width)));
+        }
+        if !(1..=config.max_fps).contains(&animation.fps) {
+            return Err(ApiError::BadRequest(format!("fps {} is outside 1-{}", animation.fps, config.max_fps)));
+        }
+        if !(2..=256).contains(&animation.max_colors) {
+            return Err(ApiError::BadRequest(format!("maxColors {} is outside 2-256", animation.max_colors)));
+        }
+        if animation.quality > 100 {
+            return Err(ApiError::BadRequest(format!("quality {} is outside 0-100", animation.quality)));
+        }
+        Ok(animation)
+    }
+
+    /// Frame rate and size; never scales up.
+    pub fn scale_filter(&self) -> String {
+        format!("fps={},scale='min(iw,{})':-2:flags=lanczos", self.fps, self.width)
+    }
+
+    /// First GIF pass: one palette for the whole clip.
+    pub fn palettegen_filter(&self) -> String {
+        let stats_mode = if self.diff_palette { "diff" } else { "full" };
+        format!(
+            "{},palettegen=max_colors={}:stats_mode={}",
+            self.scale_filter(),
+            self.max_colors,
+            stats_mode
+        )
+    }
+
+    /// Second GIF pass: map frames onto the palette, given as the second
+    /// input. Only changed rectangles are redrawn, which keeps files small.
+    pub fn paletteuse_graph(&self) -> String {
+        format!(
+            "{}[frames];[frames][1:v]paletteuse=dither={}:diff_mode=rectangle",
+            self.scale_filter(),
+            self.dither.filter_value()
+        )
+    }
+
+    /// Encoder options for WebP.
+    pub fn webp_args(&self) -> Vec<String> {
+        vec![
+            "-vf".to_string(),
+            self.scale_filter(),
+            "-c:v".to_string(),
+            "libwebp".to_string(),
+            "-lossless".to_string(),
+            "0".to_string(),
+            "-quality".to_string(),
+            self.quality.to_string(),
+        ]
+    }
+}
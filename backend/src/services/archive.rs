@@ -0,0 +1,19 @@
+use anyhow::Result;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Pack files into an in-memory ZIP. Entries are stored, not deflated: media
+/// is already compressed and this keeps archiving cheap.
+pub fn zip(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    for (name, data) in entries {
+        let options = options.large_file(data.len() as u64 >= u32::MAX as u64);
+        writer.start_file(name.as_str(), options)?;
+        writer.write_all(data)?;
+    }
+
+    Ok(writer.finish()?.into_inner())
+}
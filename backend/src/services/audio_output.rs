@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::services::ytdlp_info::InfoDict;
+
+/// Longest description written into a comment tag, in characters.
+const MAX_COMMENT_CHARS: usize = 2000;
+
+/// Audio formats downloads can be converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    Mp3,
+    Opus,
+    #[serde(alias = "m4a")]
+    Aac,
+    Flac,
+    Wav,
+    Vorbis,
+    Alac,
+}
+
+impl AudioCodec {
+    pub fn ext(self) -> &'static str {
+        match self {
+            AudioCodec::Mp3 => "mp3",
+            AudioCodec::Opus => "opus",
+            AudioCodec::Aac | AudioCodec::Alac => "m4a",
+            AudioCodec::Flac => "flac",
+            AudioCodec::Wav => "wav",
+            AudioCodec::Vorbis => "ogg",
+        }
+    }
+
+    fn encoder(self) -> &'static str {
+        match self {
+            AudioCodec::Mp3 => "libmp3lame",
+            AudioCodec::Opus => "libopus",
+            AudioCodec::Aac => "aac",
+            AudioCodec::Flac => "flac",
+            AudioCodec::Wav => "pcm_s16le",
+            AudioCodec::Vorbis => "libvorbis",
+            AudioCodec::Alac => "alac",
+        }
+    }
+
+    pub fn is_lossless(self) -> bool {
+        matches!(self, AudioCodec::Flac | AudioCodec::Wav | AudioCodec::Alac)
+    }
+
+    /// Whether a yt-dlp `acodec` such as `opus` or `mp4a.40.2` is already this
+    /// codec, so the stream can be copied.
+    fn matches(self, acodec: &str) -> bool {
+        let acodec = acodec.to_ascii_lowercase();
+        match self {
+            AudioCodec::Mp3 => acodec == "mp3",
+            AudioCodec::Opus => acodec == "opus",
+            AudioCodec::Aac => acodec.starts_with("mp4a") || acodec == "aac",
+            AudioCodec::Flac => acodec == "flac",
+            AudioCodec::Wav => acodec.starts_with("pcm"),
+            AudioCodec::Vorbis => acodec == "vorbis",
+            AudioCodec::Alac => acodec == "alac",
+        }
+    }
+
+    /// The codec a yt-dlp `acodec` names, if it is one we can encode.
+    pub fn from_acodec(acodec: &str) -> Option<Self> {
+        [
+            AudioCodec::Mp3,
+            AudioCodec::Opus,
+            AudioCodec::Aac,
+            AudioCodec::Flac,
+            AudioCodec::Wav,
+            AudioCodec::Vorbis,
+            AudioCodec::Alac,
+        ]
+        .into_iter()
+        .find(|codec| codec.matches(acodec))
+    }
+
+    /// Source format to download, preferring one that can be remuxed.
+    pub fn source_selector(self) -> &'static str {
+        match self {
+            AudioCodec::Opus => "bestaudio[acodec=opus]/bestaudio/best",
+            AudioCodec::Aac => "bestaudio[ext=m4a]/bestaudio/best",
+            AudioCodec::Vorbis => "bestaudio[acodec=vorbis]/bestaudio/best",
+            _ => "bestaudio/best",
+        }
+    }
+
+    /// Rates `sampleRate` may resample to. Opus only runs at divisors of
+    /// 48 kHz and MP3 tops out at 48 kHz.
+    fn sample_rates(self) -> &'static [u32] {
+        match self {
+            AudioCodec::Opus => &[8000, 12000, 16000, 24000, 48000],
+            AudioCodec::Mp3 => &[8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000],
+            _ => &[8000, 16000, 22050, 24000, 32000, 44100, 48000, 96000],
+        }
+    }
+
+    /// Bitrates in kbit/s standing in for VBR levels 0 (best) to 9 on codecs
+    /// without their own quality scale.
+    fn vbr_bitrate(self, vbr: u8) -> u32 {
+        let table: [u32; 10] = match self {
+            AudioCodec::Opus => [256, 224, 192, 160, 128, 112, 96, 80, 64, 48],
+            _ => [320, 256, 224, 192, 160, 128, 112, 96, 80, 64],
+        };
+        table[vbr.min(9) as usize]
+    }
+}
+
+/// Requested audio conversion. Bitrate and VBR quality are alternatives;
+/// VBR runs from 0 (best) to 9, like LAME.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioOutput {
+    pub codec: AudioCodec,
+    pub bitrate: Option<u32>,
+    pub vbr: Option<u8>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u8>,
+}
+
+impl AudioOutput {
+    pub fn new(
+        codec: AudioCodec,
+        bitrate: Option<u32>,
+        vbr: Option<u8>,
+        sample_rate: Option<u32>,
+        channels: Option<u8>,
+    ) -> Result<Self, ApiError> {
+        if bitrate.is_some() && vbr.is_some() {
+            return Err(ApiError::BadRequest("Use either audioBitrate or audioVbr, not both".to_string()));
+        }
+        if codec.is_lossless() && (bitrate.is_some() || vbr.is_some()) {
+            return Err(ApiError::BadRequest(format!(
+                "{} is lossless and takes no bitrate or VBR quality",
+                codec.ext()
+            )));
+        }
+        if let Some(bitrate) = bitrate.filter(|bitrate| !(32..=512).contains(bitrate)) {
+            return Err(ApiError::BadRequest(format!("audioBitrate {} is outside 32-512 kbit/s", bitrate)));
+        }
+        if let Some(vbr) = vbr.filter(|vbr| *vbr > 9) {
+            return Err(ApiError::BadRequest(format!("audioVbr {} is outside 0-9", vbr)));
+        }
+        if let Some(rate) = sample_rate.filter(|rate| !codec.sample_rates().contains(rate)) {
+            return Err(ApiError::BadRequest(format!(
+                "sampleRate {} is not supported by {}; use one of {}",
+                rate,
+                codec.ext(),
+                codec.sample_rates().iter().map(|rate| rate.to_string()).collect::<Vec<_>>().join(", ")
+            )));
+        }
+        if let Some(channels) = channels.filter(|channels| !(1..=2).contains(channels)) {
+            return Err(ApiError::BadRequest(format!(
+                "audioChannels {} is not supported; downmix to 1 or 2",
+                channels
+            )));
+        }
+
+        Ok(Self {
+            codec,
+            bitrate,
+            vbr,
+            sample_rate,
+            channels,
+        })
+    }
+
+    /// Nothing to change besides the codec, so a matching stream can be copied.
+    fn is_plain(&self) -> bool {
+        self.bitrate.is_none() && self.vbr.is_none() && self.sample_rate.is_none() && self.channels.is_none()
+    }
+
+    /// Whether converting a stream in `source_acodec` only needs a remux.
+    pub fn is_remux(&self, source_acodec: Option<&str>) -> bool {
+        self.is_plain() && source_acodec.is_some_and(|acodec| self.codec.matches(acodec))
+    }
+
+    /// ffmpeg output options for the audio stream.
+    pub fn ffmpeg_args(&self, source_acodec: Option<&str>) -> Vec<String> {
+        if self.is_remux(source_acodec) {
+            return vec!["-c:a".to_string(), "copy".to_string()];
+        }
+
+        let mut args = vec!["-c:a".to_string(), self.codec.encoder().to_string()];
+        match (self.codec, self.bitrate, self.vbr) {
+            (codec, _, _) if codec.is_lossless() => {}
+            (_, Some(bitrate), _) => args.extend(["-b:a".to_string(), format!("{}k", bitrate)]),
+            (AudioCodec::Mp3, None, vbr) => args.extend(["-q:a".to_string(), vbr.unwrap_or(0).to_string()]),
+            // Vorbis quality runs the other way, 10 being best
+            (AudioCodec::Vorbis, None, vbr) => {
+                args.extend(["-q:a".to_string(), (10 - vbr.unwrap_or(2).min(9)).to_string()])
+            }
+            (codec, None, vbr) => args.extend(["-b:a".to_string(), format!("{}k", codec.vbr_bitrate(vbr.unwrap_or(2)))]),
+        }
+        if let Some(rate) = self.sample_rate {
+            args.extend(["-ar".to_string(), rate.to_string()]);
+        }
+        if let Some(channels) = self.channels {
+            args.extend(["-ac".to_string(), channels.to_string()]);
+        }
+        args
+    }
+}
+
+/// Tags for an audio file made from a video: the uploader stands in for the
+/// artist and the playlist for the album.
+pub fn audio_tags(info: &InfoDict) -> Vec<(&'static str, String)> {
+    let comment = info
+        .description
+        .as_deref()
+        .map(|description| description.chars().take(MAX_COMMENT_CHARS).collect::<String>());
+    let tags = [
+        ("title", info.title.clone()),
+        ("artist", info.uploader.clone().or_else(|| info.channel.clone())),
+        ("album", info.playlist.clone()),
+        ("track", info.playlist_index.map(|index| index.to_string())),
+        // Just the year; ID3v2.3 has no full-date frame
+        ("date", info.upload_date.as_deref().and_then(|date| date.get(..4)).map(str::to_string)),
+        ("comment", comment),
+    ];
+
+    tags.into_iter()
+        .filter_map(|(key, value)| Some((key, value?.trim().to_string())))
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+}
+
+/// Containers ffmpeg can attach a cover picture to.
+pub fn supports_cover_art(ext: &str) -> bool {
+    matches!(ext, "mp3" | "m4a" | "flac")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(codec: AudioCodec) -> AudioOutput {
+        AudioOutput::new(codec, None, None, None, None).unwrap()
+    }
+
+    fn args(output: &AudioOutput, source_acodec: Option<&str>) -> String {
+        output.ffmpeg_args(source_acodec).join(" ")
+    }
+
+    #[test]
+    fn sample_rates_depend_on_the_codec() {
+        for rate in [44100, 22050, 32000, 96000] {
+            assert!(AudioOutput::new(AudioCodec::Opus, None, None, Some(rate), None).is_err(), "opus at {}", rate);
+        }
+        for rate in [48000, 24000, 16000, 12000, 8000] {
+            assert!(AudioOutput::new(AudioCodec::Opus, None, None, Some(rate), None).is_ok(), "opus at {}", rate);
+        }
+        assert!(AudioOutput::new(AudioCodec::Mp3, None, None, Some(96000), None).is_err());
+        assert!(AudioOutput::new(AudioCodec::Mp3, None, None, Some(44100), None).is_ok());
+        assert!(AudioOutput::new(AudioCodec::Flac, None, None, Some(96000), None).is_ok());
+        assert!(AudioOutput::new(AudioCodec::Flac, None, None, Some(12345), None).is_err());
+    }
+
+    #[test]
+    fn new_rejects_conflicting_or_out_of_range_options() {
+        assert!(AudioOutput::new(AudioCodec::Mp3, Some(192), Some(2), None, None).is_err());
+        assert!(AudioOutput::new(AudioCodec::Flac, Some(192), None, None, None).is_err());
+        assert!(AudioOutput::new(AudioCodec::Wav, None, Some(0), None, None).is_err());
+        assert!(AudioOutput::new(AudioCodec::Aac, Some(16), None, None, None).is_err());
+        assert!(AudioOutput::new(AudioCodec::Aac, Some(600), None, None, None).is_err());
+        assert!(AudioOutput::new(AudioCodec::Opus, None, Some(10), None, None).is_err());
+        assert!(AudioOutput::new(AudioCodec::Mp3, None, None, None, Some(6)).is_err());
+        assert!(AudioOutput::new(AudioCodec::Mp3, Some(32), None, None, Some(1)).is_ok());
+    }
+
+    #[test]
+    fn matching_source_is_remuxed() {
+        assert!(output(AudioCodec::Aac).is_remux(Some("mp4a.40.2")));
+        assert!(output(AudioCodec::Opus).is_remux(Some("opus")));
+        assert!(!output(AudioCodec::Opus).is_remux(Some("mp4a.40.2")));
+        assert!(!output(AudioCodec::Opus).is_remux(None));
+        // Any encoding option means re-encoding
+        let resampled = AudioOutput::new(AudioCodec::Opus, None, None, Some(24000), None).unwrap();
+        assert!(!resampled.is_remux(Some("opus")));
+        assert_eq!(args(&output(AudioCodec::Aac), Some("mp4a.40.2")), "-c:a copy");
+    }
+
+    #[test]
+    fn ffmpeg_args_per_codec() {
+        assert_eq!(args(&output(AudioCodec::Mp3), Some("opus")), "-c:a libmp3lame -q:a 0");
+        assert_eq!(args(&output(AudioCodec::Opus), None), "-c:a libopus -b:a 192k");
+        assert_eq!(args(&output(AudioCodec::Vorbis), None), "-c:a libvorbis -q:a 8");
+        assert_eq!(args(&output(AudioCodec::Flac), Some("opus")), "-c:a flac");
+        assert_eq!(args(&output(AudioCodec::Wav), None), "-c:a pcm_s16le");
+    }
+
+    #[test]
+    fn ffmpeg_args_carry_encoding_options() {
+        let bitrate = AudioOutput::new(AudioCodec::Aac, Some(128), None, Some(44100), Some(1)).unwrap();
+        assert_eq!(args(&bitrate, Some("mp4a.40.2")), "-c:a aac -b:a 128k -ar 44100 -ac 1");
+        let vbr = AudioOutput::new(AudioCodec::Mp3, None, Some(4), None, None).unwrap();
+        assert_eq!(args(&vbr, None), "-c:a libmp3lame -q:a 4");
+        let opus_vbr = AudioOutput::new(AudioCodec::Opus, None, Some(9), Some(48000), Some(2)).unwrap();
+        assert_eq!(args(&opus_vbr, None), "-c:a libopus -b:a 48k -ar 48000 -ac 2");
+        let vorbis_best = AudioOutput::new(AudioCodec::Vorbis, None, Some(0), None, None).unwrap();
+        assert_eq!(args(&vorbis_best, None), "-c:a libvorbis -q:a 10");
+    }
+}
@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::ApiError;
+
+/// Most sections a single download may cut out.
+const MAX_RANGES: usize = 10;
+
+/// A position in the video, given as seconds (`90.5`) or as
+/// `[[HH:]MM:]SS[.fff]` (`"1:30.5"`).
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "RawTimestamp", into = "f64")]
+pub struct Timestamp(f64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Seconds(f64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTimestamp(String);
+
+impl fmt::Display for InvalidTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid timestamp: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidTimestamp {}
+
+impl Timestamp {
+    pub fn parse(raw: &str) -> Result<Self, InvalidTimestamp> {
+        let parts: Vec<&str> = raw.trim().split(':').collect();
+        if parts.len() > 3 {
+            return Err(InvalidTimestamp(format!("'{}' has too many ':' separators", raw)));
+        }
+
+        let mut seconds = 0.0;
+        for (i, part) in parts.iter().enumerate() {
+            let is_last = i == parts.len() - 1;
+            // Only the seconds field may carry a fraction
+            let valid = !part.is_empty()
+                && part.chars().all(|c| c.is_ascii_digit() || (is_last && c == '.'))
+                && part.matches('.').count() <= 1;
+            let value: f64 = match part.parse() {
+                Ok(value) if valid => value,
+                _ => return Err(InvalidTimestamp(format!("'{}' is not [[HH:]MM:]SS[.fff]", raw))),
+            };
+            if i > 0 && value >= 60.0 {
+                return Err(InvalidTimestamp(format!("'{}' has a field of 60 or more", raw)));
+            }
+            seconds = seconds * 60.0 + value;
+        }
+
+        Self::from_seconds(seconds)
+    }
+
+    pub fn from_seconds(seconds: f64) -> Result<Self, InvalidTimestamp> {
+        if seconds.is_finite() && seconds >= 0.0 {
+            Ok(Self(seconds))
+        } else {
+            Err(InvalidTimestamp(format!("{} is not a non-negative number of seconds", seconds)))
+        }
+    }
+
+    pub fn seconds(self) -> f64 {
+        self.0
+    }
+}
+
+impl TryFrom<RawTimestamp> for Timestamp {
+    type Error = InvalidTimestamp;
+
+    fn try_from(value: RawTimestamp) -> Result<Self, Self::Error> {
+        match value {
+            RawTimestamp::Seconds(seconds) => Self::from_seconds(seconds),
+            RawTimestamp::Text(text) => Self::parse(&text),
+        }
+    }
+}
+
+impl From<Timestamp> for f64 {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.0
+    }
+}
+
+/// A section to keep. A missing `end` runs to the end of the video.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start: Timestamp,
+    pub end: Option<Timestamp>,
+}
+
+impl TimeRange {
+    /// The `--download-sections` value for this range.
+    pub fn section_arg(&self) -> String {
+        match self.end {
+            Some(end) => format!("*{:.3}-{:.3}", self.start.seconds(), end.seconds()),
+            None => format!("*{:.3}-inf", self.start.seconds()),
+        }
+    }
+}
+
+/// Length of the media once `ranges` are cut out of a video lasting
+/// `duration` seconds; all of it when there are no ranges.
+pub fn total_duration(ranges: &[TimeRange], duration: Option<f64>) -> Option<f64> {
+    if ranges.is_empty() {
+        return duration;
+    }
+    ranges
+        .iter()
+        .map(|range| Some(range.end.map(Timestamp::seconds).or(duration)? - range.start.seconds()))
+        .sum()
+}
+
+/// How sections are cut out of the source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CutMode {
+    /// Stream copy; cuts snap to the nearest keyframe, so a clip may start a
+    /// few seconds early
+    #[default]
+    Fast,
+    /// Re-encode around the cut points so the clip starts and ends exactly
+    Accurate,
+}
+
+/// Collect the ranges a download asked for, either a single `start`/`end` or
+/// a list, checked against each other and against the video's duration when
+/// it is known. Returned ranges are sorted by start time.
+pub fn requested_ranges(
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+    ranges: Option<&[TimeRange]>,
+    duration: Option<f64>,
+) -> Result<Vec<TimeRange>, ApiError> {
+    let mut ranges = match (start, end, ranges) {
+        (None, None, None) => return Ok(Vec::new()),
+        (_, _, Some(_)) if start.is_some() || end.is_some() => {
+            return Err(ApiError::BadRequest("Use either start/end or ranges, not both".to_string()));
+        }
+        (_, _, Some(ranges)) => ranges.to_vec(),
+        (start, end, None) => vec![TimeRange {
+            start: start.unwrap_or(Timestamp(0.0)),
+            end,
+        }],
+    };
+
+    if ranges.is_empty() {
+        return Err(ApiError::BadRequest("ranges needs at least one range".to_string()));
+    }
+    if ranges.len() > MAX_RANGES {
+        return Err(ApiError::BadRequest(format!("At most {} ranges can be cut from one video", MAX_RANGES)));
+    }
+
+    ranges.sort_by(|a, b| a.start.seconds().total_cmp(&b.start.seconds()));
+
+    for range in &ranges {
+        let start = range.start.seconds();
+        if let Some(end) = range.end {
+            if end.seconds() <= start {
+                return Err(ApiError::BadRequest(format!(
+                    "Range end {:.3}s must be after its start {:.3}s",
+                    end.seconds(),
+                    start
+                )));
+            }
+        }
+        if let Some(duration) = duration {
+            if start >= duration {
+                return Err(ApiError::BadRequest(format!(
+                    "Range start {:.3}s is past the end of the video ({:.3}s)",
+                    start, duration
+                )));
+            }
+            if range.end.is_some_and(|end| end.seconds() > duration) {
+                return Err(ApiError::BadRequest(format!(
+                    "Range end {:.3}s is past the end of the video ({:.3}s)",
+                    range.end.map(Timestamp::seconds).unwrap_or_default(),
+                    duration
+                )));
+            }
+        }
+    }
+
+    for pair in ranges.windows(2) {
+        let overlaps = match pair[0].end {
+            Some(end) => end.seconds() > pair[1].start.seconds(),
+            None => true,
+        };
+        if overlaps {
+            return Err(ApiError::BadRequest("Ranges must not overlap".to_string()));
+        }
+    }
+
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(raw: &str) -> f64 {
+        Timestamp::parse(raw).unwrap().seconds()
+    }
+
+    fn range(start: f64, end: Option<f64>) -> TimeRange {
+        TimeRange {
+            start: Timestamp(start),
+            end: end.map(Timestamp),
+        }
+    }
+
+    #[test]
+    fn timestamps_parse_in_every_form() {
+        assert_eq!(seconds("90.5"), 90.5);
+        assert_eq!(seconds("1:30.5"), 90.5);
+        assert_eq!(seconds("01:02:03"), 3723.0);
+        assert_eq!(seconds(" 0:05 "), 5.0);
+        // Only later fields are capped at 59
+        assert_eq!(seconds("120:00"), 7200.0);
+    }
+
+    #[test]
+    fn malformed_timestamps_are_rejected() {
+        for raw in ["", ":", "1:", "1:60", "1:2:3:4", "1.5:00", "1..2", "-5", "+5", "1e3", "1:3a", "inf", "NaN"] {
+            assert!(Timestamp::parse(raw).is_err(), "'{}' should be rejected", raw);
+        }
+    }
+
+    #[test]
+    fn timestamps_deserialize_from_numbers_and_strings() {
+        let parsed: Vec<Timestamp> = serde_json::from_str(r#"[12.5, "0:12.5"]"#).unwrap();
+        assert_eq!(parsed, [Timestamp(12.5), Timestamp(12.5)]);
+        assert!(serde_json::from_str::<Timestamp>("-1").is_err());
+    }
+
+    #[test]
+    fn no_range_asked_for() {
+        assert_eq!(requested_ranges(None, None, None, Some(100.0)).unwrap(), []);
+    }
+
+    #[test]
+    fn start_and_end_make_one_range() {
+        let ranges = requested_ranges(Some(Timestamp(10.0)), None, None, Some(100.0)).unwrap();
+        assert_eq!(ranges, [range(10.0, None)]);
+        let ranges = requested_ranges(None, Some(Timestamp(20.0)), None, None).unwrap();
+        assert_eq!(ranges, [range(0.0, Some(20.0))]);
+        assert_eq!(ranges[0].section_arg(), "*0.000-20.000");
+    }
+
+    #[test]
+    fn ranges_are_sorted() {
+        let asked = [range(50.0, Some(60.0)), range(10.0, Some(20.0)), range(70.0, None)];
+        let ranges = requested_ranges(None, None, Some(&asked), Some(100.0)).unwrap();
+        assert_eq!(ranges, [range(10.0, Some(20.0)), range(50.0, Some(60.0)), range(70.0, None)]);
+        assert_eq!(total_duration(&ranges, Some(100.0)), Some(50.0));
+    }
+
+    #[test]
+    fn bad_ranges_are_rejected() {
+        let rejected = |start: Option<f64>, end: Option<f64>, ranges: Option<&[TimeRange]>, duration: Option<f64>| {
+            requested_ranges(start.map(Timestamp), end.map(Timestamp), ranges, duration).is_err()
+        };
+        // Both forms at once, or an empty list
+        assert!(rejected(Some(1.0), None, Some(&[range(5.0, None)]), None));
+        assert!(rejected(None, None, Some(&[]), None));
+        // Ends before starts
+        assert!(rejected(Some(10.0), Some(10.0), None, None));
+        assert!(rejected(None, None, Some(&[range(10.0, Some(5.0))]), None));
+        // Past the end of the video, only when its length is known
+        assert!(rejected(Some(100.0), None, None, Some(100.0)));
+        assert!(rejected(Some(10.0), Some(101.0), None, Some(100.0)));
+        assert!(!rejected(Some(10.0), Some(101.0), None, None));
+        // Overlapping, including an open-ended range before another
+        assert!(rejected(None, None, Some(&[range(0.0, Some(20.0)), range(10.0, Some(30.0))]), None));
+        assert!(rejected(None, None, Some(&[range(0.0, None), range(10.0, Some(30.0))]), None));
+        assert!(!rejected(None, None, Some(&[range(0.0, Some(10.0)), range(10.0, Some(30.0))]), None));
+        let too_many: Vec<TimeRange> = (0..11).map(|i| range(i as f64 * 10.0, Some(i as f64 * 10.0 + 5.0))).collect();
+        assert!(rejected(None, None, Some(&too_many), None));
+    }
+}
@@ -0,0 +1,633 @@
+use std::fmt;
+
+/// How much of a Matroska/WebM file to scan for track codec IDs. Tracks sit
+/// right after the EBML header, so this comfortably covers them.
+const EBML_SCAN_BYTES: usize = 64 * 1024;
+
+/// A MIME type detected from file contents, with RFC 6381 codec strings
+/// when every track's codec could be identified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentType {
+    pub mime: &'static str,
+    pub codecs: Vec<String>,
+}
+
+impl ContentType {
+    fn plain(mime: &'static str) -> Self {
+        Self {
+            mime,
+            codecs: Vec::new(),
+        }
+    }
+
+    fn with_codecs(mime: &'static str, codecs: Option<Vec<String>>) -> Self {
+        Self {
+            mime,
+            codecs: codecs.unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.codecs.is_empty() {
+            f.write_str(self.mime)
+        } else {
+            write!(f, "{}; codecs=\"{}\"", self.mime, self.codecs.join(", "))
+        }
+    }
+}
+
+/// Work out the content type of a download from its bytes, falling back to
+/// the file extension for formats without a reliable signature.
+pub fn detect(data: &[u8], filename: &str) -> ContentType {
+    sniff(data).unwrap_or_else(|| ContentType::plain(from_extension(filename)))
+}
+
+fn sniff(data: &[u8]) -> Option<ContentType> {
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return Some(sniff_iso_bmff(data));
+    }
+    if data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return Some(sniff_matroska(data));
+    }
+    if data.starts_with(b"OggS") {
+        return Some(sniff_ogg(data));
+    }
+    if data.starts_with(b"fLaC") {
+        return Some(ContentType::plain("audio/flac"));
+    }
+    if data.len() >= 12 && data.starts_with(b"RIFF") {
+        return match &data[8..12] {
+            b"WAVE" => Some(ContentType::plain("audio/wav")),
+            b"WEBP" => Some(ContentType::plain("image/webp")),
+            _ => None,
+        };
+    }
+    if data.starts_with(b"ID3") {
+        return Some(ContentType::plain("audio/mpeg"));
+    }
+    if data.len() >= 2 && data[0] == 0xFF && data[1] & 0xF0 == 0xF0 {
+        // ADTS (AAC) uses layer bits 00; anything else is an MPEG audio frame
+        return Some(if data[1] & 0x06 == 0 {
+            ContentType::plain("audio/aac")
+        } else {
+            ContentType::plain("audio/mpeg")
+        });
+    }
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(ContentType::plain("image/jpeg"));
+    }
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(ContentType::plain("image/png"));
+    }
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some(ContentType::plain("image/gif"));
+    }
+    if data.starts_with(b"PK\x03\x04") {
+        return Some(ContentType::plain("application/zip"));
+    }
+
+    sniff_text(data)
+}
+
+/// MP4, M4A, 3GP and QuickTime all share the ISO base media file format.
+/// The brand alone doesn't say whether there is a video track (DASH audio
+/// comes out as `dash`/`isom`), so walk the track handlers as well.
+fn sniff_iso_bmff(data: &[u8]) -> ContentType {
+    let brand = &data[8..12];
+    let tracks = mp4_tracks(data);
+    let has_video = tracks.iter().any(|t| t.kind == TrackKind::Video);
+    let codecs = tracks.iter().map(|t| t.codec.clone()).collect::<Option<Vec<_>>>();
+
+    let mime = match brand {
+        b"qt  " => "video/quicktime",
+        b if b.starts_with(b"3g2") => {
+            if has_video || tracks.is_empty() {
+                "video/3gpp2"
+            } else {
+                "audio/3gpp2"
+            }
+        }
+        b if b.starts_with(b"3gp") => {
+            if has_video || tracks.is_empty() {
+                "video/3gpp"
+            } else {
+                "audio/3gpp"
+            }
+        }
+        b"M4A " | b"M4B " => "audio/mp4",
+        _ if !tracks.is_empty() && !has_video => "audio/mp4",
+        _ => "video/mp4",
+    };
+
+    ContentType::with_codecs(mime, codecs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrackKind {
+    Video,
+    Audio,
+    Other,
+}
+
+struct Mp4Track {
+    kind: TrackKind,
+    codec: Option<String>,
+}
+
+fn mp4_tracks(data: &[u8]) -> Vec<Mp4Track> {
+    let Some(moov) = find_box(data, b"moov") else {
+        return Vec::new();
+    };
+
+    Mp4Boxes::new(moov)
+        .filter(|(kind, _)| kind == b"trak")
+        .filter_map(|(_, trak)| {
+            let mdia = find_box(trak, b"mdia")?;
+            let handler = find_box(mdia, b"hdlr").filter(|h| h.len() >= 12).map(|h| &h[8..12]);
+            let kind = match handler {
+                Some(b"vide") => TrackKind::Video,
+                Some(b"soun") => TrackKind::Audio,
+                _ => TrackKind::Other,
+            };
+            if kind == TrackKind::Other {
+                return None;
+            }
+
+            let stsd = find_box(mdia, b"minf")
+                .and_then(|minf| find_box(minf, b"stbl"))
+                .and_then(|stbl| find_box(stbl, b"stsd"));
+            // Full box header (4) + entry count (4), then the sample entries
+            let codec = stsd
+                .filter(|stsd| stsd.len() > 8)
+                .and_then(|stsd| Mp4Boxes::new(&stsd[8..]).next())
+                .and_then(|(fourcc, entry)| mp4_codec_string(kind, &fourcc, entry));
+
+            Some(Mp4Track { kind, codec })
+        })
+        .collect()
+}
+
+fn mp4_codec_string(kind: TrackKind, fourcc: &[u8; 4], entry: &[u8]) -> Option<String> {
+    // Sample entries carry fixed fields before their child boxes
+    let children = match kind {
+        TrackKind::Video => entry.get(78..)?,
+        _ => entry.get(28..)?,
+    };
+
+    match fourcc {
+        b"avc1" | b"avc3" => {
+            let avcc = find_box(children, b"avcC")?;
+            let name = std::str::from_utf8(fourcc).ok()?;
+            Some(format!("{}.{:02x}{:02x}{:02x}", name, avcc.get(1)?, avcc.get(2)?, avcc.get(3)?))
+        }
+        b"av01" => {
+            let av1c = find_box(children, b"av1C")?;
+            let (b1, b2) = (*av1c.get(1)?, *av1c.get(2)?);
+            let profile = b1 >> 5;
+            let level = b1 & 0x1F;
+            let tier = if b2 & 0x80 != 0 { 'H' } else { 'M' };
+            let bit_depth = match (b2 & 0x40 != 0, b2 & 0x20 != 0) {
+                (true, true) => 12,
+                (true, false) => 10,
+                _ => 8,
+            };
+            Some(format!("av01.{}.{:02}{}.{:02}", profile, level, tier, bit_depth))
+        }
+        b"vp09" => {
+            let vpcc = find_box(children, b"vpcC")?;
+            // Full box: version/flags, then profile, level, bit depth (high nibble)
+            let (profile, level, depth) = (*vpcc.get(4)?, *vpcc.get(5)?, *vpcc.get(6)? >> 4);
+            Some(format!("vp09.{:02}.{:02}.{:02}", profile, level, depth))
+        }
+        b"mp4a" => find_box(children, b"esds").and_then(esds_codec_string),
+        b"Opus" => Some("opus".to_string()),
+        b"fLaC" => Some("flac".to_string()),
+        b"ac-3" => Some("ac-3".to_string()),
+        b"ec-3" => Some("ec-3".to_string()),
+        _ => None,
+    }
+}
+
+/// Decode `mp4a.<objectTypeIndication>[.<audioObjectType>]` from an `esds`
+/// box (ISO/IEC 14496-1 descriptors).
+fn esds_codec_string(esds: &[u8]) -> Option<String> {
+    let mut pos = 4; // version + flags
+    let (tag, len, header) = read_descriptor(esds.get(pos..)?)?;
+    if tag != 0x03 {
+        return None;
+    }
+    let es = esds.get(pos + header..pos + header + len)?;
+
+    // ES_ID (2) then flags selecting optional fields
+    let flags = *es.get(2)?;
+    pos = 3;
+    if flags & 0x80 != 0 {
+        pos += 2;
+    }
+    if flags & 0x40 != 0 {
+        pos += 1 + *es.get(pos)? as usize;
+    }
+    if flags & 0x20 != 0 {
+        pos += 2;
+    }
+
+    let (tag, len, header) = read_descriptor(es.get(pos..)?)?;
+    if tag != 0x04 {
+        return None;
+    }
+    let config = es.get(pos + header..pos + header + len)?;
+    let object_type = *config.first()?;
+    if object_type != 0x40 {
+        return Some(format!("mp4a.{:02x}", object_type));
+    }
+
+    // DecoderSpecificInfo follows the 13 fixed bytes of DecoderConfigDescriptor
+    let (tag, _, header) = read_descriptor(config.get(13..)?)?;
+    let audio_object_type = (*config.get(13 + header)?) >> 3;
+    if tag != 0x05 || audio_object_type == 0 || audio_object_type == 31 {
+        return Some("mp4a.40".to_string());
+    }
+    Some(format!("mp4a.40.{}", audio_object_type))
+}
+
+/// Returns `(tag, payload length, header length)` for an MPEG-4 descriptor.
+fn read_descriptor(data: &[u8]) -> Option<(u8, usize, usize)> {
+    let tag = *data.first()?;
+    let mut len = 0usize;
+    for i in 1..=4 {
+        let byte = *data.get(i)?;
+        len = (len << 7) | (byte & 0x7F) as usize;
+        if byte & 0x80 == 0 {
+            return Some((tag, len, i + 1));
+        }
+    }
+    None
+}
+
+fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    Mp4Boxes::new(data).find(|(k, _)| k == kind).map(|(_, payload)| payload)
+}
+
+/// Iterates over sibling boxes, yielding each box's type and payload.
+struct Mp4Boxes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Mp4Boxes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Mp4Boxes<'a> {
+    type Item = ([u8; 4], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 8 {
+            return None;
+        }
+
+        let size = u32::from_be_bytes(self.data[0..4].try_into().ok()?) as u64;
+        let kind: [u8; 4] = self.data[4..8].try_into().ok()?;
+        let (header, size) = match size {
+            0 => (8, self.data.len() as u64),
+            1 => (16, u64::from_be_bytes(self.data.get(8..16)?.try_into().ok()?)),
+            size => (8, size),
+        };
+
+        // A truncated final box (e.g. a partially read mdat) still yields
+        // whatever payload is present
+        let end = usize::try_from(size).unwrap_or(usize::MAX).min(self.data.len());
+        if end < header {
+            self.data = &[];
+            return None;
+        }
+
+        let payload = &self.data[header..end];
+        self.data = &self.data[end..];
+        Some((kind, payload))
+    }
+}
+
+fn sniff_matroska(data: &[u8]) -> ContentType {
+    let head = &data[..data.len().min(EBML_SCAN_BYTES)];
+    let is_webm = contains(&head[..head.len().min(64)], b"webm");
+
+    let mut has_video = false;
+    let mut codecs = Some(Vec::new());
+    for (codec_id, is_video) in matroska_codec_ids(head) {
+        has_video |= is_video;
+        let codec = match codec_id {
+            "V_VP9" => Some("vp9"),
+            "V_VP8" => Some("vp8"),
+            "A_OPUS" => Some("opus"),
+            "A_VORBIS" => Some("vorbis"),
+            "A_FLAC" => Some("flac"),
+            _ => None,
+        };
+        codecs = match (codecs, codec) {
+            (Some(mut list), Some(codec)) => {
+                list.push(codec.to_string());
+                Some(list)
+            }
+            _ => None,
+        };
+    }
+
+    let mime = match (is_webm, has_video) {
+        (true, true) => "video/webm",
+        (true, false) => "audio/webm",
+        (false, true) => "video/x-matroska",
+        (false, false) => "audio/x-matroska",
+    };
+
+    ContentType::with_codecs(mime, codecs.filter(|c| !c.is_empty()))
+}
+
+/// Find `CodecID` elements (ID 0x86, one-byte size) holding `V_`/`A_` codec
+/// names. Good enough for the track headers yt-dlp and ffmpeg write.
+fn matroska_codec_ids(data: &[u8]) -> Vec<(&str, bool)> {
+    let mut ids = Vec::new();
+    let mut i = 0;
+    while i + 2 < data.len() {
+        if data[i] == 0x86 && data[i + 1] & 0x80 != 0 {
+            let len = (data[i + 1] & 0x7F) as usize;
+            if let Some(raw) = data.get(i + 2..i + 2 + len) {
+                if let Ok(id) = std::str::from_utf8(raw) {
+                    if id.starts_with("V_") || id.starts_with("A_") {
+                        ids.push((id, id.starts_with("V_")));
+                        i += 2 + len;
+                        continue;
+                    }
+                }
+            }
+        }
+        i += 1;
+    }
+    ids
+}
+
+fn sniff_ogg(data: &[u8]) -> ContentType {
+    // The first page carries the codec's identification header at offset 28
+    // for a single-segment page, which is what every muxer writes
+    let head = &data[..data.len().min(128)];
+    let codec = if contains(head, b"OpusHead") {
+        Some("opus")
+    } else if contains(head, b"\x01vorbis") {
+        Some("vorbis")
+    } else if contains(head, b"\x7fFLAC") {
+        Some("flac")
+    } else {
+        None
+    };
+
+    if contains(head, b"theora") {
+        return ContentType::plain("video/ogg");
+    }
+    ContentType::with_codecs("audio/ogg", codec.map(|c| vec![c.to_string()]))
+}
+
+fn sniff_text(data: &[u8]) -> Option<ContentType> {
+    let head = &data[..data.len().min(512)];
+    let text = std::str::from_utf8(head).ok().or_else(|| {
+        // The cut may land inside a multi-byte character
+        std::str::from_utf8(&head[..head.len().saturating_sub(3)]).ok()
+    })?;
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+
+    if text.starts_with("WEBVTT") {
+        return Some(ContentType::plain("text/vtt"));
+    }
+    if text.starts_with("[Script Info]") {
+        return Some(ContentType::plain("text/x-ssa"));
+    }
+    if text.starts_with("<?xml") || text.starts_with("<tt") {
+        if text.contains("<tt") {
+            return Some(ContentType::plain("application/ttml+xml"));
+        }
+        return Some(ContentType::plain("application/xml"));
+    }
+    if text.starts_with('{') || text.starts_with('[') {
+        return Some(ContentType::plain("application/json"));
+    }
+
+    // SRT: a cue number followed by a `-->` timing line
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or("").trim();
+    let second = lines.next().unwrap_or("");
+    if !first.is_empty() && first.chars().all(|c| c.is_ascii_digit()) && second.contains("-->") {
+        return Some(ContentType::plain("application/x-subrip"));
+    }
+
+    None
+}
+
+fn from_extension(filename: &str) -> &'static str {
+    let ext = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "mka" => "audio/x-matroska",
+        "mov" => "video/quicktime",
+        "3gp" => "video/3gpp",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "aac" => "audio/aac",
+        "opus" | "ogg" | "oga" => "audio/ogg",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "vtt" => "text/vtt",
+        "srt" => "application/x-subrip",
+        "ass" | "ssa" => "text/x-ssa",
+        "ttml" => "application/ttml+xml",
+        "json" | "json3" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend(kind);
+        data.extend(payload);
+        data
+    }
+
+    fn track(handler: &[u8; 4], sample_entry: Vec<u8>) -> Vec<u8> {
+        let hdlr = mp4_box(b"hdlr", &[&[0; 8][..], handler, &[0; 12]].concat());
+        let stsd = mp4_box(b"stsd", &[&[0, 0, 0, 0, 0, 0, 0, 1][..], &sample_entry].concat());
+        let minf = mp4_box(b"minf", &mp4_box(b"stbl", &stsd));
+        mp4_box(b"trak", &mp4_box(b"mdia", &[hdlr, minf].concat()))
+    }
+
+    fn avc1_track() -> Vec<u8> {
+        let avcc = mp4_box(b"avcC", &[1, 0x64, 0x00, 0x28, 0xFF]);
+        track(b"vide", mp4_box(b"avc1", &[&[0; 78][..], &avcc].concat()))
+    }
+
+    fn aac_track() -> Vec<u8> {
+        // ES descriptor > decoder config (MPEG-4 audio) > AAC-LC specific info
+        let decoder_specific = [0x05, 2, 0x12, 0x10];
+        let decoder_config = [&[0x04, 17, 0x40, 0x15][..], &[0; 11], &decoder_specific].concat();
+        let es = [&[0x03, (3 + decoder_config.len()) as u8, 0, 1, 0][..], &decoder_config].concat();
+        let esds = mp4_box(b"esds", &[&[0; 4][..], &es].concat());
+        track(b"soun", mp4_box(b"mp4a", &[&[0; 28][..], &esds].concat()))
+    }
+
+    fn mp4(brand: &[u8; 4], tracks: &[Vec<u8>]) -> Vec<u8> {
+        let ftyp = mp4_box(b"ftyp", &[&brand[..], &[0, 0, 2, 0], b"isomiso2"].concat());
+        let moov = mp4_box(b"moov", &tracks.concat());
+        [ftyp, moov, mp4_box(b"mdat", &[0; 32])].concat()
+    }
+
+    fn webm() -> Vec<u8> {
+        let mut data = vec![0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x82, 0x84];
+        data.extend(b"webm");
+        data.extend([0x18, 0x53, 0x80, 0x67, 0x01]);
+        data.extend([0x86, 0x85]);
+        data.extend(b"V_VP9");
+        data.extend([0x86, 0x86]);
+        data.extend(b"A_OPUS");
+        data
+    }
+
+    /// Everything `detect` could be handed: each prefix, and every box size
+    /// field overwritten with sizes that are too small, huge or "extended".
+    fn assert_never_panics(data: &[u8]) {
+        for end in 0..=data.len() {
+            detect(&data[..end], "x.bin");
+        }
+        for offset in 0..data.len().saturating_sub(4) {
+            for size in [0u32, 1, 2, 7, 8, 9, 16, 0x7FFF_FFFF, u32::MAX] {
+                let mut corrupt = data.to_vec();
+                corrupt[offset..offset + 4].copy_from_slice(&size.to_be_bytes());
+                detect(&corrupt, "x.bin");
+            }
+            let mut corrupt = data.to_vec();
+            corrupt[offset] ^= 0xFF;
+            detect(&corrupt, "x.bin");
+        }
+    }
+
+    #[test]
+    fn mp4_codecs_are_read_from_sample_entries() {
+        let data = mp4(b"isom", &[avc1_track(), aac_track()]);
+        assert_eq!(detect(&data, "x.bin").to_string(), "video/mp4; codecs=\"avc1.640028, mp4a.40.2\"");
+    }
+
+    #[test]
+    fn mp4_without_video_is_audio() {
+        assert_eq!(detect(&mp4(b"dash", &[aac_track()]), "x.mp4").to_string(), "audio/mp4; codecs=\"mp4a.40.2\"");
+        assert_eq!(detect(&mp4(b"M4A ", &[]), "x.mp4").mime, "audio/mp4");
+        assert_eq!(detect(&mp4(b"3gp5", &[aac_track()]), "x.3gp").mime, "audio/3gpp");
+        assert_eq!(detect(&mp4(b"qt  ", &[]), "x.mov").mime, "video/quicktime");
+    }
+
+    #[test]
+    fn mp4_with_an_unknown_codec_leaves_codecs_out() {
+        let unknown = track(b"vide", mp4_box(b"xyz1", &[0; 90]));
+        assert_eq!(detect(&mp4(b"isom", &[unknown, aac_track()]), "x").to_string(), "video/mp4");
+    }
+
+    #[test]
+    fn broken_mp4_never_panics() {
+        assert_never_panics(&mp4(b"isom", &[avc1_track(), aac_track()]));
+    }
+
+    #[test]
+    fn mp4_box_sizes_out_of_range_stop_the_walk() {
+        // A 64-bit size smaller than its own header, and one far past the end
+        let mut tiny = vec![0, 0, 0, 1];
+        tiny.extend(b"moov");
+        tiny.extend(4u64.to_be_bytes());
+        assert_eq!(Mp4Boxes::new(&tiny).count(), 0);
+        let mut huge = vec![0, 0, 0, 1];
+        huge.extend(b"moov");
+        huge.extend(u64::MAX.to_be_bytes());
+        huge.extend([1, 2, 3]);
+        assert_eq!(Mp4Boxes::new(&huge).next(), Some((*b"moov", &[1u8, 2, 3][..])));
+        // Shorter than a header, or missing half of a 64-bit size
+        assert_eq!(Mp4Boxes::new(&[0, 0, 0, 9, b'f']).count(), 0);
+        assert_eq!(Mp4Boxes::new(&[0, 0, 0, 1, b'm', b'o', b'o', b'v', 0, 0]).count(), 0);
+    }
+
+    #[test]
+    fn truncated_descriptors_are_rejected() {
+        assert_eq!(read_descriptor(&[]), None);
+        assert_eq!(read_descriptor(&[0x03]), None);
+        // A length that keeps claiming more bytes
+        assert_eq!(read_descriptor(&[0x03, 0x80, 0x80, 0x80, 0x80, 0x01]), None);
+        assert_eq!(read_descriptor(&[0x03, 0x81, 0x00]), Some((0x03, 128, 3)));
+        assert_eq!(esds_codec_string(&[0, 0, 0, 0, 0x03, 50, 0, 1]), None);
+    }
+
+    #[test]
+    fn webm_codecs_are_read_from_codec_ids() {
+        assert_eq!(detect(&webm(), "x.bin").to_string(), "video/webm; codecs=\"vp9, opus\"");
+        assert_never_panics(&webm());
+        // A CodecID size running past the end
+        let mut cut = webm();
+        cut.extend([0x86, 0xFF, b'V', b'_']);
+        assert_eq!(detect(&cut, "x").mime, "video/webm");
+    }
+
+    #[test]
+    fn ogg_and_other_signatures() {
+        let mut ogg = b"OggS".to_vec();
+        ogg.extend([0; 24]);
+        ogg.extend(b"OpusHead");
+        assert_eq!(detect(&ogg, "x").to_string(), "audio/ogg; codecs=\"opus\"");
+        assert_eq!(detect(b"fLaC\0\0\0\x22", "x").mime, "audio/flac");
+        assert_eq!(detect(b"RIFF\0\0\0\0WAVEfmt ", "x").mime, "audio/wav");
+        assert_eq!(detect(b"RIFF\0\0\0\0WEBPVP8 ", "x").mime, "image/webp");
+        assert_eq!(detect(b"ID3\x04\0", "x").mime, "audio/mpeg");
+        assert_eq!(detect(&[0xFF, 0xF1, 0x50], "x").mime, "audio/aac");
+        assert_eq!(detect(&[0xFF, 0xFB, 0x90], "x").mime, "audio/mpeg");
+        assert_eq!(detect(b"PK\x03\x04", "x").mime, "application/zip");
+        // Too short for its signature, so the extension decides
+        assert_eq!(detect(b"RIFF", "x.wav").mime, "audio/wav");
+        assert_eq!(detect(b"\0\0\0\x08ftyp", "x.m4a").mime, "audio/mp4");
+    }
+
+    #[test]
+    fn text_formats_are_sniffed() {
+        assert_eq!(detect("\u{feff}WEBVTT\n\n".as_bytes(), "x").mime, "text/vtt");
+        assert_eq!(detect(b"1\n00:00:01,000 --> 00:00:02,000\nHi", "x").mime, "application/x-subrip");
+        assert_eq!(detect(b"<?xml version=\"1.0\"?><tt xmlns=\"\">", "x").mime, "application/ttml+xml");
+        assert_eq!(detect(b"[Script Info]\n", "x").mime, "text/x-ssa");
+        assert_eq!(detect(b"{\"events\": []}", "x.json3").mime, "application/json");
+        // A multi-byte character cut at the scan limit still reads as text
+        let mut long = b"WEBVTT\n".to_vec();
+        long.extend("é".repeat(300).as_bytes());
+        assert_eq!(detect(&long[..512], "x").mime, "text/vtt");
+        assert_eq!(detect(&[0xC3, 0x28, 0xA0, 0xA1], "x.srt").mime, "application/x-subrip");
+    }
+
+    #[test]
+    fn unknown_data_falls_back_to_the_extension() {
+        assert_eq!(detect(b"", "Song.MP3").mime, "audio/mpeg");
+        assert_eq!(detect(b"plain words", "notes.txt").mime, "text/plain; charset=utf-8");
+        assert_eq!(detect(b"plain words", "no-extension").mime, "application/octet-stream");
+    }
+}
@@ -0,0 +1,454 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use crate::services::animation::{Animation, AnimationFormat};
+use crate::services::clip::Timestamp;
+use crate::services::jobs::{self, JobHandle, JobProgress};
+
+pub fn check_ffmpeg_available() -> bool {
+    Command::new("ffmpeg")
+        .arg("-version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Join files that share codecs end to end without re-encoding.
+pub async fn concat(inputs: &[impl AsRef<Path>], output: &Path) -> Result<()> {
+    let list_path = output.with_extension("concat.txt");
+    let list: String = inputs
+        .iter()
+        .map(|input| {
+            // The concat demuxer's list syntax quotes with `'` and escapes it as `'\''`
+            let path = input.as_ref().to_string_lossy().replace('\'', "'\\''");
+            format!("file '{}'\n", path)
+        })
+        .collect();
+    fs::write(&list_path, list).await?;
+
+    let mut args = vec!["-hide_banner", "-loglevel", "error", "-y", "-f", "concat", "-safe", "0", "-i"];
+    let list_arg = list_path.to_string_lossy().to_string();
+    let output_arg = output.to_string_lossy().to_string();
+    args.push(&list_arg);
+    args.extend(["-c", "copy"]);
+    args.push(&output_arg);
+
+    let result = run(&args).await;
+    let _ = fs::remove_file(&list_path).await;
+    result.map(|_| ())
+}
+
+/// Run an analysis filter such as `loudnorm` over the first audio stream of
+/// `input` without writing anything, returning ffmpeg's log.
+pub async fn analyze_audio(input: &Path, filter: &str) -> Result<String> {
+    let input_arg = input.to_string_lossy().to_string();
+    run(&[
+        "-hide_banner", "-nostats", "-loglevel", "info", "-i", &input_arg, "-map", "0:a:0", "-af", filter, "-f", "null", "-",
+    ])
+    .await
+}
+
+/// Length of `input` in seconds, from the header ffmpeg logs on opening it;
+/// `None` when the container does not say.
+pub async fn probe_duration(input: &Path) -> Result<Option<f64>> {
+    Ok(parse_duration(&probe(input).await?))
+}
+
+/// Stream specifiers (`0:2`) of the text subtitles in `input`, the only kind
+/// that can be converted to another text format such as `mov_text`.
+pub async fn text_subtitle_streams(input: &Path) -> Result<Vec<String>> {
+    Ok(parse_text_subtitle_streams(&probe(input).await?))
+}
+
+/// ffmpeg's log from opening `input`, which describes its streams.
+async fn probe(input: &Path) -> Result<String> {
+    let input_arg = input.to_string_lossy().to_string();
+    run(&["-hide_banner", "-nostats", "-loglevel", "info", "-i", &input_arg, "-t", "0", "-f", "null", "-"]).await
+}
+
+/// `Duration: 00:03:32.02, start: ...`, or `Duration: N/A` for streams.
+fn parse_duration(log: &str) -> Option<f64> {
+    let (_, rest) = log.split_once("Duration: ")?;
+    let value = rest.split(',').next()?.trim();
+    Timestamp::parse(value).ok().map(Timestamp::seconds)
+}
+
+/// Subtitle codecs ffmpeg decodes to text rather than to bitmaps.
+const TEXT_SUBTITLE_CODECS: [&str; 15] = [
+    "mov_text", "subrip", "srt", "ass", "ssa", "webvtt", "text", "microdvd", "mpl2", "realtext", "sami", "subviewer",
+    "subviewer1", "vplayer", "jacosub",
+];
+
+/// `Stream #0:2(eng): Subtitle: webvtt`, or `Stream #0:3[0x1e1]: Subtitle:
+/// hdmv_pgs_subtitle` for a bitmap one. Only the input's streams count, not
+/// the null output's listed after them.
+fn parse_text_subtitle_streams(log: &str) -> Vec<String> {
+    log.lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with("Output #") && !line.starts_with("Stream mapping:"))
+        .filter_map(|line| {
+            let rest = line.strip_prefix("Stream #")?;
+            let (_, codec) = rest.split_once(": Subtitle: ")?;
+            let codec = codec.split([' ', ',']).next()?;
+            let id: String = rest.chars().take_while(|c| c.is_ascii_digit() || *c == ':').collect();
+            (TEXT_SUBTITLE_CODECS.contains(&codec) && !id.is_empty()).then_some(id)
+        })
+        .collect()
+}
+
+/// Write the first audio stream of `input` to `output` with the given codec
+/// options, keeping existing tags and chapters and adding `tags`. A `cover`
+/// image is center-cropped to a square and attached as the front cover. An
+/// audio `filter` needs encoding `codec_args`; ffmpeg's log is returned so
+/// filters that report statistics can be read back.
+pub async fn convert_audio(
+    input: &Path,
+    cover: Option<&Path>,
+    output: &Path,
+    codec_args: &[String],
+    tags: &[(&str, String)],
+    filter: Option<&str>,
+) -> Result<String> {
+    let loglevel = if filter.is_some() { "info" } else { "error" };
+    let mut args: Vec<String> = ["-hide_banner", "-nostats", "-loglevel", loglevel, "-y", "-i"].map(String::from).to_vec();
+    args.push(input.to_string_lossy().to_string());
+    if let Some(cover) = cover {
+        args.push("-i".to_string());
+        args.push(cover.to_string_lossy().to_string());
+    }
+
+    args.extend(["-map", "0:a:0", "-map_metadata", "0"].map(String::from));
+    if cover.is_some() {
+        args.extend(
+            [
+                "-map",
+                "1:v:0",
+                "-c:v",
+                "mjpeg",
+                "-vf",
+                "crop=w='min(iw,ih)':h='min(iw,ih)'",
+                "-disposition:v:0",
+                "attached_pic",
+                "-metadata:s:v",
+                "title=Album cover",
+                "-metadata:s:v",
+                "comment=Cover (front)",
+            ]
+            .map(String::from),
+        );
+    }
+    if let Some(filter) = filter {
+        args.push("-af".to_string());
+        args.push(filter.to_string());
+    }
+    args.extend(codec_args.iter().cloned());
+    for (key, value) in tags {
+        args.push("-metadata".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+    // ID3v2.3 is what most players and car stereos read
+    if output.extension().is_some_and(|ext| ext == "mp3") {
+        args.extend(["-id3v2_version", "3"].map(String::from));
+    }
+    args.push(output.to_string_lossy().to_string());
+
+    run(&args.iter().map(String::as_str).collect::<Vec<_>>()).await
+}
+
+/// Re-encode `input` into `output` with the given output options, reporting
+/// progress through the `duration` seconds of media as `stage`.
+pub async fn transcode(
+    input: &Path,
+    output: &Path,
+    output_args: &[String],
+    duration: Option<f64>,
+    stage: &str,
+    job: Option<&JobHandle>,
+) -> Result<()> {
+    let mut args = progress_args(&[input]);
+    args.extend(output_args.iter().cloned());
+    args.push(output.to_string_lossy().to_string());
+
+    run_with_progress(&args, duration, stage, job).await
+}
+
+/// Turn a clip into an animated GIF or WebP, cut off at the animation's
+/// maximum duration. GIFs take two passes: the first builds a palette
+/// tailored to the clip, the second maps each frame onto it.
+pub async fn animate(
+    input: &Path,
+    output: &Path,
+    animation: &Animation,
+    duration: Option<f64>,
+    job: Option<&JobHandle>,
+) -> Result<()> {
+    let max_duration = animation.max_duration.to_string();
+    let duration = Some(duration.map_or(animation.max_duration, |duration| duration.min(animation.max_duration)));
+    let output_arg = output.to_string_lossy().to_string();
+
+    match animation.format {
+        AnimationFormat::Gif => {
+            let palette = output.with_extension("palette.png");
+            let mut args = progress_args(&[input]);
+            args.extend(["-t".to_string(), max_duration.clone(), "-vf".to_string(), animation.palettegen_filter()]);
+            args.extend(["-update".to_string(), "1".to_string(), palette.to_string_lossy().to_string()]);
+            let result = run_with_progress(&args, duration, "palette", job).await;
+
+            let result = match result {
+                Ok(()) => {
+                    let mut args = progress_args(&[input, palette.as_path()]);
+                    args.extend(["-t".to_string(), max_duration, "-lavfi".to_string(), animation.paletteuse_graph()]);
+                    args.extend(["-loop".to_string(), "0".to_string(), output_arg]);
+                    run_with_progress(&args, duration, "encoding", job).await
+                }
+                Err(e) => Err(e),
+            };
+            let _ = fs::remove_file(&palette).await;
+            result
+        }
+        AnimationFormat::Webp => {
+            let mut args = progress_args(&[input]);
+            args.extend(["-t".to_string(), max_duration]);
+            args.extend(animation.webp_args());
+            args.extend(["-loop".to_string(), "0".to_string(), "-an".to_string(), output_arg]);
+            run_with_progress(&args, duration, "encoding", job).await
+        }
+    }
+}
+
+/// Re-encode an image, optionally scaling it with `filter`.
+pub async fn convert_image(input: &Path, output: &Path, filter: Option<&str>, codec_args: &[String]) -> Result<()> {
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-y", "-i"].map(String::from).to_vec();
+    args.push(input.to_string_lossy().to_string());
+    args.extend(image_output_args(output, filter, codec_args));
+    run(&args.iter().map(String::as_str).collect::<Vec<_>>()).await.map(|_| ())
+}
+
+/// Save the frame shown `seconds` into `input` as an image. Seeking before
+/// the input is fast and, since ffmpeg decodes up to the exact position,
+/// still frame-accurate.
+pub async fn extract_frame(
+    input: &Path,
+    seconds: f64,
+    output: &Path,
+    filter: Option<&str>,
+    codec_args: &[String],
+) -> Result<()> {
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-y", "-ss"].map(String::from).to_vec();
+    args.push(format!("{:.3}", seconds));
+    args.push("-i".to_string());
+    args.push(input.to_string_lossy().to_string());
+    args.extend(image_output_args(output, filter, codec_args));
+    run(&args.iter().map(String::as_str).collect::<Vec<_>>()).await?;
+
+    // Seeking past the last frame succeeds without writing anything
+    if !fs::try_exists(output).await.unwrap_or(false) {
+        return Err(anyhow!("No frame at {:.3}s", seconds));
+    }
+    Ok(())
+}
+
+/// Copy `input` with its chapters replaced by those in `metadata`, an
+/// ffmetadata document.
+pub async fn set_chapters(input: &Path, metadata: &str, output: &Path) -> Result<()> {
+    let metadata_path = output.with_extension("chapters.txt");
+    fs::write(&metadata_path, metadata).await?;
+
+    let input_arg = input.to_string_lossy().to_string();
+    let metadata_arg = metadata_path.to_string_lossy().to_string();
+    let output_arg = output.to_string_lossy().to_string();
+    let args = [
+        "-hide_banner", "-loglevel", "error", "-y", "-i", &input_arg, "-i", &metadata_arg, "-map", "0",
+        "-map_metadata", "0", "-map_chapters", "1", "-c", "copy", &output_arg,
+    ];
+
+    let result = run(&args).await;
+    let _ = fs::remove_file(&metadata_path).await;
+    result.map(|_| ())
+}
+
+/// Combine every stream of `inputs` into `output` without re-encoding,
+/// which also rebuilds the index of a recording that was cut short.
+pub async fn remux(inputs: &[impl AsRef<Path>], output: &Path) -> Result<()> {
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-y"].map(String::from).to_vec();
+    for input in inputs {
+        args.push("-i".to_string());
+        args.push(input.as_ref().to_string_lossy().to_string());
+    }
+    for i in 0..inputs.len() {
+        args.push("-map".to_string());
+        args.push(i.to_string());
+    }
+    args.extend(["-c".to_string(), "copy".to_string()]);
+    let ext = output.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    if matches!(ext.as_deref(), Some("mp4" | "m4a" | "mov")) {
+        args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+    }
+    args.push(output.to_string_lossy().to_string());
+    run(&args.iter().map(String::as_str).collect::<Vec<_>>()).await.map(|_| ())
+}
+
+/// Combine numbered images, given as a `%03d` pattern, into one image with
+/// `filter`, usually a `tile` grid.
+pub async fn tile_images(pattern: &Path, output: &Path, filter: &str, codec_args: &[String]) -> Result<()> {
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-y", "-start_number", "1", "-i"]
+        .map(String::from)
+        .to_vec();
+    args.push(pattern.to_string_lossy().to_string());
+    args.extend(image_output_args(output, Some(filter), codec_args));
+    run(&args.iter().map(String::as_str).collect::<Vec<_>>()).await.map(|_| ())
+}
+
+fn image_output_args(output: &Path, filter: Option<&str>, codec_args: &[String]) -> Vec<String> {
+    let mut args = vec!["-frames:v".to_string(), "1".to_string()];
+    if let Some(filter) = filter {
+        args.push("-vf".to_string());
+        args.push(filter.to_string());
+    }
+    args.extend(codec_args.iter().cloned());
+    // A single image, not a numbered sequence
+    args.extend(["-update".to_string(), "1".to_string()]);
+    args.push(output.to_string_lossy().to_string());
+    args
+}
+
+/// Leading options for a run reporting through `-progress`, up to and
+/// including the inputs.
+fn progress_args(inputs: &[&Path]) -> Vec<String> {
+    let mut args: Vec<String> = ["-hide_banner", "-nostats", "-loglevel", "error", "-y", "-progress", "pipe:1"]
+        .map(String::from)
+        .to_vec();
+    for input in inputs {
+        args.push("-i".to_string());
+        args.push(input.to_string_lossy().to_string());
+    }
+    args
+}
+
+/// Run ffmpeg with `-progress pipe:1`, turning its `key=value` blocks into
+/// job progress. Cancelling the job stops ffmpeg.
+async fn run_with_progress(args: &[String], duration: Option<f64>, stage: &str, job: Option<&JobHandle>) -> Result<()> {
+    println!("Executing ffmpeg with args: {:?}", args);
+
+    let mut child = tokio::process::Command::new("ffmpeg")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr = tokio::spawn(async move {
+        let mut log = String::new();
+        let _ = stderr.read_to_string(&mut log).await;
+        log
+    });
+
+    jobs::report_stage(job, stage);
+    let mut lines = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+    let (mut position, mut speed) = (None, None);
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            _ = jobs::wait_cancelled(job) => {
+                let _ = child.kill().await;
+                return Err(anyhow!("Cancelled while {}", stage));
+            }
+        };
+        let Some(line) = line else {
+            break;
+        };
+        match line.split_once('=') {
+            // Microseconds, despite the name of its `out_time_ms` twin
+            Some(("out_time_us", value)) => position = value.trim().parse::<f64>().ok().map(|us| us / 1_000_000.0),
+            // `1.5x` of real time
+            Some(("speed", value)) => speed = value.trim().trim_end_matches('x').parse::<f64>().ok(),
+            // Ends each block of values
+            Some(("progress", _)) => jobs::report(job, progress(stage, position, speed, duration)),
+            _ => {}
+        }
+    }
+
+    let status = child.wait().await?;
+    let log = stderr.await.unwrap_or_default();
+    if !status.success() {
+        println!("ffmpeg stderr: {}", log);
+        return Err(anyhow!("ffmpeg failed: {}", log));
+    }
+    Ok(())
+}
+
+fn progress(stage: &str, position: Option<f64>, speed: Option<f64>, duration: Option<f64>) -> JobProgress {
+    let (percent, eta_secs) = match (position, duration) {
+        (Some(position), Some(duration)) if duration > 0.0 => {
+            let percent = (position / duration * 100.0).clamp(0.0, 100.0);
+            let eta = speed
+                .filter(|speed| *speed > 0.0)
+                .map(|speed| ((duration - position).max(0.0) / speed) as u64);
+            (Some((percent * 10.0).round() / 10.0), eta)
+        }
+        _ => (None, None),
+    };
+
+    JobProgress {
+        percent,
+        eta_secs,
+        ..JobProgress::stage(stage)
+    }
+}
+
+/// Run ffmpeg and return what it logged. Dropping the future kills ffmpeg.
+async fn run(args: &[&str]) -> Result<String> {
+    println!("Executing ffmpeg with args: {:?}", args);
+
+    let output = tokio::process::Command::new("ffmpeg")
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    let log = String::from_utf8_lossy(&output.stderr).to_string();
+    if !output.status.success() {
+        println!("ffmpeg stderr: {}", log);
+        return Err(anyhow!("ffmpeg failed: {}", log));
+    }
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_is_read_from_the_input_header() {
+        let log = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'in.mp4':\n  Duration: 00:03:32.02, start: 0.000000, bitrate: 1200 kb/s\n";
+        assert_eq!(parse_duration(log), Some(212.02));
+        assert_eq!(parse_duration("  Duration: 01:00:00.00, start: 0"), Some(3600.0));
+        assert_eq!(parse_duration("  Duration: N/A, start: 0.000000, bitrate: N/A"), None);
+        assert_eq!(parse_duration("no header"), None);
+    }
+
+    #[test]
+    fn only_text_subtitles_are_listed() {
+        let log = "Input #0, matroska,webm, from 'in.mkv':
+  Duration: 00:01:00.00, start: 0.000000, bitrate: 900 kb/s
+  Stream #0:0(eng): Video: vp9 (Profile 0), yuv420p(tv), 1920x1080, SAR 1:1 DAR 16:9, 30 fps
+  Stream #0:1(eng): Audio: opus, 48000 Hz, stereo, fltp (default)
+  Stream #0:2(eng): Subtitle: webvtt (default)
+  Stream #0:3[0x1e1](deu): Subtitle: hdmv_pgs_subtitle, 1920x1080
+  Stream #0:4(fra): Subtitle: ass
+  Stream #0:5: Subtitle: dvd_subtitle
+  Stream #0:6: Data: bin_data (text / 0x74786574)
+Stream mapping:
+  Stream #0:2 -> #0:0 (webvtt (native) -> ass (ssa))
+Output #0, null, to 'pipe:':
+  Stream #0:0(eng): Subtitle: ass (default)
+";
+        assert_eq!(parse_text_subtitle_streams(log), ["0:2", "0:4"]);
+        assert!(parse_text_subtitle_streams("  Stream #0:0: Video: h264").is_empty());
+    }
+}
@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Longest filename we hand out, in bytes. Most filesystems cap names at 255.
+const MAX_FILENAME_BYTES: usize = 200;
+
+const TEMPLATE_FIELDS: [&str; 8] = [
+    "id",
+    "title",
+    "uploader",
+    "channel",
+    "upload_date",
+    "format_id",
+    "resolution",
+    "ext",
+];
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub const DEFAULT_TEMPLATE: &str = "{title}.{ext}";
+
+/// A download filename template such as `{uploader} - {title} [{id}].{ext}`.
+/// Placeholders are checked up front so a typo fails the request instead of
+/// producing a literal `{titel}` in every filename.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FilenameTemplate(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTemplate(String);
+
+impl fmt::Display for InvalidTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid filename template: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidTemplate {}
+
+impl FilenameTemplate {
+    pub fn parse(raw: &str) -> Result<Self, InvalidTemplate> {
+        let mut rest = raw;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map(|i| start + i)
+                .ok_or_else(|| InvalidTemplate(format!("unclosed placeholder in '{}'", raw)))?;
+            let field = &rest[start + 1..end];
+            if !TEMPLATE_FIELDS.contains(&field) {
+                return Err(InvalidTemplate(format!(
+                    "unknown placeholder '{{{}}}', expected one of {}",
+                    field,
+                    TEMPLATE_FIELDS.join(", ")
+                )));
+            }
+            rest = &rest[end + 1..];
+        }
+
+        if !raw.contains("{ext}") {
+            return Err(InvalidTemplate("template must contain {ext}".to_string()));
+        }
+
+        Ok(Self(raw.to_string()))
+    }
+
+    /// Fill in the placeholders. Missing fields render as `NA`, like yt-dlp's
+    /// own output templates; the result still needs `sanitize_filename`.
+    pub fn render(&self, fields: &HashMap<&str, String>) -> String {
+        let mut out = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let end = start + rest[start..].find('}').unwrap_or(rest.len() - start);
+            let field = &rest[start + 1..end];
+            match fields.get(field).map(|v| v.trim()) {
+                Some(value) if !value.is_empty() => out.push_str(value),
+                _ => out.push_str("NA"),
+            }
+            rest = &rest[(end + 1).min(rest.len())..];
+        }
+
+        out.push_str(rest);
+        out
+    }
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        Self(DEFAULT_TEMPLATE.to_string())
+    }
+}
+
+impl TryFrom<String> for FilenameTemplate {
+    type Error = InvalidTemplate;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<FilenameTemplate> for String {
+    fn from(template: FilenameTemplate) -> Self {
+        template.0
+    }
+}
+
+/// Make a filename safe to write to disk and to put in a header: control
+/// characters and path separators go, reserved characters become `_`,
+/// whitespace is collapsed, reserved device names such as `CON` are
+/// prefixed with `_` and the name is trimmed to `MAX_FILENAME_BYTES` while
+/// keeping the extension.
+pub fn sanitize_filename(name: &str) -> String {
+    let mut cleaned = String::with_capacity(name.len());
+    let mut last_was_space = false;
+
+    for c in name.chars() {
+        let c = match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() || c.is_whitespace() => ' ',
+            c => c,
+        };
+
+        if c == ' ' {
+            if last_was_space {
+                continue;
+            }
+            last_was_space = true;
+        } else {
+            last_was_space = false;
+        }
+        cleaned.push(c);
+    }
+
+    // Leading dots would make hidden files; trailing dots and spaces are
+    // stripped by Windows
+    let cleaned = cleaned
+        .trim_matches(|c: char| c == ' ')
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' '])
+        .to_string();
+
+    let stem = cleaned.split('.').next().unwrap_or_default().trim_end();
+    let cleaned = if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        format!("_{}", cleaned)
+    } else {
+        cleaned
+    };
+
+    let cleaned = truncate_keeping_extension(&cleaned, MAX_FILENAME_BYTES);
+    if cleaned.is_empty() || cleaned.starts_with('.') {
+        format!("download{}", cleaned)
+    } else {
+        cleaned
+    }
+}
+
+/// An ASCII-only rendition of a sanitized filename for the plain `filename`
+/// parameter. Non-ASCII runs become a single `_`.
+pub fn ascii_fallback(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        let c = match c {
+            c if c.is_ascii_graphic() || c == ' ' => c,
+            _ => '_',
+        };
+        // `%` and `\` are legal in a quoted-string but browsers treat them
+        // inconsistently, so avoid them altogether
+        let c = if matches!(c, '%' | '\\' | '"' | ';') { '_' } else { c };
+        if c == '_' && out.ends_with('_') {
+            continue;
+        }
+        out.push(c);
+    }
+
+    let out = out.trim().to_string();
+    if out.is_empty() || out.starts_with('.') {
+        format!("download{}", out)
+    } else {
+        out
+    }
+}
+
+/// Build an RFC 6266 `Content-Disposition` value with an ASCII fallback and
+/// an RFC 5987 `filename*` carrying the full UTF-8 name.
+pub fn content_disposition(filename: &str) -> String {
+    let filename = sanitize_filename(filename);
+    let fallback = ascii_fallback(&filename);
+
+    if fallback == filename {
+        format!("attachment; filename=\"{}\"", fallback)
+    } else {
+        format!(
+            "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+            fallback,
+            percent_encode(&filename)
+        )
+    }
+}
+
+/// Percent-encode everything outside RFC 5987 `attr-char`.
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() * 3);
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn truncate_keeping_extension(name: &str, max_bytes: usize) -> String {
+    if name.len() <= max_bytes {
+        return name.to_string();
+    }
+
+    let (stem, ext) = match name.rfind('.') {
+        Some(pos) if name.len() - pos <= 10 => (&name[..pos], &name[pos..]),
+        _ => (name, ""),
+    };
+
+    let mut cut = max_bytes.saturating_sub(ext.len()).min(stem.len());
+    while !stem.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    format!("{}{}", stem[..cut].trim_end(), ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_characters_and_control_characters_are_replaced() {
+        assert_eq!(sanitize_filename("a/b\\c:d*e?f\"g<h>i|j.mp4"), "a_b_c_d_e_f_g_h_i_j.mp4");
+        assert_eq!(sanitize_filename("line\nbreak\tand\u{0}nul\u{7f}.mp4"), "line break and nul .mp4");
+        assert_eq!(sanitize_filename("  lots   of\r\n space  .mp4 "), "lots of space .mp4");
+        assert_eq!(sanitize_filename("../../etc/passwd"), "_.._etc_passwd");
+    }
+
+    #[test]
+    fn hidden_and_empty_names_get_a_stem() {
+        assert_eq!(sanitize_filename(".bashrc"), "bashrc");
+        assert_eq!(sanitize_filename("name. . ."), "name");
+        assert_eq!(sanitize_filename(""), "download");
+        assert_eq!(sanitize_filename("\u{1}\u{2}"), "download");
+    }
+
+    #[test]
+    fn windows_device_names_are_prefixed() {
+        for name in ["CON", "con.mp4", "Aux.tar.gz", "nul ", "COM1.txt", "lpt9.mp3", "PRN .mp4"] {
+            assert!(sanitize_filename(name).starts_with('_'), "'{}' should be prefixed", name);
+        }
+        for name in ["CONSOLE.mp4", "COM10.mp4", "icon.png", "nullable.txt"] {
+            assert!(!sanitize_filename(name).starts_with('_'), "'{}' should be left alone", name);
+        }
+    }
+
+    #[test]
+    fn long_names_are_cut_on_a_character_boundary() {
+        // Two-, three- and four-byte characters straddling the limit
+        for c in ["é", "日", "🎬"] {
+            for pad in 0..4 {
+                let name = format!("{}{}.mp4", "a".repeat(pad), c.repeat(100));
+                let truncated = sanitize_filename(&name);
+                assert!(truncated.len() <= MAX_FILENAME_BYTES, "{} bytes", truncated.len());
+                assert!(truncated.ends_with(".mp4"));
+                assert!(truncated.trim_end_matches(".mp4").ends_with(c));
+            }
+        }
+        // An extension too long to be one is cut like the rest of the name
+        let name = format!("{}.{}", "a".repeat(150), "b".repeat(100));
+        assert_eq!(sanitize_filename(&name).len(), MAX_FILENAME_BYTES);
+        assert_eq!(truncate_keeping_extension("short.mp4", 5), "s.mp4");
+    }
+
+    #[test]
+    fn ascii_names_need_no_extended_parameter() {
+        assert_eq!(content_disposition("My Video.mp4"), "attachment; filename=\"My Video.mp4\"");
+    }
+
+    #[test]
+    fn quotes_and_backslashes_never_reach_the_quoted_string() {
+        let header = content_disposition("say \"hi\" \\ bye.mp4");
+        assert_eq!(header, "attachment; filename=\"say _hi_ _ bye.mp4\"");
+        let header = content_disposition("50% off; \"deal\".mp4");
+        assert_eq!(
+            header,
+            "attachment; filename=\"50_ off_ _deal_.mp4\"; filename*=UTF-8''50%25%20off%3B%20_deal_.mp4"
+        );
+    }
+
+    #[test]
+    fn unicode_names_are_percent_encoded_per_rfc_5987() {
+        assert_eq!(
+            content_disposition("Café ☕ 'n' *.mp4"),
+            "attachment; filename=\"Caf_ _ 'n' _.mp4\"; filename*=UTF-8''Caf%C3%A9%20%E2%98%95%20%27n%27%20_.mp4"
+        );
+        assert_eq!(
+            content_disposition("日本語.mp4"),
+            "attachment; filename=\"_.mp4\"; filename*=UTF-8''%E6%97%A5%E6%9C%AC%E8%AA%9E.mp4"
+        );
+    }
+
+    #[test]
+    fn templates_are_checked_and_rendered() {
+        assert!(FilenameTemplate::parse("{title}").is_err());
+        assert!(FilenameTemplate::parse("{titel}.{ext}").is_err());
+        assert!(FilenameTemplate::parse("{title.{ext}").is_err());
+        let template = FilenameTemplate::parse("{uploader} - {title} [{id}].{ext}").unwrap();
+        let fields = HashMap::from([("title", "Song".to_string()), ("id", "abc".to_string()), ("ext", "mp3".to_string())]);
+        assert_eq!(template.render(&fields), "NA - Song [abc].mp3");
+    }
+}
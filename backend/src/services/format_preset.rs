@@ -0,0 +1,380 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+
+use crate::error::ApiError;
+use crate::services::format_ranking::VideoCodec;
+use crate::services::format_selector::FormatSelector;
+use crate::services::ytdlp_info::{Format, InfoDict};
+
+/// Named constraint sets for the common cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Highest quality available
+    Best,
+    /// Smallest download that still has video (or audio)
+    Smallest,
+    /// 720p H.264/AAC in MP4, for older TVs and players
+    Compatible,
+    /// 480p, preferring the most efficient codecs
+    DataSaver,
+}
+
+impl Preset {
+    pub fn constraints(self) -> FormatConstraints {
+        match self {
+            Preset::Best => FormatConstraints::default(),
+            Preset::Smallest => FormatConstraints {
+                prefer: Some(Preference::Smallest),
+                ..Default::default()
+            },
+            Preset::Compatible => FormatConstraints {
+                max_height: Some(720),
+                max_fps: Some(30.0),
+                codecs: Some(vec![VideoCodec::H264]),
+                container: Some(Container::Mp4),
+                ..Default::default()
+            },
+            Preset::DataSaver => FormatConstraints {
+                max_height: Some(480),
+                codec_preference: Some(vec![VideoCodec::Av1, VideoCodec::Vp9, VideoCodec::H264]),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preference {
+    Best,
+    Smallest,
+}
+
+/// Output container. Video downloads pair MP4 video with M4A audio and WebM
+/// with WebM so yt-dlp can merge without re-encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    M4a,
+    Webm,
+}
+
+impl Container {
+    fn video_ext(self) -> &'static str {
+        match self {
+            Container::Mp4 | Container::M4a => "mp4",
+            Container::Webm => "webm",
+        }
+    }
+
+    fn audio_ext(self) -> &'static str {
+        match self {
+            Container::Mp4 | Container::M4a => "m4a",
+            Container::Webm => "webm",
+        }
+    }
+}
+
+/// Limits a download's format has to satisfy. Unset fields are unconstrained.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FormatConstraints {
+    #[serde(rename = "maxHeight", skip_serializing_if = "Option::is_none")]
+    pub max_height: Option<u32>,
+    #[serde(rename = "maxFps", skip_serializing_if = "Option::is_none")]
+    pub max_fps: Option<f64>,
+    /// Most preferred first; codecs not listed rank last
+    #[serde(rename = "codecPreference", skip_serializing_if = "Option::is_none")]
+    pub codec_preference: Option<Vec<VideoCodec>>,
+    /// The only video codecs allowed; formats in any other, or an unknown
+    /// one, are skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codecs: Option<Vec<VideoCodec>>,
+    /// Upper bound on the combined size in bytes. Formats whose size can
+    /// neither be read nor estimated from bitrate and duration are skipped.
+    #[serde(rename = "maxFilesize", skip_serializing_if = "Option::is_none")]
+    pub max_filesize: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefer: Option<Preference>,
+}
+
+impl FormatConstraints {
+    /// Fields set here win over the ones in `base`.
+    pub fn or(self, base: FormatConstraints) -> FormatConstraints {
+        FormatConstraints {
+            max_height: self.max_height.or(base.max_height),
+            max_fps: self.max_fps.or(base.max_fps),
+            codec_preference: self.codec_preference.or(base.codec_preference),
+            codecs: self.codecs.or(base.codecs),
+            max_filesize: self.max_filesize.or(base.max_filesize),
+            container: self.container.or(base.container),
+            prefer: self.prefer.or(base.prefer),
+        }
+    }
+
+    fn allows_video(&self, format: &Format) -> bool {
+        let height_ok = match (self.max_height, format.height) {
+            (Some(max), Some(height)) => height <= max,
+            _ => true,
+        };
+        let fps_ok = match (self.max_fps, format.fps) {
+            (Some(max), Some(fps)) => fps <= max,
+            _ => true,
+        };
+        let codec_ok = self.codecs.as_ref().is_none_or(|codecs| {
+            format
+                .vcodec
+                .as_deref()
+                .and_then(VideoCodec::from_vcodec)
+                .is_some_and(|codec| codecs.contains(&codec))
+        });
+        height_ok && fps_ok && codec_ok && self.container_ok(format, self.container.map(Container::video_ext))
+    }
+
+    fn allows_audio(&self, format: &Format) -> bool {
+        self.container_ok(format, self.container.map(Container::audio_ext))
+    }
+
+    fn container_ok(&self, format: &Format, ext: Option<&str>) -> bool {
+        ext.is_none_or(|ext| format.ext.as_deref() == Some(ext))
+    }
+}
+
+/// Concrete formats chosen for a set of constraints.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub selector: FormatSelector,
+    pub video: Option<Format>,
+    pub audio: Option<Format>,
+    /// Exact or approximate size, or one estimated from bitrate and duration
+    pub estimated_filesize: Option<u64>,
+}
+
+struct Candidate<'a> {
+    video: Option<&'a Format>,
+    audio: Option<&'a Format>,
+    size: Option<u64>,
+}
+
+/// Pick the formats that best satisfy `constraints`. Video downloads get a
+/// video-only format merged with an audio-only one, or a progressive format;
+/// audio downloads get a single audio-only format.
+pub fn resolve(
+    info: &InfoDict,
+    constraints: &FormatConstraints,
+    audio_only: bool,
+    default_preference: &[VideoCodec],
+) -> Result<Selection> {
+    let duration = info.duration;
+    let size = |format: &Format| estimated_size(format, duration);
+
+    let audios: Vec<&Format> = info
+        .formats
+        .iter()
+        .filter(|f| f.has_audio() && !f.has_video() && constraints.allows_audio(f))
+        .collect();
+
+    let mut candidates = Vec::new();
+    if audio_only {
+        for audio in &audios {
+            candidates.push(Candidate { video: None, audio: Some(*audio), size: size(audio) });
+        }
+    } else {
+        for video in info.formats.iter().filter(|f| f.has_video() && constraints.allows_video(f)) {
+            if video.has_audio() {
+                candidates.push(Candidate { video: Some(video), audio: None, size: size(video) });
+                continue;
+            }
+            for audio in &audios {
+                let combined = size(video).zip(size(audio)).map(|(v, a)| v + a);
+                candidates.push(Candidate { video: Some(video), audio: Some(*audio), size: combined });
+            }
+        }
+    }
+
+    if let Some(max) = constraints.max_filesize {
+        candidates.retain(|c| c.size.is_some_and(|size| size <= max));
+    }
+
+    let preference = constraints.codec_preference.as_deref().unwrap_or(default_preference);
+    let best = match constraints.prefer.unwrap_or(Preference::Best) {
+        Preference::Best => candidates.into_iter().min_by(|a, b| compare_best(a, b, preference)),
+        Preference::Smallest => candidates
+            .into_iter()
+            .min_by(|a, b| compare_smallest(a, b).then_with(|| compare_best(a, b, preference))),
+    };
+
+    let best = best.ok_or_else(|| ApiError::BadRequest("No format satisfies the requested constraints".to_string()))?;
+
+    let selector = match (best.video, best.audio) {
+        (Some(video), Some(audio)) => format!("{}+{}", video.format_id, audio.format_id),
+        (Some(single), None) | (None, Some(single)) => single.format_id.clone(),
+        (None, None) => unreachable!("candidates always carry a format"),
+    };
+
+    Ok(Selection {
+        selector: FormatSelector::parse(&selector)?,
+        video: best.video.cloned(),
+        audio: best.audio.cloned(),
+        estimated_filesize: best.size,
+    })
+}
+
+/// Smaller is better for `min_by`: higher resolution, frame rate, preferred
+/// codec and bitrate sort first.
+fn compare_best(a: &Candidate, b: &Candidate, preference: &[VideoCodec]) -> Ordering {
+    let height = |c: &Candidate| Reverse(c.video.and_then(|v| v.height).unwrap_or(0));
+    let fps = |c: &Candidate| Reverse(c.video.and_then(|v| v.fps).unwrap_or(0.0).round() as u32);
+    let codec = |c: &Candidate| {
+        c.video
+            .and_then(|v| v.vcodec.as_deref())
+            .and_then(VideoCodec::from_vcodec)
+            .and_then(|codec| preference.iter().position(|p| *p == codec))
+            .unwrap_or(preference.len())
+    };
+    let video_bitrate = |c: &Candidate| c.video.and_then(|v| v.tbr).unwrap_or(0.0);
+    let audio_bitrate = |c: &Candidate| c.audio.and_then(|a| a.abr.or(a.tbr)).unwrap_or(0.0);
+
+    height(a)
+        .cmp(&height(b))
+        .then(fps(a).cmp(&fps(b)))
+        .then(codec(a).cmp(&codec(b)))
+        .then(video_bitrate(b).total_cmp(&video_bitrate(a)))
+        .then(audio_bitrate(b).total_cmp(&audio_bitrate(a)))
+}
+
+/// Known sizes first, smallest first.
+fn compare_smallest(a: &Candidate, b: &Candidate) -> Ordering {
+    match (a.size, b.size) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn estimated_size(format: &Format, duration: Option<f64>) -> Option<u64> {
+    format.filesize.or(format.filesize_approx).or_else(|| {
+        // tbr is in kbit/s
+        let bytes = format.tbr? * 1000.0 / 8.0 * duration?;
+        (bytes > 0.0).then_some(bytes as u64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(id: &str, ext: &str, vcodec: Option<&str>, acodec: Option<&str>, height: Option<u32>, tbr: f64) -> Format {
+        Format {
+            format_id: id.to_string(),
+            ext: Some(ext.to_string()),
+            vcodec: Some(vcodec.unwrap_or("none").to_string()),
+            acodec: Some(acodec.unwrap_or("none").to_string()),
+            height,
+            fps: height.map(|_| 30.0),
+            tbr: Some(tbr),
+            ..Default::default()
+        }
+    }
+
+    /// A spread of codecs and containers like YouTube serves.
+    fn mixed_info() -> InfoDict {
+        InfoDict {
+            duration: Some(100.0),
+            formats: vec![
+                format("18", "mp4", Some("avc1.42001E"), Some("mp4a.40.2"), Some(360), 500.0),
+                format("140", "m4a", None, Some("mp4a.40.2"), None, 128.0),
+                format("251", "webm", None, Some("opus"), None, 160.0),
+                format("136", "mp4", Some("avc1.4d401f"), None, Some(720), 1500.0),
+                format("247", "webm", Some("vp9"), None, Some(720), 1400.0),
+                format("398", "mp4", Some("av01.0.05M.08"), None, Some(720), 1000.0),
+                format("399", "mp4", Some("av01.0.08M.08"), None, Some(1080), 2000.0),
+                format("137", "mp4", Some("avc1.640028"), None, Some(1080), 4000.0),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn selector(constraints: &FormatConstraints) -> String {
+        let preference = [VideoCodec::Av1, VideoCodec::Vp9, VideoCodec::H264];
+        resolve(&mixed_info(), constraints, false, &preference).unwrap().selector.to_string()
+    }
+
+    #[test]
+    fn best_follows_codec_preference_at_top_resolution() {
+        assert_eq!(selector(&Preset::Best.constraints()), "399+251");
+    }
+
+    #[test]
+    fn compatible_only_takes_h264() {
+        // The 720p AV1 format is also in MP4 and would win on preference
+        assert_eq!(selector(&Preset::Compatible.constraints()), "136+140");
+    }
+
+    #[test]
+    fn compatible_fails_without_h264() {
+        let mut info = mixed_info();
+        info.formats.retain(|format| !format.vcodec.as_deref().unwrap_or_default().starts_with("avc1"));
+        let preference = [VideoCodec::Av1];
+        assert!(resolve(&info, &Preset::Compatible.constraints(), false, &preference).is_err());
+    }
+
+    #[test]
+    fn codecs_allow_several() {
+        let constraints = FormatConstraints {
+            max_height: Some(720),
+            codecs: Some(vec![VideoCodec::Vp9, VideoCodec::H264]),
+            codec_preference: Some(vec![VideoCodec::Vp9]),
+            ..Default::default()
+        };
+        assert_eq!(selector(&constraints), "247+251");
+    }
+
+    #[test]
+    fn data_saver_caps_height() {
+        // Only the progressive 360p format is at or below 480p
+        assert_eq!(selector(&Preset::DataSaver.constraints()), "18");
+    }
+
+    #[test]
+    fn smallest_picks_the_least_bytes() {
+        assert_eq!(selector(&Preset::Smallest.constraints()), "18");
+    }
+
+    #[test]
+    fn max_filesize_drops_larger_pairs() {
+        // 100s at 1000+160 kbit/s is 14.5 MB
+        let constraints = FormatConstraints {
+            max_filesize: Some(15_000_000),
+            ..Default::default()
+        };
+        assert_eq!(selector(&constraints), "398+251");
+    }
+
+    #[test]
+    fn audio_only_picks_best_audio_in_container() {
+        let constraints = FormatConstraints {
+            container: Some(Container::M4a),
+            ..Default::default()
+        };
+        let selection = resolve(&mixed_info(), &constraints, true, &[]).unwrap();
+        assert_eq!(selection.selector.to_string(), "140");
+        assert_eq!(selection.estimated_filesize, Some(1_600_000));
+    }
+
+    #[test]
+    fn request_fields_override_the_preset() {
+        let constraints = FormatConstraints {
+            max_height: Some(1080),
+            ..Default::default()
+        }
+        .or(Preset::Compatible.constraints());
+        assert_eq!(constraints.codecs, Some(vec![VideoCodec::H264]));
+        assert_eq!(selector(&constraints), "137+140");
+    }
+}
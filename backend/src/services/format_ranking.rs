@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+
+use crate::types::{AudioFormat, VideoFormat};
+
+/// Video codec families that can be ranked against each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    Av1,
+    Vp9,
+    H264,
+    Hevc,
+}
+
+impl VideoCodec {
+    /// Classify a yt-dlp `vcodec` string such as `avc1.640028` or `vp09.00.40.08`.
+    pub fn from_vcodec(vcodec: &str) -> Option<Self> {
+        let vcodec = vcodec.to_ascii_lowercase();
+        if vcodec.starts_with("av01") || vcodec == "av1" {
+            Some(VideoCodec::Av1)
+        } else if vcodec.starts_with("vp09") || vcodec.starts_with("vp9") {
+            Some(VideoCodec::Vp9)
+        } else if vcodec.starts_with("avc") || vcodec.starts_with("h264") {
+            Some(VideoCodec::H264)
+        } else if vcodec.starts_with("hvc1") || vcodec.starts_with("hev1") || vcodec.starts_with("h265") {
+            Some(VideoCodec::Hevc)
+        } else {
+            None
+        }
+    }
+}
+
+/// H.264 first: it plays everywhere, which is what most users downloading a
+/// file want.
+pub fn default_codec_preference() -> Vec<VideoCodec> {
+    vec![VideoCodec::H264, VideoCodec::Vp9, VideoCodec::Av1, VideoCodec::Hevc]
+}
+
+/// Formats are interchangeable when they share a resolution, frame-rate
+/// class, dynamic range and whether they carry audio.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GroupKey {
+    height: u32,
+    high_fps: bool,
+    hdr: bool,
+    has_audio: bool,
+}
+
+impl GroupKey {
+    fn of(format: &VideoFormat) -> Self {
+        Self {
+            height: format.height.unwrap_or(0),
+            // 48/50/60 fps vs. 24/25/30 fps
+            high_fps: format.fps.is_some_and(|fps| fps > 30.5),
+            hdr: format.dynamic_range.as_deref().is_some_and(|range| range != "SDR"),
+            has_audio: format.has_audio,
+        }
+    }
+
+    /// Highest resolution first, then high frame rate, then HDR; progressive
+    /// formats sort after their video-only counterparts.
+    fn display_order(&self, other: &Self) -> Ordering {
+        other
+            .height
+            .cmp(&self.height)
+            .then(other.high_fps.cmp(&self.high_fps))
+            .then(other.hdr.cmp(&self.hdr))
+            .then(self.has_audio.cmp(&other.has_audio))
+    }
+}
+
+/// Collapse video formats into one entry per group, choosing the preferred
+/// codec, then a format with a known size, then the highest bitrate. The
+/// losers are attached as `alternatives` when `keep_alternatives` is set.
+pub fn rank_video_formats(
+    formats: Vec<VideoFormat>,
+    preference: &[VideoCodec],
+    keep_alternatives: bool,
+) -> Vec<VideoFormat> {
+    let mut groups: Vec<(GroupKey, Vec<VideoFormat>)> = Vec::new();
+    for format in formats {
+        let key = GroupKey::of(&format);
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, members)) => members.push(format),
+            None => groups.push((key, vec![format])),
+        }
+    }
+
+    groups.sort_by(|(a, _), (b, _)| a.display_order(b));
+
+    groups
+        .into_iter()
+        .filter_map(|(_, mut members)| {
+            members.sort_by(|a, b| compare_video(a, b, preference));
+            let mut members = members.into_iter();
+            let mut best = members.next()?;
+            if keep_alternatives {
+                best.alternatives = members.collect();
+            }
+            Some(best)
+        })
+        .collect()
+}
+
+fn compare_video(a: &VideoFormat, b: &VideoFormat, preference: &[VideoCodec]) -> Ordering {
+    let codec_rank = |format: &VideoFormat| {
+        format
+            .vcodec
+            .as_deref()
+            .and_then(VideoCodec::from_vcodec)
+            .and_then(|codec| preference.iter().position(|p| *p == codec))
+            .unwrap_or(preference.len())
+    };
+    let has_size = |format: &VideoFormat| format.filesize.is_some() || format.filesize_approx.is_some();
+    let bitrate = |format: &VideoFormat| format.tbr.or(format.vbr).unwrap_or(0.0);
+
+    codec_rank(a)
+        .cmp(&codec_rank(b))
+        .then(has_size(b).cmp(&has_size(a)))
+        .then(bitrate(b).total_cmp(&bitrate(a)))
+}
+
+/// Audio keeps one entry per bitrate and container, preferring formats
+/// whose size is known.
+pub fn rank_audio_formats(mut formats: Vec<AudioFormat>) -> Vec<AudioFormat> {
+    formats.sort_by_key(|f| {
+        let has_size = f.filesize.is_some() || f.filesize_approx.is_some();
+        (Reverse(f.abr), f.ext.clone(), !has_size)
+    });
+    formats.dedup_by(|a, b| a.abr == b.abr && a.ext == b.ext);
+    formats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(format_id: &str, vcodec: &str, height: u32, fps: f64, dynamic_range: &str, tbr: f64) -> VideoFormat {
+        VideoFormat {
+            format_id: format_id.to_string(),
+            quality: format!("{}p", height),
+            ext: "mp4".to_string(),
+            filesize: None,
+            filesize_approx: None,
+            filesize_display: None,
+            width: None,
+            height: Some(height),
+            fps: Some(fps),
+            vcodec: Some(vcodec.to_string()),
+            acodec: None,
+            has_audio: false,
+            tbr: Some(tbr),
+            vbr: None,
+            dynamic_range: Some(dynamic_range.to_string()),
+            protocol: None,
+            alternatives: Vec::new(),
+        }
+    }
+
+    fn ids(formats: &[VideoFormat]) -> Vec<&str> {
+        formats.iter().map(|format| format.format_id.as_str()).collect()
+    }
+
+    /// What YouTube typically offers at 1080p.
+    fn formats_1080p() -> Vec<VideoFormat> {
+        vec![
+            video("137", "avc1.640028", 1080, 30.0, "SDR", 4400.0),
+            video("248", "vp09.00.40.08", 1080, 30.0, "SDR", 2600.0),
+            video("399", "av01.0.08M.08", 1080, 30.0, "SDR", 2100.0),
+            video("299", "avc1.64002a", 1080, 60.0, "SDR", 6600.0),
+            video("303", "vp09.00.41.08", 1080, 60.0, "SDR", 4300.0),
+            video("699", "av01.0.09M.10", 1080, 60.0, "HDR10", 5800.0),
+            video("337", "vp09.02.51.10", 1080, 60.0, "HDR10", 7500.0),
+        ]
+    }
+
+    #[test]
+    fn codecs_are_classified() {
+        assert_eq!(VideoCodec::from_vcodec("avc1.640028"), Some(VideoCodec::H264));
+        assert_eq!(VideoCodec::from_vcodec("vp9"), Some(VideoCodec::Vp9));
+        assert_eq!(VideoCodec::from_vcodec("VP09.00.40.08"), Some(VideoCodec::Vp9));
+        assert_eq!(VideoCodec::from_vcodec("av01.0.08M.08"), Some(VideoCodec::Av1));
+        assert_eq!(VideoCodec::from_vcodec("hev1.1.6.L93"), Some(VideoCodec::Hevc));
+        assert_eq!(VideoCodec::from_vcodec("none"), None);
+    }
+
+    #[test]
+    fn groups_split_on_frame_rate_and_dynamic_range() {
+        let ranked = rank_video_formats(formats_1080p(), &default_codec_preference(), false);
+        // 60 fps HDR, 60 fps SDR, then 30 fps SDR
+        assert_eq!(ids(&ranked), ["337", "299", "137"]);
+        assert!(ranked.iter().all(|format| format.alternatives.is_empty()));
+    }
+
+    #[test]
+    fn the_preferred_codec_represents_each_group() {
+        let ranked = rank_video_formats(formats_1080p(), &[VideoCodec::Av1, VideoCodec::Vp9], false);
+        // H.264 is not listed, so VP9 wins at 60 fps SDR
+        assert_eq!(ids(&ranked), ["699", "303", "399"]);
+    }
+
+    #[test]
+    fn losers_are_kept_as_alternatives_in_rank_order() {
+        let ranked = rank_video_formats(formats_1080p(), &default_codec_preference(), true);
+        let alternatives: Vec<Vec<&str>> = ranked.iter().map(|format| ids(&format.alternatives)).collect();
+        assert_eq!(alternatives, [vec!["699"], vec!["303"], vec!["248", "399"]]);
+    }
+
+    #[test]
+    fn frame_rates_up_to_30_count_as_standard() {
+        let formats = vec![
+            video("a", "avc1", 720, 29.97, "SDR", 1000.0),
+            video("b", "avc1", 720, 30.5, "SDR", 1100.0),
+            video("c", "avc1", 720, 30.6, "SDR", 1200.0),
+            video("d", "avc1", 720, 50.0, "SDR", 1300.0),
+        ];
+        let ranked = rank_video_formats(formats, &default_codec_preference(), true);
+        assert_eq!(ids(&ranked), ["d", "b"]);
+        assert_eq!(ids(&ranked[0].alternatives), ["c"]);
+        assert_eq!(ids(&ranked[1].alternatives), ["a"]);
+    }
+
+    #[test]
+    fn known_sizes_then_bitrate_break_codec_ties() {
+        let mut sized = video("sized", "avc1", 720, 30.0, "SDR", 900.0);
+        sized.filesize_approx = Some(1_000_000);
+        let formats = vec![video("fast", "avc1", 720, 30.0, "SDR", 2000.0), sized, video("slow", "avc1", 720, 30.0, "SDR", 1000.0)];
+        let ranked = rank_video_formats(formats, &default_codec_preference(), true);
+        assert_eq!(ids(&ranked), ["sized"]);
+        assert_eq!(ids(&ranked[0].alternatives), ["fast", "slow"]);
+    }
+
+    #[test]
+    fn progressive_formats_sort_after_video_only_ones() {
+        let mut progressive = video("18", "avc1.42001E", 360, 30.0, "SDR", 500.0);
+        progressive.has_audio = true;
+        let formats = vec![
+            progressive,
+            video("134", "avc1.4d401e", 360, 30.0, "SDR", 300.0),
+            video("135", "avc1.4d401f", 480, 30.0, "SDR", 600.0),
+            // Unknown codecs rank after every preferred one
+            video("x", "theora", 360, 30.0, "SDR", 9000.0),
+        ];
+        let ranked = rank_video_formats(formats, &default_codec_preference(), true);
+        assert_eq!(ids(&ranked), ["135", "134", "18"]);
+        assert_eq!(ids(&ranked[1].alternatives), ["x"]);
+    }
+
+    #[test]
+    fn audio_keeps_one_format_per_bitrate_and_container() {
+        let audio = |format_id: &str, ext: &str, abr: u32, filesize: Option<u64>| AudioFormat {
+            format_id: format_id.to_string(),
+            ext: ext.to_string(),
+            abr,
+            filesize,
+            ..AudioFormat::default()
+        };
+        let ranked = rank_audio_formats(vec![
+            audio("139", "m4a", 48, None),
+            audio("140-drc", "m4a", 129, None),
+            audio("140", "m4a", 129, Some(3_000_000)),
+            audio("251", "webm", 129, None),
+        ]);
+        let ids: Vec<&str> = ranked.iter().map(|format| format.format_id.as_str()).collect();
+        assert_eq!(ids, ["140", "251", "139"]);
+    }
+}
@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const MAX_SELECTOR_LEN: usize = 256;
+
+/// Selector keywords yt-dlp understands.
+const KEYWORDS: [&str; 16] = [
+    "best", "worst", "bestvideo", "worstvideo", "bestaudio", "worstaudio", "b", "w", "bv", "wv", "ba",
+    "wa", "bv*", "wv*", "ba*", "wa*",
+];
+
+/// Keywords that look like format IDs but fan out into downloading every
+/// format.
+const REJECTED_KEYWORDS: [&str; 2] = ["all", "mergeall"];
+
+const FILTER_FIELDS: [&str; 17] = [
+    "height",
+    "width",
+    "fps",
+    "filesize",
+    "filesize_approx",
+    "tbr",
+    "abr",
+    "vbr",
+    "asr",
+    "audio_channels",
+    "ext",
+    "vcodec",
+    "acodec",
+    "protocol",
+    "format_id",
+    "dynamic_range",
+    "language",
+];
+
+// Longest operators first so `<=` is not read as `<`
+const FILTER_OPERATORS: [&str; 9] = ["<=", ">=", "!=", "^=", "$=", "*=", "<", ">", "="];
+
+/// A yt-dlp `-f` value that has been checked against a restricted grammar:
+/// format IDs, the `best`/`worst` family of keywords, `+` merges, `/`
+/// fallbacks and `[field op value]` filters. Nothing that starts with `-` or
+/// contains other selector syntax gets through, so the value can never be
+/// read as an option or expand into something the caller did not ask for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FormatSelector(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSelector(String);
+
+impl fmt::Display for InvalidSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid format selector: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidSelector {}
+
+impl FormatSelector {
+    pub fn parse(raw: &str) -> Result<Self, InvalidSelector> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return Err(InvalidSelector("selector is empty".to_string()));
+        }
+        if raw.len() > MAX_SELECTOR_LEN {
+            return Err(InvalidSelector(format!("selector is longer than {} characters", MAX_SELECTOR_LEN)));
+        }
+
+        for alternative in raw.split('/') {
+            for single in alternative.split('+') {
+                validate_single(single)?;
+            }
+        }
+
+        Ok(Self(raw.to_string()))
+    }
+
+    /// Whether this names one format, possibly filtered, rather than a
+    /// `+` merge or a list of `/` fallbacks.
+    pub fn is_single_format(&self) -> bool {
+        !self.0.contains(['+', '/'])
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for FormatSelector {
+    type Error = InvalidSelector;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<FormatSelector> for String {
+    fn from(selector: FormatSelector) -> Self {
+        selector.0
+    }
+}
+
+impl fmt::Display for FormatSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// One format atom (an ID or keyword) followed by any number of filters.
+fn validate_single(single: &str) -> Result<(), InvalidSelector> {
+    let (atom, mut filters) = match single.find('[') {
+        Some(pos) => (&single[..pos], &single[pos..]),
+        None => (single, ""),
+    };
+
+    // A bare filter list applies to `best`, e.g. `[height<=720]`
+    if REJECTED_KEYWORDS.contains(&atom) {
+        return Err(InvalidSelector(format!("'{}' is not allowed", atom)));
+    }
+    if !atom.is_empty() && !KEYWORDS.contains(&atom) && !is_format_id(atom) {
+        return Err(InvalidSelector(format!("'{}' is not a format ID or keyword", atom)));
+    }
+    if atom.is_empty() && filters.is_empty() {
+        return Err(InvalidSelector("empty alternative".to_string()));
+    }
+
+    while !filters.is_empty() {
+        let end = filters
+            .find(']')
+            .ok_or_else(|| InvalidSelector(format!("unterminated filter in '{}'", single)))?;
+        if !filters.starts_with('[') {
+            return Err(InvalidSelector(format!("unexpected text in '{}'", single)));
+        }
+        validate_filter(&filters[1..end])?;
+        filters = &filters[end + 1..];
+    }
+
+    Ok(())
+}
+
+fn validate_filter(filter: &str) -> Result<(), InvalidSelector> {
+    let field_end = filter
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(filter.len());
+    let (field, rest) = filter.split_at(field_end);
+
+    if !FILTER_FIELDS.contains(&field) {
+        return Err(InvalidSelector(format!("unsupported filter field '{}'", field)));
+    }
+
+    let op = FILTER_OPERATORS
+        .iter()
+        .find(|op| rest.starts_with(*op))
+        .ok_or_else(|| InvalidSelector(format!("missing operator in filter '{}'", filter)))?;
+    let value = rest[op.len()..].strip_prefix('?').unwrap_or(&rest[op.len()..]);
+
+    let valid_value = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid_value {
+        return Err(InvalidSelector(format!("invalid value in filter '{}'", filter)));
+    }
+
+    Ok(())
+}
+
+/// yt-dlp format IDs are short tokens such as `137`, `hls-1080p` or
+/// `dash-video_eng`. They never start with a dash.
+fn is_format_id(id: &str) -> bool {
+    let mut chars = id.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_selectors_are_accepted() {
+        for raw in [
+            "137",
+            "hls-1080p",
+            "dash-video_eng",
+            "bv*",
+            "best",
+            "137+140",
+            "bestvideo[height<=720][ext=mp4]+bestaudio/best",
+            "[height<=480]",
+            "best[vcodec^=avc1]",
+            "ba[language=?en-US]",
+            " 18 ",
+        ] {
+            assert!(FormatSelector::parse(raw).is_ok(), "'{}' should be accepted", raw);
+        }
+        assert_eq!(FormatSelector::parse(" 18 ").unwrap().as_str(), "18");
+    }
+
+    #[test]
+    fn unsafe_selectors_are_rejected() {
+        for raw in [
+            "",
+            "-f",
+            "--exec=rm",
+            "137+-140",
+            "best/-x",
+            "all",
+            "mergeall",
+            "137,140",
+            "(bv+ba)",
+            "best[height<=720",
+            "best[height<=720]x",
+            "best[title=x]",
+            "best[height]",
+            "best[height<=]",
+            "best[ext=mp4 ]",
+            "best[ext=m$4]",
+            "137//140",
+            "137+",
+            "bv*-",
+        ] {
+            assert!(FormatSelector::parse(raw).is_err(), "'{}' should be rejected", raw);
+        }
+        assert!(FormatSelector::parse(&"1".repeat(MAX_SELECTOR_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn selectors_deserialize_through_the_grammar() {
+        assert!(serde_json::from_str::<FormatSelector>(r#""137""#).is_ok());
+        assert!(serde_json::from_str::<FormatSelector>(r#""--exec x""#).is_err());
+    }
+
+    #[test]
+    fn single_formats() {
+        assert!(FormatSelector::parse("137").unwrap().is_single_format());
+        assert!(FormatSelector::parse("bv[height<=720]").unwrap().is_single_format());
+        assert!(!FormatSelector::parse("137+140").unwrap().is_single_format());
+        assert!(!FormatSelector::parse("137/best").unwrap().is_single_format());
+    }
+}
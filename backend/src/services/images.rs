@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::services::clip::Timestamp;
+use crate::services::ytdlp_info::InfoDict;
+use crate::types::ThumbnailInfo;
+
+/// Most frames one request may extract.
+pub const MAX_FRAMES: usize = 100;
+
+/// Largest width or height an image may be scaled to.
+const MAX_DIMENSION: u32 = 4096;
+
+/// Still image formats thumbnails and frames can be delivered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn ext(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+
+    /// ffmpeg encoder options for a single image.
+    pub fn ffmpeg_args(self) -> Vec<String> {
+        let args: &[&str] = match self {
+            // 2 is near the top of mjpeg's 2-31 quality scale
+            ImageFormat::Jpeg => &["-c:v", "mjpeg", "-q:v", "2"],
+            ImageFormat::Png => &["-c:v", "png"],
+            ImageFormat::Webp => &["-c:v", "libwebp", "-quality", "85"],
+        };
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+}
+
+/// Target size for an image. With both sides set the image fits inside the
+/// box; with one, the other follows the aspect ratio.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageSize {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl ImageSize {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        for (name, value) in [("width", self.width), ("height", self.height)] {
+            if let Some(value) = value.filter(|value| !(16..=MAX_DIMENSION).contains(value)) {
+                return Err(ApiError::BadRequest(format!("{} {} is outside 16-{}", name, value, MAX_DIMENSION)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_original(&self) -> bool {
+        self.width.is_none() && self.height.is_none()
+    }
+
+    pub fn scale_filter(&self) -> Option<String> {
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => Some(format!(
+                "scale={}:{}:force_original_aspect_ratio=decrease:flags=lanczos",
+                width, height
+            )),
+            (Some(width), None) => Some(format!("scale={}:-1:flags=lanczos", width)),
+            (None, Some(height)) => Some(format!("scale=-1:{}:flags=lanczos", height)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Every thumbnail yt-dlp lists, largest first. Variants of unknown size
+/// follow, ordered by yt-dlp's own preference.
+pub fn list_thumbnails(info: &InfoDict) -> Vec<ThumbnailInfo> {
+    let mut thumbnails: Vec<ThumbnailInfo> = info
+        .thumbnails
+        .iter()
+        .enumerate()
+        .map(|(i, thumbnail)| ThumbnailInfo {
+            // yt-dlp numbers thumbnails without an ID by position
+            id: thumbnail.id.clone().unwrap_or_else(|| i.to_string()),
+            url: thumbnail.url.clone(),
+            width: thumbnail.width,
+            height: thumbnail.height,
+            resolution: thumbnail.resolution.clone().or_else(|| match (thumbnail.width, thumbnail.height) {
+                (Some(width), Some(height)) => Some(format!("{}x{}", width, height)),
+                _ => None,
+            }),
+            preference: thumbnail.preference,
+        })
+        .collect();
+    sort_thumbnails(&mut thumbnails);
+    thumbnails
+}
+
+/// The fixed set of thumbnails YouTube serves for every video, for when
+/// yt-dlp is unavailable. `maxresdefault` only exists for HD uploads.
+pub fn static_thumbnails(video_id: &str) -> Vec<ThumbnailInfo> {
+    let variants = [
+        ("maxresdefault", 1280, 720),
+        ("sddefault", 640, 480),
+        ("hqdefault", 480, 360),
+        ("mqdefault", 320, 180),
+        ("default", 120, 90),
+    ];
+    variants
+        .iter()
+        .map(|(name, width, height)| ThumbnailInfo {
+            id: name.to_string(),
+            url: format!("https://i.ytimg.com/vi/{}/{}.jpg", video_id, name),
+            width: Some(*width),
+            height: Some(*height),
+            resolution: Some(format!("{}x{}", width, height)),
+            preference: None,
+        })
+        .collect()
+}
+
+fn sort_thumbnails(thumbnails: &mut [ThumbnailInfo]) {
+    thumbnails.sort_by_key(|thumbnail| {
+        let area = match (thumbnail.width, thumbnail.height) {
+            (Some(width), Some(height)) => Some(width as u64 * height as u64),
+            _ => None,
+        };
+        (std::cmp::Reverse(area), std::cmp::Reverse(thumbnail.preference))
+    });
+}
+
+/// The thumbnail with `id`, or the largest one.
+pub fn pick_thumbnail<'a>(thumbnails: &'a [ThumbnailInfo], id: Option<&str>) -> Result<&'a ThumbnailInfo, ApiError> {
+    match id {
+        Some(id) => thumbnails
+            .iter()
+            .find(|thumbnail| thumbnail.id == id)
+            .ok_or_else(|| ApiError::NotFound(format!("No thumbnail with id '{}'", id))),
+        None => thumbnails
+            .first()
+            .ok_or_else(|| ApiError::NotFound("This video has no thumbnails".to_string())),
+    }
+}
+
+/// Which frames a `frames` download extracts: either explicit timestamps or
+/// `count` evenly spaced ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FrameOptions {
+    pub timestamps: Option<Vec<Timestamp>>,
+    pub count: Option<u32>,
+    #[serde(default)]
+    pub format: ImageFormat,
+    #[serde(flatten)]
+    pub size: ImageSize,
+}
+
+impl FrameOptions {
+    /// Frame positions in seconds, checked against the video's duration when
+    /// it is known. Evenly spaced frames sit in the middle of equal slices,
+    /// so the first and last are never black fades.
+    pub fn frame_times(&self, duration: Option<f64>) -> Result<Vec<f64>, ApiError> {
+        self.size.validate()?;
+
+        // Checked before any positions are generated, so a huge count cannot
+        // allocate its way past the limit
+        let frame_count = match (&self.timestamps, self.count) {
+            (Some(_), Some(_)) => {
+                return Err(ApiError::BadRequest("Use either timestamps or count, not both".to_string()));
+            }
+            (Some(timestamps), None) => timestamps.len(),
+            (None, Some(count)) => count as usize,
+            (None, None) => {
+                return Err(ApiError::BadRequest("frames needs timestamps or a count".to_string()));
+            }
+        };
+        if frame_count == 0 {
+            return Err(ApiError::BadRequest("frames needs at least one frame".to_string()));
+        }
+        if frame_count > MAX_FRAMES {
+            return Err(ApiError::BadRequest(format!("At most {} frames can be extracted at once", MAX_FRAMES)));
+        }
+
+        let times: Vec<f64> = match &self.timestamps {
+            Some(timestamps) => timestamps.iter().map(|timestamp| timestamp.seconds()).collect(),
+            None => {
+                let duration = duration
+                    .ok_or_else(|| ApiError::BadRequest("The video's duration is unknown; give timestamps instead".to_string()))?;
+                evenly_spaced(frame_count, duration)
+            }
+        };
+        if let Some(duration) = duration {
+            if let Some(time) = times.iter().find(|time| **time >= duration) {
+                return Err(ApiError::BadRequest(format!(
+                    "Frame at {:.3}s is past the end of the video ({:.3}s)",
+                    time, duration
+                )));
+            }
+        }
+        Ok(times)
+    }
+}
+
+/// `count` positions in the middle of equal slices of `duration`.
+pub fn evenly_spaced(count: usize, duration: f64) -> Vec<f64> {
+    (0..count).map(|i| duration * (i as f64 + 0.5) / count as f64).collect()
+}
+
+/// Width and height from a PNG's header, which always leads with IHDR.
+pub fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 24 || !data.starts_with(b"\x89PNG\r\n\x1a\n") || &data[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(data[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(data[20..24].try_into().ok()?);
+    Some((width, height))
+}
+
+/// `001_00-01-02.500.jpg`: frame number and position, safe in any archive.
+pub fn frame_filename(number: usize, seconds: f64, format: ImageFormat) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    let (hours, minutes, secs, millis) = (millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000);
+    format!(
+        "{:03}_{:02}-{:02}-{:02}.{:03}.{}",
+        number,
+        hours,
+        minutes,
+        secs,
+        millis,
+        format.ext()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(count: u32) -> FrameOptions {
+        FrameOptions {
+            count: Some(count),
+            ..Default::default()
+        }
+    }
+
+    fn timestamps(count: usize) -> FrameOptions {
+        FrameOptions {
+            timestamps: Some((0..count).map(|i| Timestamp::from_seconds(i as f64).unwrap()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn frame_count_is_limited_before_times_are_built() {
+        assert!(count(0).frame_times(Some(60.0)).is_err());
+        assert!(count(u32::MAX).frame_times(Some(60.0)).is_err());
+        assert!(count(MAX_FRAMES as u32 + 1).frame_times(Some(600.0)).is_err());
+        assert_eq!(count(MAX_FRAMES as u32).frame_times(Some(600.0)).unwrap().len(), MAX_FRAMES);
+    }
+
+    #[test]
+    fn timestamp_count_is_limited() {
+        assert!(timestamps(0).frame_times(Some(600.0)).is_err());
+        assert!(timestamps(MAX_FRAMES + 1).frame_times(Some(600.0)).is_err());
+        assert_eq!(timestamps(MAX_FRAMES).frame_times(Some(600.0)).unwrap().len(), MAX_FRAMES);
+    }
+
+    #[test]
+    fn evenly_spaced_frames_sit_mid_slice() {
+        assert_eq!(count(4).frame_times(Some(100.0)).unwrap(), vec![12.5, 37.5, 62.5, 87.5]);
+        assert!(count(4).frame_times(None).is_err());
+    }
+
+    /// The signature and IHDR chunk of a PNG, without the image data.
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data.extend([8, 2, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn png_dimensions_come_from_ihdr() {
+        assert_eq!(png_dimensions(&png_header(320, 180)), Some((320, 180)));
+        assert_eq!(png_dimensions(&png_header(1, 65_536)), Some((1, 65_536)));
+    }
+
+    #[test]
+    fn png_dimensions_reject_other_data() {
+        let header = png_header(320, 180);
+        assert_eq!(png_dimensions(&header[..23]), None);
+        assert_eq!(png_dimensions(&[]), None);
+        let mut not_png = header.clone();
+        not_png[1] = b'J';
+        assert_eq!(png_dimensions(&not_png), None);
+        let mut other_chunk = header;
+        other_chunk[12..16].copy_from_slice(b"tEXt");
+        assert_eq!(png_dimensions(&other_chunk), None);
+        assert_eq!(png_dimensions(b"\xff\xd8\xff\xe0 a JPEG, not a PNG at all"), None);
+    }
+
+    #[test]
+    fn frames_past_the_end_are_rejected() {
+        assert!(timestamps(3).frame_times(Some(2.0)).is_err());
+        assert!(timestamps(3).frame_times(Some(2.5)).is_ok());
+    }
+}
@@ -0,0 +1,521 @@
+use axum::body::Bytes;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+use crate::config::JobConfig;
+use crate::error::ApiError;
+use crate::services::key_store::{ApiKeyIdentity, Scope};
+use crate::services::loudness::LoudnessReport;
+use crate::services::segments::Segment;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+/// What a running job is doing. Percent and byte counts refer to the current
+/// stage; a merged download runs through `downloading` once per stream.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobProgress {
+    /// `extracting`, `downloading`, `postprocessing`, `normalizing`, ...
+    pub stage: String,
+    pub percent: Option<f64>,
+    pub downloaded_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    /// Bytes per second
+    pub speed: Option<f64>,
+    pub eta_secs: Option<u64>,
+}
+
+impl JobProgress {
+    pub fn stage(stage: &str) -> Self {
+        Self {
+            stage: stage.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// The file a completed job produced, collected from `/api/jobs/:id/file`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobResult {
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<LoudnessReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segments: Option<Vec<Segment>>,
+}
+
+/// A job as reported to clients.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobView {
+    pub id: String,
+    pub status: JobStatus,
+    /// Unix time in seconds
+    pub created_at: u64,
+    pub progress: Option<JobProgress>,
+    pub result: Option<JobResult>,
+    pub error: Option<String>,
+}
+
+struct Job {
+    view: JobView,
+    /// Name of the API key that created the job
+    owner: Option<String>,
+    data: Option<Bytes>,
+    finished: Option<Instant>,
+    cancel: watch::Sender<bool>,
+    /// Set while the job records a live stream, which can be stopped early
+    stoppable: bool,
+    stop: watch::Sender<bool>,
+}
+
+impl Job {
+    fn is_expired(&self, ttl: Duration) -> bool {
+        self.finished.is_some_and(|finished| finished.elapsed() >= ttl)
+    }
+}
+
+/// Background jobs and their results, kept in memory. At most
+/// `max_running` jobs work at once; finished jobs are dropped after the
+/// configured TTL, or earlier when their files take up too much memory.
+pub struct JobStore {
+    ttl: Duration,
+    max_jobs: usize,
+    max_retained_bytes: u64,
+    slots: Arc<Semaphore>,
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl JobStore {
+    pub fn new(config: &JobConfig) -> Self {
+        Self {
+            ttl: Duration::from_secs(config.result_ttl_secs),
+            max_jobs: config.max_jobs,
+            max_retained_bytes: config.max_retained_bytes,
+            slots: Arc::new(Semaphore::new(config.max_running.max(1))),
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register a queued job owned by `caller`.
+    pub fn create(self: &Arc<Self>, caller: Option<&ApiKeyIdentity>) -> Result<JobHandle, ApiError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| !job.is_expired(self.ttl));
+        if jobs.len() >= self.max_jobs {
+            return Err(ApiError::Unavailable("Too many jobs in progress; try again later".to_string()));
+        }
+
+        let id = Uuid::new_v4().to_string();
+        let (cancel, cancel_rx) = watch::channel(false);
+        let (stop, stop_rx) = watch::channel(false);
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        jobs.insert(
+            id.clone(),
+            Job {
+                view: JobView {
+                    id: id.clone(),
+                    status: JobStatus::Queued,
+                    created_at,
+                    progress: None,
+                    result: None,
+                    error: None,
+                },
+                owner: caller.map(|identity| identity.name.clone()),
+                data: None,
+                finished: None,
+                cancel,
+                stoppable: false,
+                stop,
+            },
+        );
+
+        Ok(JobHandle {
+            store: self.clone(),
+            id,
+            cancel: cancel_rx,
+            stop: stop_rx,
+        })
+    }
+
+    pub fn get(&self, id: &str, caller: Option<&ApiKeyIdentity>) -> Result<JobView, ApiError> {
+        self.with_job(id, caller, |job| Ok(job.view.clone()))
+    }
+
+    /// The finished job's file and what it is. The bytes are shared, not
+    /// copied.
+    pub fn file(&self, id: &str, caller: Option<&ApiKeyIdentity>) -> Result<(Bytes, JobResult), ApiError> {
+        self.with_job(id, caller, |job| match (&job.data, &job.view.result) {
+            (Some(data), Some(result)) => Ok((data.clone(), result.clone())),
+            _ => Err(ApiError::Conflict(format!("Job {} has no file; its status is {:?}", id, job.view.status))),
+        })
+    }
+
+    /// Cancel a queued or running job, or discard a finished one along with
+    /// its file.
+    pub fn remove(&self, id: &str, caller: Option<&ApiKeyIdentity>) -> Result<(), ApiError> {
+        let finished = self.with_job(id, caller, |job| {
+            if !job.view.status.is_finished() {
+                job.cancel.send_replace(true);
+            }
+            Ok(job.view.status.is_finished())
+        })?;
+        if finished {
+            self.jobs.lock().unwrap().remove(id);
+        }
+        Ok(())
+    }
+
+    /// Ask a recording job to finish early; it then completes with what it
+    /// has recorded so far.
+    pub fn stop(&self, id: &str, caller: Option<&ApiKeyIdentity>) -> Result<JobView, ApiError> {
+        self.with_job(id, caller, |job| {
+            if job.view.status.is_finished() || !job.stoppable {
+                return Err(ApiError::Conflict(format!("Job {} is not recording", id)));
+            }
+            job.stop.send_replace(true);
+            Ok(job.view.clone())
+        })
+    }
+
+    /// Jobs are only visible to the key that created them, and to admins.
+    fn with_job<T>(
+        &self,
+        id: &str,
+        caller: Option<&ApiKeyIdentity>,
+        f: impl FnOnce(&mut Job) -> Result<T, ApiError>,
+    ) -> Result<T, ApiError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let visible = |job: &Job| match &job.owner {
+            None => true,
+            Some(owner) => caller.is_some_and(|identity| identity.name == *owner || identity.grants(Scope::Admin)),
+        };
+        match jobs.get_mut(id) {
+            Some(job) if visible(job) && !job.is_expired(self.ttl) => f(job),
+            _ => Err(ApiError::NotFound(format!("Job {} not found", id))),
+        }
+    }
+
+    /// Keep a completed job's file. The oldest finished jobs are dropped
+    /// until all files fit in `max_retained_bytes`; a file that could never
+    /// fit is refused.
+    fn store_result(&self, id: &str, data: Bytes, result: JobResult) -> Result<(), String> {
+        let size = data.len() as u64;
+        if size > self.max_retained_bytes {
+            return Err(format!(
+                "The file is {} bytes; jobs can hold at most {} bytes",
+                size, self.max_retained_bytes
+            ));
+        }
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| !job.is_expired(self.ttl));
+        let mut retained: u64 = jobs.values().filter_map(|job| job.data.as_ref()).map(|data| data.len() as u64).sum();
+        while retained + size > self.max_retained_bytes {
+            let oldest = jobs
+                .iter()
+                .filter(|(_, job)| job.data.is_some())
+                .min_by_key(|(_, job)| job.finished)
+                .map(|(id, _)| id.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            if let Some(data) = jobs.remove(&oldest).and_then(|job| job.data) {
+                println!("Dropping the result of job {} to make room", oldest);
+                retained -= data.len() as u64;
+            }
+        }
+
+        if let Some(job) = jobs.get_mut(id).filter(|job| !job.view.status.is_finished()) {
+            job.view.status = JobStatus::Completed;
+            job.view.progress = None;
+            job.view.result = Some(result);
+            job.data = Some(data);
+            job.finished = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            // A cancelled job keeps its final state
+            if !job.view.status.is_finished() {
+                f(job);
+            }
+        }
+    }
+}
+
+/// The worker's side of a job.
+pub struct JobHandle {
+    store: Arc<JobStore>,
+    id: String,
+    cancel: watch::Receiver<bool>,
+    stop: watch::Receiver<bool>,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn view(&self) -> JobView {
+        self.store.jobs.lock().unwrap().get(&self.id).map(|job| job.view.clone()).expect("job exists")
+    }
+
+    /// Wait for a free slot and mark the job running. Returns `None` if the
+    /// job was cancelled while queued.
+    pub async fn start(&self) -> Option<OwnedSemaphorePermit> {
+        // Cancellation wins over a slot that happens to be free
+        let permit = tokio::select! {
+            biased;
+            _ = self.cancelled() => return None,
+            permit = self.store.slots.clone().acquire_owned() => permit.ok()?,
+        };
+        self.store.update(&self.id, |job| job.view.status = JobStatus::Running);
+        Some(permit)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancel.borrow()
+    }
+
+    /// Resolves once a client cancels the job.
+    pub async fn cancelled(&self) {
+        let mut cancel = self.cancel.clone();
+        let _ = cancel.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Let clients stop the job early from now on.
+    pub fn allow_stop(&self) {
+        self.store.update(&self.id, |job| job.stoppable = true);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.stop.borrow()
+    }
+
+    /// Resolves once a client stops the job.
+    pub async fn stopped(&self) {
+        let mut stop = self.stop.clone();
+        let _ = stop.wait_for(|stopped| *stopped).await;
+    }
+
+    pub fn report(&self, progress: JobProgress) {
+        self.store.update(&self.id, |job| job.view.progress = Some(progress));
+    }
+
+    pub fn complete(&self, data: Vec<u8>, result: JobResult) {
+        // Cancelled while a step that cannot be interrupted was running
+        if self.is_cancelled() {
+            return self.fail(String::new());
+        }
+        if let Err(error) = self.store.store_result(&self.id, Bytes::from(data), result) {
+            self.fail(error);
+        }
+    }
+
+    pub fn fail(&self, error: String) {
+        let status = if self.is_cancelled() { JobStatus::Cancelled } else { JobStatus::Failed };
+        self.store.update(&self.id, |job| {
+            job.view.status = status;
+            job.view.progress = None;
+            job.view.error = (status == JobStatus::Failed).then_some(error);
+            job.finished = Some(Instant::now());
+        });
+    }
+}
+
+/// Report progress when running as a job.
+pub fn report(job: Option<&JobHandle>, progress: JobProgress) {
+    if let Some(job) = job {
+        job.report(progress);
+    }
+}
+
+pub fn report_stage(job: Option<&JobHandle>, stage: &str) {
+    report(job, JobProgress::stage(stage));
+}
+
+/// Resolves once `job` is stopped; never for work outside a job.
+pub async fn wait_stopped(job: Option<&JobHandle>) {
+    match job {
+        Some(job) => job.stopped().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves once `job` is cancelled; never for work outside a job.
+pub async fn wait_cancelled(job: Option<&JobHandle>) {
+    match job {
+        Some(job) => job.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(result_ttl_secs: u64, max_retained_bytes: u64) -> Arc<JobStore> {
+        Arc::new(JobStore::new(&JobConfig {
+            max_running: 1,
+            max_jobs: 3,
+            result_ttl_secs,
+            max_retained_bytes,
+        }))
+    }
+
+    fn identity(name: &str, scope: Scope) -> ApiKeyIdentity {
+        ApiKeyIdentity {
+            name: name.to_string(),
+            scopes: vec![scope],
+        }
+    }
+
+    fn result(size: usize) -> JobResult {
+        JobResult {
+            filename: "video.mp4".to_string(),
+            content_type: "video/mp4".to_string(),
+            size: size as u64,
+            loudness: None,
+            segments: None,
+        }
+    }
+
+    #[test]
+    fn jobs_are_visible_to_their_owner_and_admins() {
+        let store = store(60, 1000);
+        let alice = identity("alice", Scope::Download);
+        let job = store.create(Some(&alice)).unwrap();
+
+        assert!(store.get(job.id(), Some(&alice)).is_ok());
+        assert!(store.get(job.id(), Some(&identity("admin", Scope::Admin))).is_ok());
+        assert!(matches!(store.get(job.id(), Some(&identity("bob", Scope::Download))), Err(ApiError::NotFound(_))));
+        assert!(matches!(store.get(job.id(), None), Err(ApiError::NotFound(_))));
+        assert!(store.remove(job.id(), Some(&identity("bob", Scope::Download))).is_err());
+
+        // Jobs created without auth are open to everyone
+        let open = store.create(None).unwrap();
+        assert!(store.get(open.id(), Some(&identity("bob", Scope::Inspect))).is_ok());
+    }
+
+    #[test]
+    fn store_is_bounded() {
+        let store = store(60, 1000);
+        let _jobs: Vec<JobHandle> = (0..3).map(|_| store.create(None).unwrap()).collect();
+        assert!(matches!(store.create(None), Err(ApiError::Unavailable(_))));
+    }
+
+    #[test]
+    fn completed_job_serves_its_file() {
+        let store = store(60, 1000);
+        let job = store.create(None).unwrap();
+        assert!(matches!(store.file(job.id(), None), Err(ApiError::Conflict(_))));
+
+        job.complete(vec![7; 10], result(10));
+        let (data, result) = store.file(job.id(), None).unwrap();
+        assert_eq!(data.as_ref(), [7; 10]);
+        assert_eq!(result.size, 10);
+        assert_eq!(store.get(job.id(), None).unwrap().status, JobStatus::Completed);
+
+        // A finished job stays finished
+        job.fail("late".to_string());
+        assert_eq!(store.get(job.id(), None).unwrap().status, JobStatus::Completed);
+
+        store.remove(job.id(), None).unwrap();
+        assert!(matches!(store.get(job.id(), None), Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn finished_jobs_expire() {
+        let store = store(0, 1000);
+        let job = store.create(None).unwrap();
+        assert!(store.get(job.id(), None).is_ok());
+        job.fail("broken".to_string());
+        assert!(matches!(store.get(job.id(), None), Err(ApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_queued_job_stops_it_starting() {
+        let store = store(60, 1000);
+        let job = store.create(None).unwrap();
+        store.remove(job.id(), None).unwrap();
+        assert!(job.is_cancelled());
+        assert!(job.start().await.is_none());
+
+        job.complete(vec![1], result(1));
+        let view = store.get(job.id(), None).unwrap();
+        assert_eq!(view.status, JobStatus::Cancelled);
+        assert_eq!(view.error, None);
+        // Now finished, so removing it discards it
+        store.remove(job.id(), None).unwrap();
+        assert!(store.get(job.id(), None).is_err());
+    }
+
+    #[tokio::test]
+    async fn jobs_run_once_started() {
+        let store = store(60, 1000);
+        let job = store.create(None).unwrap();
+        let _permit = job.start().await.unwrap();
+        assert_eq!(store.get(job.id(), None).unwrap().status, JobStatus::Running);
+        job.fail("no formats".to_string());
+        let view = store.get(job.id(), None).unwrap();
+        assert_eq!((view.status, view.error.as_deref()), (JobStatus::Failed, Some("no formats")));
+    }
+
+    #[test]
+    fn only_recordings_can_be_stopped() {
+        let store = store(60, 1000);
+        let job = store.create(None).unwrap();
+        assert!(matches!(store.stop(job.id(), None), Err(ApiError::Conflict(_))));
+        job.allow_stop();
+        store.stop(job.id(), None).unwrap();
+        assert!(job.is_stopped());
+        assert!(!job.is_cancelled());
+    }
+
+    #[test]
+    fn oldest_results_make_room() {
+        let store = store(60, 100);
+        let first = store.create(None).unwrap();
+        first.complete(vec![0; 60], result(60));
+        let second = store.create(None).unwrap();
+        second.complete(vec![0; 30], result(30));
+        assert!(store.file(first.id(), None).is_ok());
+
+        let third = store.create(None).unwrap();
+        third.complete(vec![0; 50], result(50));
+        assert!(matches!(store.get(first.id(), None), Err(ApiError::NotFound(_))));
+        assert!(store.file(second.id(), None).is_ok());
+        assert!(store.file(third.id(), None).is_ok());
+    }
+
+    #[test]
+    fn results_larger_than_the_limit_fail() {
+        let store = store(60, 100);
+        let kept = store.create(None).unwrap();
+        kept.complete(vec![0; 10], result(10));
+        let job = store.create(None).unwrap();
+        job.complete(vec![0; 101], result(101));
+        assert_eq!(store.get(job.id(), None).unwrap().status, JobStatus::Failed);
+        assert!(store.file(kept.id(), None).is_ok());
+    }
+}
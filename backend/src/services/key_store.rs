@@ -0,0 +1,343 @@
+use crate::config::AppConfig;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// What an API key is allowed to do. Scopes are hierarchical: `Admin`
+/// implies `Download`, which implies `Inspect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Inspect,
+    Download,
+    Admin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyEntry {
+    pub name: String,
+    pub key: String,
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub revoked: bool,
+}
+
+impl ApiKeyEntry {
+    pub fn admin(name: &str, key: String) -> Self {
+        Self {
+            name: name.to_string(),
+            key,
+            scopes: vec![Scope::Admin],
+            revoked: false,
+        }
+    }
+}
+
+/// Why an admin change to the key store was refused.
+#[derive(Debug, Error)]
+pub enum KeyStoreError {
+    #[error("No API key named '{0}'")]
+    NotFound(String),
+    #[error("An API key named '{0}' already exists")]
+    Conflict(String),
+    #[error("{0}")]
+    Invalid(String),
+    /// The keys file could not be written.
+    #[error(transparent)]
+    Io(#[from] anyhow::Error),
+}
+
+/// The identity attached to a request once its API key has been verified.
+#[derive(Debug, Clone)]
+pub struct ApiKeyIdentity {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiKeyIdentity {
+    pub fn grants(&self, required: Scope) -> bool {
+        self.scopes.iter().any(|scope| *scope >= required)
+    }
+}
+
+/// Summary of a key as shown by the admin endpoints; never includes the secret.
+#[derive(Debug, Serialize)]
+pub struct ApiKeySummary {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub revoked: bool,
+    pub managed: bool,
+}
+
+/// In-memory view of every known API key. Keys come from the config file
+/// (read-only) and from the managed keys file, which the admin endpoints
+/// write back to so revocations survive a restart.
+pub struct KeyStore {
+    enabled: bool,
+    config_path: Option<PathBuf>,
+    keys_file: Option<PathBuf>,
+    static_keys: Vec<ApiKeyEntry>,
+    inline_keys: Vec<ApiKeyEntry>,
+    state: RwLock<KeyStoreState>,
+}
+
+#[derive(Default)]
+struct KeyStoreState {
+    configured: Vec<ApiKeyEntry>,
+    managed: Vec<ApiKeyEntry>,
+}
+
+impl KeyStore {
+    pub fn new(config: &AppConfig) -> Result<Self> {
+        let static_keys = config
+            .auth
+            .bootstrap_key
+            .iter()
+            .map(|key| ApiKeyEntry::admin("env-admin", key.clone()))
+            .collect();
+
+        let store = Self {
+            enabled: config.auth.enabled,
+            config_path: config.source.clone(),
+            keys_file: config.auth.keys_file.clone(),
+            static_keys,
+            inline_keys: config.auth.keys.clone(),
+            state: RwLock::new(KeyStoreState::default()),
+        };
+        store.reload()?;
+        Ok(store)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Re-read the config file and the managed keys file.
+    pub fn reload(&self) -> Result<usize> {
+        let mut configured = self.static_keys.clone();
+        configured.extend(match &self.config_path {
+            Some(path) => AppConfig::from_file(path)?.auth.keys,
+            None => self.inline_keys.clone(),
+        });
+
+        let managed = match &self.keys_file {
+            Some(path) if path.exists() => {
+                let raw = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read keys file {}", path.display()))?;
+                serde_json::from_str(&raw)
+                    .with_context(|| format!("Failed to parse keys file {}", path.display()))?
+            }
+            _ => Vec::new(),
+        };
+
+        // An empty key would let anyone in who sends an empty header
+        if let Some(entry) = configured.iter().chain(managed.iter()).find(|entry| entry.key.trim().is_empty()) {
+            return Err(anyhow!("API key '{}' has an empty key", entry.name));
+        }
+
+        let mut state = self.state.write().unwrap();
+        state.configured = configured;
+        state.managed = managed;
+        Ok(state.configured.len() + state.managed.len())
+    }
+
+    /// Look up a key, ignoring revoked ones. Every key is compared in
+    /// constant time, so response timing says nothing about how much of a
+    /// guess was right.
+    pub fn authenticate(&self, key: &str) -> Option<ApiKeyIdentity> {
+        if key.is_empty() {
+            return None;
+        }
+
+        let state = self.state.read().unwrap();
+        state
+            .configured
+            .iter()
+            .chain(state.managed.iter())
+            .fold(None, |found, entry| {
+                let matches = bool::from(entry.key.as_bytes().ct_eq(key.as_bytes()));
+                found.or((matches && !entry.revoked).then_some(entry))
+            })
+            .map(|entry| ApiKeyIdentity {
+                name: entry.name.clone(),
+                scopes: entry.scopes.clone(),
+            })
+    }
+
+    pub fn list(&self) -> Vec<ApiKeySummary> {
+        let state = self.state.read().unwrap();
+        let summarize = |entry: &ApiKeyEntry, managed: bool| ApiKeySummary {
+            name: entry.name.clone(),
+            scopes: entry.scopes.clone(),
+            revoked: entry.revoked,
+            managed,
+        };
+
+        state
+            .configured
+            .iter()
+            .map(|entry| summarize(entry, false))
+            .chain(state.managed.iter().map(|entry| summarize(entry, true)))
+            .collect()
+    }
+
+    /// Create a new managed key and return it, including the generated secret.
+    pub fn create(&self, name: &str, scopes: Vec<Scope>) -> Result<ApiKeyEntry, KeyStoreError> {
+        if name.trim().is_empty() {
+            return Err(KeyStoreError::Invalid("An API key needs a name".to_string()));
+        }
+        if scopes.is_empty() {
+            return Err(KeyStoreError::Invalid("At least one scope is required".to_string()));
+        }
+
+        let mut state = self.state.write().unwrap();
+        let taken = state
+            .configured
+            .iter()
+            .chain(state.managed.iter())
+            .any(|entry| entry.name == name);
+        if taken {
+            return Err(KeyStoreError::Conflict(name.to_string()));
+        }
+
+        let entry = ApiKeyEntry {
+            name: name.to_string(),
+            key: format!("vs_{}", Uuid::new_v4().simple()),
+            scopes,
+            revoked: false,
+        };
+        // Only keep the key once it is on disk, so a failed write leaves no
+        // key behind that would vanish on the next restart
+        let mut managed = state.managed.clone();
+        managed.push(entry.clone());
+        self.persist(&managed)?;
+        state.managed = managed;
+        Ok(entry)
+    }
+
+    /// Revoke a key by name. Keys from the config file are revoked in memory
+    /// only; edit the file and reload to make that permanent.
+    pub fn revoke(&self, name: &str) -> Result<(), KeyStoreError> {
+        let mut state = self.state.write().unwrap();
+
+        if let Some(index) = state.managed.iter().position(|entry| entry.name == name) {
+            let mut managed = state.managed.clone();
+            managed[index].revoked = true;
+            self.persist(&managed)?;
+            state.managed = managed;
+            return Ok(());
+        }
+
+        match state.configured.iter_mut().find(|entry| entry.name == name) {
+            Some(entry) => {
+                entry.revoked = true;
+                Ok(())
+            }
+            None => Err(KeyStoreError::NotFound(name.to_string())),
+        }
+    }
+
+    fn persist(&self, managed: &[ApiKeyEntry]) -> Result<()> {
+        let Some(path) = &self.keys_file else {
+            return Ok(());
+        };
+
+        let json = serde_json::to_string_pretty(managed)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write keys file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(keys: Vec<ApiKeyEntry>) -> Result<KeyStore> {
+        let mut config = AppConfig::default();
+        config.auth.enabled = true;
+        config.auth.keys = keys;
+        KeyStore::new(&config)
+    }
+
+    fn entry(name: &str, key: &str, scopes: Vec<Scope>) -> ApiKeyEntry {
+        ApiKeyEntry {
+            name: name.to_string(),
+            key: key.to_string(),
+            scopes,
+            revoked: false,
+        }
+    }
+
+    #[test]
+    fn keys_authenticate_only_on_an_exact_match() {
+        let store = store(vec![entry("reader", "vs_reader", vec![Scope::Inspect]), entry("admin", "vs_admin", vec![Scope::Admin])]).unwrap();
+        let identity = store.authenticate("vs_admin").unwrap();
+        assert_eq!(identity.name, "admin");
+        assert!(identity.grants(Scope::Download));
+        assert!(!store.authenticate("vs_reader").unwrap().grants(Scope::Download));
+        for guess in ["", "vs_", "vs_admi", "vs_adminx", "VS_ADMIN"] {
+            assert!(store.authenticate(guess).is_none(), "'{}' should not authenticate", guess);
+        }
+    }
+
+    #[test]
+    fn revoked_keys_do_not_authenticate() {
+        let store = store(vec![entry("reader", "vs_reader", vec![Scope::Inspect])]).unwrap();
+        store.revoke("reader").unwrap();
+        assert!(store.authenticate("vs_reader").is_none());
+        assert!(matches!(store.revoke("missing"), Err(KeyStoreError::NotFound(_))));
+    }
+
+    #[test]
+    fn empty_keys_are_refused() {
+        assert!(store(vec![entry("blank", "", vec![Scope::Admin])]).is_err());
+        assert!(store(vec![entry("spaces", "  ", vec![Scope::Admin])]).is_err());
+
+        let mut config = AppConfig::default();
+        config.auth.bootstrap_key = Some(String::new());
+        assert!(KeyStore::new(&config).is_err());
+    }
+
+    #[test]
+    fn created_keys_need_a_free_name_and_a_scope() {
+        let store = store(vec![entry("reader", "vs_reader", vec![Scope::Inspect])]).unwrap();
+        let created = store.create("uploader", vec![Scope::Download]).unwrap();
+        assert!(created.key.starts_with("vs_") && created.key.len() > 3);
+        assert_eq!(store.authenticate(&created.key).unwrap().name, "uploader");
+        assert!(matches!(store.create("reader", vec![Scope::Inspect]), Err(KeyStoreError::Conflict(_))));
+        assert!(matches!(store.create(" ", vec![Scope::Inspect]), Err(KeyStoreError::Invalid(_))));
+        assert!(matches!(store.create("nobody", Vec::new()), Err(KeyStoreError::Invalid(_))));
+    }
+
+    #[test]
+    fn failed_writes_leave_the_keys_unchanged() {
+        let mut config = AppConfig::default();
+        config.auth.enabled = true;
+        config.auth.keys_file = Some(std::env::temp_dir().join(format!("missing-{}", Uuid::new_v4())).join("keys.json"));
+        let store = KeyStore::new(&config).unwrap();
+
+        assert!(matches!(store.create("uploader", vec![Scope::Download]), Err(KeyStoreError::Io(_))));
+        assert!(store.list().is_empty());
+        assert!(matches!(store.create("uploader", vec![Scope::Download]), Err(KeyStoreError::Io(_))));
+    }
+
+    #[test]
+    fn managed_keys_are_written_before_they_change() {
+        let dir = std::env::temp_dir().join(format!("keys-{}", Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let mut config = AppConfig::default();
+        config.auth.enabled = true;
+        config.auth.keys_file = Some(dir.join("keys.json"));
+        let store = KeyStore::new(&config).unwrap();
+
+        let created = store.create("uploader", vec![Scope::Download]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(store.revoke("uploader"), Err(KeyStoreError::Io(_))));
+        assert!(store.authenticate(&created.key).is_some());
+        assert!(!store.list()[0].revoked);
+    }
+}
@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::AppConfig;
+use crate::error::ApiError;
+use crate::services::clip::Timestamp;
+use crate::services::ytdlp_info::{Format, InfoDict};
+
+/// yt-dlp `--match-filter` that skips live and upcoming streams, for
+/// downloads that never looked at the metadata. A missing `live_status`
+/// passes, as the `?` says.
+pub const NOT_LIVE_FILTER: &str = "!is_live & live_status!=?is_upcoming";
+
+/// Where a video stands as a live stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveStatus {
+    #[default]
+    NotLive,
+    IsLive,
+    /// A scheduled stream or premiere that has not started
+    IsUpcoming,
+    /// A finished stream, available as a regular video
+    WasLive,
+    /// A stream that just ended and is still being processed; only its most
+    /// recent part may be available yet
+    PostLive,
+}
+
+impl LiveStatus {
+    /// Read from `live_status`, falling back to the older `is_live` and
+    /// `was_live` flags for extractors that do not set it.
+    pub fn of(info: &InfoDict) -> Self {
+        match info.live_status.as_deref() {
+            Some("is_live") => LiveStatus::IsLive,
+            Some("is_upcoming") => LiveStatus::IsUpcoming,
+            Some("was_live") => LiveStatus::WasLive,
+            Some("post_live") => LiveStatus::PostLive,
+            Some(_) => LiveStatus::NotLive,
+            None if info.is_live == Some(true) => LiveStatus::IsLive,
+            None if info.was_live == Some(true) => LiveStatus::WasLive,
+            None => LiveStatus::NotLive,
+        }
+    }
+
+    /// Live or about to be; downloading either means recording.
+    pub fn is_recordable(self) -> bool {
+        matches!(self, LiveStatus::IsLive | LiveStatus::IsUpcoming)
+    }
+}
+
+/// How a request wants a live stream recorded.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LiveOptions {
+    /// Record from the start of the stream instead of from now
+    #[serde(rename = "fromStart", default)]
+    pub from_start: bool,
+    /// Stop recording after this long; the configured limit when left out
+    #[serde(rename = "maxDuration")]
+    pub max_duration: Option<Timestamp>,
+    /// Wait for an upcoming stream or premiere to start
+    #[serde(rename = "waitForStart", default)]
+    pub wait_for_start: bool,
+}
+
+/// Rough bitrate of a recording in kbit/s, from the best formats the stream
+/// offers: the video and audio together, or the audio alone.
+pub fn estimated_bitrate(info: &InfoDict, audio_only: bool) -> Option<f64> {
+    let best = |keep: &dyn Fn(&Format) -> bool| {
+        info.formats
+            .iter()
+            .filter(|format| keep(format))
+            .filter_map(|format| format.tbr.or(format.abr))
+            .reduce(f64::max)
+    };
+    let audio = best(&|format| format.has_audio() && !format.has_video());
+    if audio_only {
+        // Live streams often only come with audio muxed into the video
+        return audio.or_else(|| best(&|format| format.has_audio()));
+    }
+    best(&Format::has_video).map(|video| video + audio.unwrap_or_default())
+}
+
+/// A checked live recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recording {
+    pub from_start: bool,
+    /// Longest recording, in seconds
+    pub max_duration: f64,
+    /// Seconds between checks while waiting for the stream to start
+    pub wait_retry: Option<u64>,
+}
+
+impl Recording {
+    /// `scheduled_start` and `now` are Unix times in seconds. A finished
+    /// recording is held by its job, so it may last no longer than
+    /// `jobs.max_retained_bytes` takes at `bitrate_kbps`: a longer
+    /// `maxDuration` is refused, and the default is shortened to fit.
+    pub fn resolve(
+        options: &LiveOptions,
+        status: LiveStatus,
+        scheduled_start: Option<i64>,
+        now: i64,
+        bitrate_kbps: Option<f64>,
+        config: &AppConfig,
+    ) -> Result<Self, ApiError> {
+        let bitrate_kbps = bitrate_kbps.filter(|kbps| *kbps > 0.0).unwrap_or(config.live.assumed_bitrate_kbps);
+        let fits = (config.jobs.max_retained_bytes as f64 * 8.0 / (bitrate_kbps * 1000.0)).floor();
+        if fits < 1.0 {
+            return Err(ApiError::BadRequest(format!(
+                "At about {:.0} kbit/s, no recording fits in the {} byte job limit",
+                bitrate_kbps, config.jobs.max_retained_bytes
+            )));
+        }
+
+        let max_duration = match options.max_duration.map(Timestamp::seconds) {
+            Some(max_duration) if !(1.0..=config.live.max_duration_secs).contains(&max_duration) => {
+                return Err(ApiError::BadRequest(format!(
+                    "maxDuration {:.0}s is outside 1-{:.0}s",
+                    max_duration, config.live.max_duration_secs
+                )));
+            }
+            Some(max_duration) if max_duration > fits => {
+                return Err(ApiError::BadRequest(format!(
+                    "At about {:.0} kbit/s, a {:.0}s recording would not fit in the {} byte job limit; the most is {:.0}s",
+                    bitrate_kbps, max_duration, config.jobs.max_retained_bytes, fits
+                )));
+            }
+            Some(max_duration) => max_duration,
+            None => config.live.max_duration_secs.min(fits),
+        };
+
+        let wait_retry = if status == LiveStatus::IsUpcoming {
+            if !options.wait_for_start {
+                return Err(ApiError::BadRequest(match scheduled_start {
+                    Some(start) => format!(
+                        "This stream starts in {}s; set live.waitForStart to wait for it",
+                        (start - now).max(0)
+                    ),
+                    None => "This stream has not started; set live.waitForStart to wait for it".to_string(),
+                }));
+            }
+            let max_wait = config.live.max_wait_secs;
+            if let Some(wait) = scheduled_start.map(|start| start - now).filter(|wait| *wait > max_wait as i64) {
+                return Err(ApiError::BadRequest(format!(
+                    "This stream starts in {}s; recordings can wait at most {}s",
+                    wait, max_wait
+                )));
+            }
+            Some(config.live.wait_retry_secs)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            from_start: options.from_start,
+            max_duration,
+            wait_retry,
+        })
+    }
+
+    /// yt-dlp options for the recording. From the start, yt-dlp fetches the
+    /// stream's fragments itself and the length is capped as a section. From
+    /// now, ffmpeg records into MPEG-TS, which stays playable when stopped
+    /// early, and stops itself at the limit.
+    pub fn ytdlp_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.from_start {
+            args.push("--live-from-start".to_string());
+            args.push("--download-sections".to_string());
+            args.push(format!("*0-{:.0}", self.max_duration));
+        } else {
+            args.push("--hls-use-mpegts".to_string());
+            args.push("--downloader-args".to_string());
+            args.push(format!("ffmpeg_o:-t {:.0}", self.max_duration));
+        }
+        if let Some(retry) = self.wait_retry {
+            args.push("--wait-for-video".to_string());
+            args.push(retry.to_string());
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn options(max_duration: Option<f64>, wait_for_start: bool) -> LiveOptions {
+        LiveOptions {
+            from_start: false,
+            max_duration: max_duration.map(|seconds| Timestamp::from_seconds(seconds).unwrap()),
+            wait_for_start,
+        }
+    }
+
+    fn resolve(options: &LiveOptions, status: LiveStatus, scheduled_start: Option<i64>, bitrate_kbps: Option<f64>) -> Result<Recording, ApiError> {
+        Recording::resolve(options, status, scheduled_start, NOW, bitrate_kbps, &AppConfig::default())
+    }
+
+    fn info(json: &str) -> InfoDict {
+        InfoDict::parse(json).unwrap().info
+    }
+
+    #[test]
+    fn status_falls_back_to_the_old_flags() {
+        assert_eq!(LiveStatus::of(&info(r#"{"id": "a", "live_status": "is_upcoming"}"#)), LiveStatus::IsUpcoming);
+        assert_eq!(LiveStatus::of(&info(r#"{"id": "a", "live_status": "post_live"}"#)), LiveStatus::PostLive);
+        assert_eq!(LiveStatus::of(&info(r#"{"id": "a", "live_status": "not_live", "is_live": true}"#)), LiveStatus::NotLive);
+        assert_eq!(LiveStatus::of(&info(r#"{"id": "a", "is_live": true}"#)), LiveStatus::IsLive);
+        assert_eq!(LiveStatus::of(&info(r#"{"id": "a", "was_live": true}"#)), LiveStatus::WasLive);
+        assert_eq!(LiveStatus::of(&info(r#"{"id": "a"}"#)), LiveStatus::NotLive);
+        assert!(LiveStatus::IsUpcoming.is_recordable());
+        assert!(!LiveStatus::PostLive.is_recordable());
+    }
+
+    #[test]
+    fn max_duration_defaults_to_the_configured_limit() {
+        // 1 Mbit/s fills 2 GB in 16000s, longer than the 4 hour limit
+        let recording = resolve(&options(None, false), LiveStatus::IsLive, None, Some(1000.0)).unwrap();
+        assert_eq!(recording.max_duration, 14400.0);
+        assert_eq!(recording.wait_retry, None);
+        let recording = resolve(&options(Some(90.0), false), LiveStatus::IsLive, None, Some(1000.0)).unwrap();
+        assert_eq!(recording.max_duration, 90.0);
+    }
+
+    #[test]
+    fn max_duration_is_bounded() {
+        for seconds in [0.0, 0.5, 14401.0] {
+            assert!(resolve(&options(Some(seconds), false), LiveStatus::IsLive, None, Some(1000.0)).is_err());
+        }
+        assert!(resolve(&options(Some(14400.0), false), LiveStatus::IsLive, None, Some(1000.0)).is_ok());
+    }
+
+    #[test]
+    fn recordings_fit_in_the_job_size_limit() {
+        // 8 Mbit/s fills 2 GB in 2000s: the default shrinks, a longer request fails
+        let recording = resolve(&options(None, false), LiveStatus::IsLive, None, Some(8000.0)).unwrap();
+        assert_eq!(recording.max_duration, 2000.0);
+        assert!(resolve(&options(Some(2000.0), false), LiveStatus::IsLive, None, Some(8000.0)).is_ok());
+        assert!(resolve(&options(Some(2001.0), false), LiveStatus::IsLive, None, Some(8000.0)).is_err());
+        // Without a reported bitrate, the assumed one applies
+        let recording = resolve(&options(None, false), LiveStatus::IsLive, None, None).unwrap();
+        assert_eq!(recording.max_duration, 2000.0);
+        assert!(resolve(&options(None, false), LiveStatus::IsLive, None, Some(f64::MAX)).is_err());
+    }
+
+    #[test]
+    fn upcoming_streams_need_waiting_for() {
+        let starts_in = |seconds: i64| Some(NOW + seconds);
+        assert!(resolve(&options(None, false), LiveStatus::IsUpcoming, starts_in(600), Some(1000.0)).is_err());
+        let recording = resolve(&options(None, true), LiveStatus::IsUpcoming, starts_in(600), Some(1000.0)).unwrap();
+        assert_eq!(recording.wait_retry, Some(60));
+        // A premiere with no known start is waited for; one too far off is not
+        assert!(resolve(&options(None, true), LiveStatus::IsUpcoming, None, Some(1000.0)).is_ok());
+        assert!(resolve(&options(None, true), LiveStatus::IsUpcoming, starts_in(86400), Some(1000.0)).is_ok());
+        assert!(resolve(&options(None, true), LiveStatus::IsUpcoming, starts_in(86401), Some(1000.0)).is_err());
+        // Waiting only applies to streams that have not started
+        let recording = resolve(&options(None, true), LiveStatus::IsLive, None, Some(1000.0)).unwrap();
+        assert_eq!(recording.wait_retry, None);
+    }
+
+    #[test]
+    fn recordings_from_the_start_are_a_section() {
+        let recording = Recording {
+            from_start: true,
+            max_duration: 3600.0,
+            wait_retry: None,
+        };
+        assert_eq!(recording.ytdlp_args(), ["--live-from-start", "--download-sections", "*0-3600"]);
+    }
+
+    #[test]
+    fn recordings_from_now_stop_ffmpeg() {
+        let recording = Recording {
+            from_start: false,
+            max_duration: 90.4,
+            wait_retry: Some(60),
+        };
+        assert_eq!(
+            recording.ytdlp_args(),
+            ["--hls-use-mpegts", "--downloader-args", "ffmpeg_o:-t 90", "--wait-for-video", "60"]
+        );
+    }
+
+    #[test]
+    fn bitrate_is_estimated_from_the_best_formats() {
+        let stream = info(
+            r#"{"id": "a", "formats": [
+                {"format_id": "91", "vcodec": "avc1", "acodec": "mp4a", "tbr": 300},
+                {"format_id": "96", "vcodec": "avc1", "acodec": "mp4a", "tbr": 4500},
+                {"format_id": "299", "vcodec": "avc1", "acodec": "none", "tbr": 6000}
+            ]}"#,
+        );
+        assert_eq!(estimated_bitrate(&stream, false), Some(6000.0));
+        // With no audio-only format, the best muxed one stands in
+        assert_eq!(estimated_bitrate(&stream, true), Some(4500.0));
+
+        let split = info(
+            r#"{"id": "a", "formats": [
+                {"format_id": "140", "vcodec": "none", "acodec": "mp4a", "abr": 128},
+                {"format_id": "137", "vcodec": "avc1", "acodec": "none", "tbr": 4400}
+            ]}"#,
+        );
+        assert_eq!(estimated_bitrate(&split, false), Some(4528.0));
+        assert_eq!(estimated_bitrate(&split, true), Some(128.0));
+        assert_eq!(estimated_bitrate(&info(r#"{"id": "a"}"#), false), None);
+    }
+}
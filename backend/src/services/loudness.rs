@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::LoudnessConfig;
+use crate::error::ApiError;
+
+/// Loudness normalization asked for by a request. Fields left out fall back
+/// to the configured defaults.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LoudnessOptions {
+    /// Integrated loudness in LUFS
+    #[serde(rename = "targetLufs")]
+    pub target_lufs: Option<f64>,
+    /// Maximum true peak in dBTP
+    #[serde(rename = "truePeak")]
+    pub true_peak: Option<f64>,
+    /// Loudness range in LU
+    #[serde(rename = "loudnessRange")]
+    pub loudness_range: Option<f64>,
+}
+
+/// A checked EBU R128 target for ffmpeg's `loudnorm` filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessTarget {
+    pub integrated: f64,
+    pub true_peak: f64,
+    pub range: f64,
+}
+
+impl LoudnessTarget {
+    /// Merge `options` over `defaults` and check the result against the
+    /// ranges `loudnorm` accepts.
+    pub fn resolve(options: &LoudnessOptions, defaults: &LoudnessConfig) -> Result<Self, ApiError> {
+        let target = Self {
+            integrated: options.target_lufs.unwrap_or(defaults.target_lufs),
+            true_peak: options.true_peak.unwrap_or(defaults.true_peak),
+            range: options.loudness_range.unwrap_or(defaults.loudness_range),
+        };
+
+        let checks = [
+            ("targetLufs", target.integrated, -70.0, -5.0),
+            ("truePeak", target.true_peak, -9.0, 0.0),
+            ("loudnessRange", target.range, 1.0, 50.0),
+        ];
+        for (name, value, min, max) in checks {
+            if !(min..=max).contains(&value) {
+                return Err(ApiError::BadRequest(format!("{} {} is outside {} to {}", name, value, min, max)));
+            }
+        }
+        Ok(target)
+    }
+
+    /// First pass: measure only.
+    pub fn measure_filter(&self) -> String {
+        format!("{}:print_format=json", self.base_filter())
+    }
+
+    /// Second pass: apply the gain worked out from the first pass's
+    /// measurement. `linear` keeps the dynamics intact whenever the target
+    /// can be reached without exceeding the true peak.
+    pub fn apply_filter(&self, measured: &LoudnormStats) -> String {
+        format!(
+            "{}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true:print_format=json",
+            self.base_filter(),
+            measured.input_i,
+            measured.input_tp,
+            measured.input_lra,
+            measured.input_thresh,
+            measured.target_offset
+        )
+    }
+
+    fn base_filter(&self) -> String {
+        format!("loudnorm=I={}:TP={}:LRA={}", self.integrated, self.true_peak, self.range)
+    }
+}
+
+/// The statistics `loudnorm` prints with `print_format=json`. ffmpeg writes
+/// every value as a string, `-inf` included for silence.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoudnormStats {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    output_i: String,
+    output_tp: String,
+    output_lra: String,
+    normalization_type: String,
+    target_offset: String,
+}
+
+impl LoudnormStats {
+    /// Pull the JSON block `loudnorm` appends to ffmpeg's log output.
+    pub fn parse(log: &str) -> Option<Self> {
+        let start = log.rfind("[Parsed_loudnorm")?;
+        let json_start = start + log[start..].find('{')?;
+        let json_end = json_start + log[json_start..].find('}')?;
+        serde_json::from_str(&log[json_start..=json_end]).ok()
+    }
+
+    /// Silent input measures `-inf` and cannot be normalized.
+    pub fn is_audible(&self) -> bool {
+        value(&self.input_i).is_some_and(f64::is_finite)
+    }
+}
+
+fn value(raw: &str) -> Option<f64> {
+    raw.trim().parse().ok()
+}
+
+/// Loudness before and after normalization. Values ffmpeg could not measure
+/// are left out.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoudnessReport {
+    pub target_lufs: f64,
+    pub target_true_peak: f64,
+    /// Integrated loudness in LUFS, true peak in dBTP and loudness range in LU
+    pub input_lufs: Option<f64>,
+    pub input_true_peak: Option<f64>,
+    pub input_range: Option<f64>,
+    pub output_lufs: Option<f64>,
+    pub output_true_peak: Option<f64>,
+    pub output_range: Option<f64>,
+    /// `linear` when a plain gain change was enough, `dynamic` when the
+    /// filter had to compress to respect the true peak; absent when the
+    /// input was silent and left alone
+    pub normalization: Option<String>,
+}
+
+impl LoudnessReport {
+    /// `applied` is the second pass's output, missing when it was skipped.
+    pub fn new(target: &LoudnessTarget, measured: &LoudnormStats, applied: Option<&LoudnormStats>) -> Self {
+        let finite = |raw: &str| value(raw).filter(|value| value.is_finite());
+        Self {
+            target_lufs: target.integrated,
+            target_true_peak: target.true_peak,
+            input_lufs: finite(&measured.input_i),
+            input_true_peak: finite(&measured.input_tp),
+            input_range: finite(&measured.input_lra),
+            output_lufs: applied.and_then(|stats| finite(&stats.output_i)),
+            output_true_peak: applied.and_then(|stats| finite(&stats.output_tp)),
+            output_range: applied.and_then(|stats| finite(&stats.output_lra)),
+            normalization: applied.map(|stats| stats.normalization_type.to_ascii_lowercase()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = r#"Input #0, matroska,webm, from 'in.webm':
+  Duration: 00:03:32.02, start: -0.007000, bitrate: 133 kb/s
+[Parsed_loudnorm_0 @ 0x5581a3c0] 
+{
+	"input_i" : "-9.51",
+	"input_tp" : "0.52",
+	"input_lra" : "4.30",
+	"input_thresh" : "-19.62",
+	"output_i" : "-16.02",
+	"output_tp" : "-1.50",
+	"output_lra" : "3.90",
+	"output_thresh" : "-26.10",
+	"normalization_type" : "Dynamic",
+	"target_offset" : "0.02"
+}
+[out#0/null @ 0x5581a400] video:0kB audio:36523kB"#;
+
+    #[test]
+    fn stats_are_read_from_the_log() {
+        let stats = LoudnormStats::parse(LOG).unwrap();
+        assert!(stats.is_audible());
+        assert_eq!(value(&stats.input_i), Some(-9.51));
+        assert_eq!(stats.normalization_type, "Dynamic");
+        assert_eq!(value(&stats.target_offset), Some(0.02));
+    }
+
+    #[test]
+    fn silence_is_not_audible() {
+        let silent = LOG.replace(r#""input_i" : "-9.51""#, r#""input_i" : "-inf""#);
+        assert!(!LoudnormStats::parse(&silent).unwrap().is_audible());
+    }
+
+    #[test]
+    fn last_block_wins() {
+        let twice = format!("{}\n{}", LOG, LOG.replace("-16.02", "-14.00"));
+        assert_eq!(value(&LoudnormStats::parse(&twice).unwrap().output_i), Some(-14.0));
+    }
+
+    #[test]
+    fn missing_or_broken_stats() {
+        assert!(LoudnormStats::parse("").is_none());
+        assert!(LoudnormStats::parse("[Parsed_loudnorm_0 @ 0x1] no json here").is_none());
+        assert!(LoudnormStats::parse("[Parsed_loudnorm_0 @ 0x1] { \"input_i\" : \"-9\" ").is_none());
+        assert!(LoudnormStats::parse("[Parsed_loudnorm_0 @ 0x1] { \"input_i\" : \"-9\" }").is_none());
+    }
+}
@@ -1 +1,24 @@
-pub mod youtube_service;
\ No newline at end of file
+pub mod animation;
+pub mod archive;
+pub mod audio_output;
+pub mod clip;
+pub mod content_type;
+pub mod ffmpeg;
+pub mod filename;
+pub mod format_preset;
+pub mod format_ranking;
+pub mod format_selector;
+pub mod images;
+pub mod jobs;
+pub mod key_store;
+pub mod live;
+pub mod loudness;
+pub mod rate_limiter;
+pub mod segments;
+pub mod storyboard;
+pub mod subtitles;
+pub mod transcode;
+pub mod transcript;
+pub mod youtube_service;
+pub mod ytdlp_info;
+pub mod ytdlp_progress;
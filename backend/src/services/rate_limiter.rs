@@ -0,0 +1,276 @@
+use crate::config::BucketConfig;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets that have been full and untouched for this long are dropped to
+/// bound memory. A full bucket is what a new client gets anyway, so dropping
+/// it never hands anyone extra requests.
+const IDLE_EVICTION: Duration = Duration::from_secs(600);
+const EVICTION_THRESHOLD: usize = 10_000;
+
+/// Outcome of taking a token from a client's bucket.
+#[derive(Debug, Clone, Copy)]
+pub enum Decision {
+    Allowed { limit: u32, remaining: u32 },
+    Limited { limit: u32, retry_after: Duration },
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token-bucket limiter with one bucket per client key.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// `name` identifies the bucket in configuration errors. A bucket that
+    /// never refills would lock clients out for good, so it is refused.
+    pub fn new(name: &str, config: BucketConfig) -> Result<Self> {
+        if config.refill_per_minute == 0 {
+            return Err(anyhow!("rate_limit.{}.refill_per_minute must be at least 1", name));
+        }
+        Ok(Self {
+            capacity: config.capacity.max(1) as f64,
+            refill_per_sec: config.refill_per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn check(&self, client: &str) -> Decision {
+        let now = Instant::now();
+        let limit = self.capacity as u32;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= EVICTION_THRESHOLD {
+            buckets.retain(|_, bucket| !self.is_stale(bucket, now));
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Decision::Allowed {
+                limit,
+                remaining: bucket.tokens as u32,
+            };
+        }
+
+        let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / self.refill_per_sec);
+        Decision::Limited { limit, retry_after }
+    }
+
+    /// Whether a bucket has refilled completely and gone unused since.
+    fn is_stale(&self, bucket: &Bucket, now: Instant) -> bool {
+        let idle = now.duration_since(bucket.updated);
+        idle >= IDLE_EVICTION && bucket.tokens + idle.as_secs_f64() * self.refill_per_sec >= self.capacity
+    }
+}
+
+/// Addresses allowed to report the original client through `X-Forwarded-For`.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    pub fn parse(entries: &[String]) -> Result<Self> {
+        let ranges = entries
+            .iter()
+            .map(|entry| parse_cidr(entry))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { ranges })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        self.ranges
+            .iter()
+            .any(|(network, prefix)| in_range(addr, *network, *prefix))
+    }
+
+    /// Resolve the real client address. Starting from the peer, walk the
+    /// forwarded chain right to left while hops are trusted proxies; the
+    /// first untrusted hop is the client.
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        if !self.contains(peer) {
+            return peer;
+        }
+
+        let mut client = peer;
+        for hop in forwarded_for.unwrap_or("").rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(addr) => {
+                    client = addr;
+                    if !self.contains(addr) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        client
+    }
+}
+
+fn parse_cidr(entry: &str) -> Result<(IpAddr, u8)> {
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (entry, None),
+    };
+
+    let addr: IpAddr = addr
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid trusted proxy address: {}", entry))?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix
+            .trim()
+            .parse::<u8>()
+            .ok()
+            .filter(|p| *p <= max)
+            .ok_or_else(|| anyhow!("Invalid trusted proxy prefix: {}", entry))?,
+        None => max,
+    };
+
+    Ok((addr, prefix))
+}
+
+fn in_range(addr: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (addr, network) {
+        (IpAddr::V4(addr), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(addr) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(addr) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(capacity: u32, refill_per_minute: u32) -> RateLimiter {
+        RateLimiter::new("test", BucketConfig { capacity, refill_per_minute }).unwrap()
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn bucket_allows_capacity_then_limits() {
+        let limiter = limiter(3, 60);
+        for remaining in [2, 1, 0] {
+            match limiter.check("a") {
+                Decision::Allowed { limit, remaining: left } => assert_eq!((limit, left), (3, remaining)),
+                Decision::Limited { .. } => panic!("limited too early"),
+            }
+        }
+        match limiter.check("a") {
+            // One token a second, and the bucket is just under empty
+            Decision::Limited { limit, retry_after } => {
+                assert_eq!(limit, 3);
+                assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+            }
+            Decision::Allowed { .. } => panic!("allowed past capacity"),
+        }
+        // Buckets are per client
+        assert!(matches!(limiter.check("b"), Decision::Allowed { remaining: 2, .. }));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        // 100 tokens a second
+        let limiter = limiter(1, 6000);
+        assert!(matches!(limiter.check("a"), Decision::Allowed { .. }));
+        assert!(matches!(limiter.check("a"), Decision::Limited { .. }));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(matches!(limiter.check("a"), Decision::Allowed { .. }));
+    }
+
+    #[test]
+    fn only_idle_full_buckets_are_stale() {
+        // One token a minute, so an empty bucket takes 100 minutes to refill
+        let limiter = limiter(100, 1);
+        let now = Instant::now() + Duration::from_secs(3600);
+        let bucket = |tokens: f64, idle_secs: u64| Bucket {
+            tokens,
+            updated: now - Duration::from_secs(idle_secs),
+        };
+        assert!(limiter.is_stale(&bucket(100.0, 600), now));
+        assert!(!limiter.is_stale(&bucket(100.0, 599), now));
+        assert!(limiter.is_stale(&bucket(90.0, 600), now));
+        assert!(!limiter.is_stale(&bucket(0.0, 600), now));
+        assert!(!limiter.is_stale(&bucket(0.0, 3599), now));
+    }
+
+    #[test]
+    fn zero_refill_is_rejected() {
+        let config = BucketConfig {
+            capacity: 5,
+            refill_per_minute: 0,
+        };
+        assert!(RateLimiter::new("download", config).is_err());
+    }
+
+    #[test]
+    fn cidr_entries_parse() {
+        assert_eq!(parse_cidr("10.0.0.0/8").unwrap(), (ip("10.0.0.0"), 8));
+        assert_eq!(parse_cidr("192.168.1.5").unwrap(), (ip("192.168.1.5"), 32));
+        assert_eq!(parse_cidr("::1").unwrap(), (ip("::1"), 128));
+        assert_eq!(parse_cidr(" fd00::/8 ").unwrap(), (ip("fd00::"), 8));
+        assert_eq!(parse_cidr("0.0.0.0/0").unwrap(), (ip("0.0.0.0"), 0));
+        for invalid in ["10.0.0.0/33", "::/129", "10.0.0/8", "proxy", "10.0.0.0/x", ""] {
+            assert!(parse_cidr(invalid).is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    #[test]
+    fn ranges_match_by_prefix() {
+        assert!(in_range(ip("10.1.2.3"), ip("10.0.0.0"), 8));
+        assert!(!in_range(ip("11.0.0.1"), ip("10.0.0.0"), 8));
+        assert!(in_range(ip("203.0.113.9"), ip("0.0.0.0"), 0));
+        assert!(in_range(ip("fd00::1"), ip("fd00::"), 8));
+        // Families never match each other
+        assert!(!in_range(ip("::ffff:10.0.0.1"), ip("10.0.0.0"), 8));
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peers() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8".to_string()]).unwrap();
+        assert_eq!(proxies.client_ip(ip("203.0.113.7"), Some("198.51.100.1")), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_chain_is_walked_through_trusted_proxies() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8".to_string()]).unwrap();
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), Some("198.51.100.1")), ip("198.51.100.1"));
+        // A spoofed entry left of the real client is never reached
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), Some("1.2.3.4, 198.51.100.1, 10.0.0.2")),
+            ip("198.51.100.1")
+        );
+        // Garbage stops the walk at the last good hop
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), Some("198.51.100.1, junk")), ip("10.0.0.1"));
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), None), ip("10.0.0.1"));
+    }
+}
@@ -0,0 +1,484 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::config::SegmentConfig;
+use crate::error::ApiError;
+use crate::services::clip::{TimeRange, Timestamp};
+use crate::services::ytdlp_info::Chapter;
+
+/// Most segments a request may list itself.
+const MAX_SEGMENTS: usize = 50;
+
+/// Kept sections shorter than this, in seconds, are dropped rather than
+/// downloaded on their own.
+const MIN_KEPT: f64 = 0.5;
+
+/// SponsorBlock's segment categories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentCategory {
+    Sponsor,
+    /// Unpaid promotion of the creator's own merch, channels or Patreon
+    Selfpromo,
+    /// Reminders to like, subscribe or comment
+    Interaction,
+    Intro,
+    Outro,
+    /// Recaps and previews of other parts of the video
+    Preview,
+    /// Non-music parts of music videos
+    MusicOfftopic,
+    /// Tangents and jokes that are not needed to follow the video
+    Filler,
+}
+
+impl SegmentCategory {
+    const ALL: [SegmentCategory; 8] = [
+        SegmentCategory::Sponsor,
+        SegmentCategory::Selfpromo,
+        SegmentCategory::Interaction,
+        SegmentCategory::Intro,
+        SegmentCategory::Outro,
+        SegmentCategory::Preview,
+        SegmentCategory::MusicOfftopic,
+        SegmentCategory::Filler,
+    ];
+
+    /// The name the API uses.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SegmentCategory::Sponsor => "sponsor",
+            SegmentCategory::Selfpromo => "selfpromo",
+            SegmentCategory::Interaction => "interaction",
+            SegmentCategory::Intro => "intro",
+            SegmentCategory::Outro => "outro",
+            SegmentCategory::Preview => "preview",
+            SegmentCategory::MusicOfftopic => "music_offtopic",
+            SegmentCategory::Filler => "filler",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|category| category.as_str() == name)
+    }
+
+    /// Chapter title for a marked segment.
+    pub fn title(self) -> &'static str {
+        match self {
+            SegmentCategory::Sponsor => "Sponsor",
+            SegmentCategory::Selfpromo => "Self-promotion",
+            SegmentCategory::Interaction => "Interaction reminder",
+            SegmentCategory::Intro => "Intro",
+            SegmentCategory::Outro => "Outro",
+            SegmentCategory::Preview => "Preview",
+            SegmentCategory::MusicOfftopic => "Non-music",
+            SegmentCategory::Filler => "Filler",
+        }
+    }
+}
+
+/// What happens to the segments found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentAction {
+    /// Cut them out of the download
+    #[default]
+    Remove,
+    /// Keep everything and give each segment its own chapter
+    Chapters,
+}
+
+/// A segment listed in the request.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SegmentInput {
+    pub category: SegmentCategory,
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+/// Segment handling asked for by a request. Without `segments`, they are
+/// looked up on the configured SponsorBlock-compatible server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SegmentOptions {
+    /// Categories to act on; the configured defaults when left out
+    pub categories: Option<Vec<SegmentCategory>>,
+    /// Segments to use instead of asking the server
+    pub segments: Option<Vec<SegmentInput>>,
+    #[serde(default)]
+    pub action: SegmentAction,
+}
+
+impl SegmentOptions {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.categories.as_ref().is_some_and(Vec::is_empty) {
+            return Err(ApiError::BadRequest("categories needs at least one category".to_string()));
+        }
+        let Some(segments) = &self.segments else {
+            return Ok(());
+        };
+        if segments.len() > MAX_SEGMENTS {
+            return Err(ApiError::BadRequest(format!("At most {} segments can be given", MAX_SEGMENTS)));
+        }
+        for segment in segments {
+            if segment.end.seconds() <= segment.start.seconds() {
+                return Err(ApiError::BadRequest(format!(
+                    "Segment end {:.3}s must be after its start {:.3}s",
+                    segment.end.seconds(),
+                    segment.start.seconds()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where this request's segments come from. A list given in the request
+    /// is only narrowed down when the request names categories too.
+    pub fn provider<'a>(&'a self, config: &'a SegmentConfig) -> SegmentProvider<'a> {
+        match &self.segments {
+            Some(segments) => SegmentProvider::List {
+                segments,
+                categories: self.categories.as_deref(),
+            },
+            None => SegmentProvider::Api {
+                base_url: &config.api_url,
+                timeout: Duration::from_secs(config.timeout_secs),
+                categories: self.categories.as_deref().unwrap_or(&config.categories),
+            },
+        }
+    }
+}
+
+/// A section of the video in one category, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Segment {
+    pub category: SegmentCategory,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// A source of segments for a video.
+#[derive(Debug, Clone, Copy)]
+pub enum SegmentProvider<'a> {
+    /// A server speaking SponsorBlock's `/api/skipSegments`
+    Api {
+        base_url: &'a str,
+        timeout: Duration,
+        categories: &'a [SegmentCategory],
+    },
+    /// Segments given in the request
+    List {
+        segments: &'a [SegmentInput],
+        categories: Option<&'a [SegmentCategory]>,
+    },
+}
+
+/// One entry of a `/api/skipSegments` response.
+#[derive(Debug, Deserialize)]
+struct ApiSegment {
+    segment: [f64; 2],
+    category: String,
+    #[serde(rename = "actionType", default)]
+    action_type: Option<String>,
+}
+
+impl SegmentProvider<'_> {
+    /// Segments of `video_id` in the wanted categories, sorted by start.
+    pub async fn segments(&self, video_id: &str) -> Result<Vec<Segment>> {
+        let mut segments: Vec<Segment> = match self {
+            SegmentProvider::Api {
+                base_url,
+                timeout,
+                categories,
+            } => fetch_segments(base_url, *timeout, video_id, categories).await?,
+            SegmentProvider::List { segments, categories } => segments
+                .iter()
+                .filter(|segment| categories.is_none_or(|categories| categories.contains(&segment.category)))
+                .map(|segment| Segment {
+                    category: segment.category,
+                    start: segment.start.seconds(),
+                    end: segment.end.seconds(),
+                })
+                .collect(),
+        };
+        segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+        Ok(segments)
+    }
+}
+
+async fn fetch_segments(
+    base_url: &str,
+    timeout: Duration,
+    video_id: &str,
+    categories: &[SegmentCategory],
+) -> Result<Vec<Segment>> {
+    let names: Vec<&str> = categories.iter().map(|category| category.as_str()).collect();
+    let url = format!("{}/api/skipSegments", base_url.trim_end_matches('/'));
+    let client = reqwest::Client::builder().timeout(timeout).build()?;
+    let response = client
+        .get(&url)
+        .query(&[("videoID", video_id.to_string()), ("categories", serde_json::to_string(&names)?)])
+        .send()
+        .await
+        .map_err(|e| ApiError::Unavailable(format!("Segment lookup failed: {}", e)))?;
+
+    // The API answers 404 when a video has no segments
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(Vec::new());
+    }
+    let found: Vec<ApiSegment> = response
+        .error_for_status()
+        .map_err(|e| ApiError::Unavailable(format!("Segment lookup failed: {}", e)))?
+        .json()
+        .await
+        .map_err(|e| ApiError::Unavailable(format!("Segment lookup returned an unexpected response: {}", e)))?;
+
+    // Mute and full-video labels are not sections that can be cut
+    Ok(found
+        .into_iter()
+        .filter(|segment| segment.action_type.as_deref().is_none_or(|action| action == "skip"))
+        .filter(|segment| segment.segment[1] > segment.segment[0])
+        .filter_map(|segment| {
+            Some(Segment {
+                category: SegmentCategory::from_name(&segment.category)?,
+                start: segment.segment[0],
+                end: segment.segment[1],
+            })
+        })
+        .collect())
+}
+
+/// Clamp segments to the video, sort them and merge any that overlap.
+pub fn merge(segments: &[Segment], duration: f64) -> Vec<Segment> {
+    let mut sorted = segments.to_vec();
+    sorted.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut merged: Vec<Segment> = Vec::new();
+    for segment in &sorted {
+        let (start, end) = (segment.start.max(0.0), segment.end.min(duration));
+        if end <= start {
+            continue;
+        }
+        match merged.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => merged.push(Segment {
+                category: segment.category,
+                start,
+                end,
+            }),
+        }
+    }
+    merged
+}
+
+/// The sections left once `segments` are cut out, as clip ranges. Slivers
+/// between segments are dropped along with them.
+pub fn kept_ranges(segments: &[Segment], duration: f64) -> Result<Vec<TimeRange>, ApiError> {
+    let mut ranges = Vec::new();
+    let mut position = 0.0;
+    for segment in merge(segments, duration).iter().chain([&Segment {
+        category: SegmentCategory::Sponsor,
+        start: duration,
+        end: duration,
+    }]) {
+        if segment.start - position >= MIN_KEPT {
+            ranges.push(TimeRange {
+                start: Timestamp::from_seconds(position).map_err(|e| ApiError::BadRequest(e.to_string()))?,
+                end: Some(Timestamp::from_seconds(segment.start).map_err(|e| ApiError::BadRequest(e.to_string()))?),
+            });
+        }
+        position = segment.end;
+    }
+
+    if ranges.is_empty() {
+        return Err(ApiError::BadRequest("The segments cover the whole video".to_string()));
+    }
+    Ok(ranges)
+}
+
+/// Chapters with each segment marked. Cuts fall at both the video's own
+/// chapter boundaries and the segment edges; the parts in between keep the
+/// title of the chapter they belong to.
+pub fn chapters_with_segments(segments: &[Segment], chapters: &[Chapter], duration: f64) -> Vec<Chapter> {
+    let segments = merge(segments, duration);
+    let mut points = vec![0.0, duration];
+    points.extend(segments.iter().flat_map(|segment| [segment.start, segment.end]));
+    // A chapter boundary inside a segment would split its chapter in two
+    points.extend(
+        chapters
+            .iter()
+            .flat_map(|chapter| [chapter.start_time, chapter.end_time])
+            .filter(|point| !segments.iter().any(|segment| segment.start < *point && *point < segment.end)),
+    );
+    points.retain(|point| (0.0..=duration).contains(point));
+    points.sort_by(f64::total_cmp);
+    points.dedup_by(|a, b| (*a - *b).abs() < 0.001);
+
+    points
+        .windows(2)
+        .map(|pair| {
+            let middle = (pair[0] + pair[1]) / 2.0;
+            let segment = segments.iter().find(|segment| (segment.start..segment.end).contains(&middle));
+            let chapter = chapters
+                .iter()
+                .find(|chapter| (chapter.start_time..chapter.end_time).contains(&middle));
+            let title = match (segment, chapter) {
+                (Some(segment), _) => segment.category.title().to_string(),
+                (None, Some(chapter)) => chapter.title.clone().unwrap_or_else(|| "Content".to_string()),
+                (None, None) => "Content".to_string(),
+            };
+            Chapter {
+                title: Some(title),
+                start_time: pair[0],
+                end_time: pair[1],
+            }
+        })
+        .collect()
+}
+
+/// An ffmpeg metadata file holding `chapters`, for `-map_chapters`.
+pub fn ffmetadata(chapters: &[Chapter]) -> String {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        metadata.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start_time * 1000.0).round() as u64,
+            (chapter.end_time * 1000.0).round() as u64,
+            escape_metadata(chapter.title.as_deref().unwrap_or_default())
+        ));
+    }
+    metadata
+}
+
+/// ffmetadata escapes `=`, `;`, `#`, `\` and newlines with a backslash.
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64) -> Segment {
+        Segment {
+            category: SegmentCategory::Sponsor,
+            start,
+            end,
+        }
+    }
+
+    fn chapter(title: &str, start_time: f64, end_time: f64) -> Chapter {
+        Chapter {
+            title: Some(title.to_string()),
+            start_time,
+            end_time,
+        }
+    }
+
+    fn spans(ranges: &[TimeRange]) -> Vec<(f64, Option<f64>)> {
+        ranges.iter().map(|range| (range.start.seconds(), range.end.map(Timestamp::seconds))).collect()
+    }
+
+    fn titled(chapters: &[Chapter]) -> Vec<(&str, f64, f64)> {
+        chapters
+            .iter()
+            .map(|chapter| (chapter.title.as_deref().unwrap_or_default(), chapter.start_time, chapter.end_time))
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_and_unsorted_segments_are_merged() {
+        let segments = [segment(50.0, 60.0), segment(10.0, 20.0), segment(15.0, 30.0), segment(30.0, 35.0)];
+        assert_eq!(merge(&segments, 100.0), [segment(10.0, 35.0), segment(50.0, 60.0)]);
+        // A segment inside another leaves it as it is
+        assert_eq!(merge(&[segment(10.0, 40.0), segment(20.0, 30.0)], 100.0), [segment(10.0, 40.0)]);
+    }
+
+    #[test]
+    fn segments_are_clamped_to_the_video() {
+        let segments = [segment(-5.0, 5.0), segment(95.0, 120.0), segment(100.0, 110.0)];
+        assert_eq!(merge(&segments, 100.0), [segment(0.0, 5.0), segment(95.0, 100.0)]);
+    }
+
+    #[test]
+    fn kept_ranges_are_the_gaps_between_segments() {
+        let ranges = kept_ranges(&[segment(30.0, 40.0), segment(10.0, 20.0)], 100.0).unwrap();
+        assert_eq!(spans(&ranges), [(0.0, Some(10.0)), (20.0, Some(30.0)), (40.0, Some(100.0))]);
+    }
+
+    #[test]
+    fn segments_at_either_end_leave_no_empty_range() {
+        let ranges = kept_ranges(&[segment(0.0, 10.0), segment(90.0, 100.0)], 100.0).unwrap();
+        assert_eq!(spans(&ranges), [(10.0, Some(90.0))]);
+    }
+
+    #[test]
+    fn slivers_shorter_than_min_kept_are_dropped() {
+        let ranges = kept_ranges(&[segment(0.4, 10.0), segment(10.4, 20.0), segment(20.5, 30.0)], 100.0).unwrap();
+        assert_eq!(spans(&ranges), [(20.0, Some(20.5)), (30.0, Some(100.0))]);
+        let ranges = kept_ranges(&[segment(0.0, 99.6)], 100.0);
+        assert!(ranges.is_err());
+    }
+
+    #[test]
+    fn segments_covering_the_whole_video_are_refused() {
+        assert!(kept_ranges(&[segment(0.0, 60.0), segment(50.0, 100.0)], 100.0).is_err());
+        assert!(kept_ranges(&[segment(-1.0, 200.0)], 100.0).is_err());
+        assert_eq!(spans(&kept_ranges(&[], 100.0).unwrap()), [(0.0, Some(100.0))]);
+    }
+
+    #[test]
+    fn segments_become_chapters_between_the_video_chapters() {
+        let chapters = [chapter("Intro", 0.0, 30.0), chapter("Main", 30.0, 100.0)];
+        let marked = chapters_with_segments(&[segment(50.0, 60.0)], &chapters, 100.0);
+        assert_eq!(
+            titled(&marked),
+            [("Intro", 0.0, 30.0), ("Main", 30.0, 50.0), ("Sponsor", 50.0, 60.0), ("Main", 60.0, 100.0)]
+        );
+    }
+
+    #[test]
+    fn chapter_boundaries_inside_a_segment_are_dropped() {
+        let chapters = [chapter("Intro", 0.0, 30.0), chapter("Main", 30.0, 100.0)];
+        let marked = chapters_with_segments(&[segment(25.0, 35.0)], &chapters, 100.0);
+        assert_eq!(titled(&marked), [("Intro", 0.0, 25.0), ("Sponsor", 25.0, 35.0), ("Main", 35.0, 100.0)]);
+    }
+
+    #[test]
+    fn near_identical_boundaries_are_one_cut() {
+        // Chapter and segment edges a rounding error apart make no empty chapter
+        let chapters = [chapter("Intro", 0.0, 30.0004), chapter("Main", 30.0004, 100.0)];
+        let marked = chapters_with_segments(&[segment(30.0, 40.0), segment(99.9995, 100.0)], &chapters, 100.0);
+        assert_eq!(titled(&marked), [("Intro", 0.0, 30.0), ("Sponsor", 30.0, 40.0), ("Main", 40.0, 99.9995)]);
+    }
+
+    #[test]
+    fn videos_without_chapters_get_content_chapters() {
+        let marked = chapters_with_segments(&[segment(0.0, 10.0)], &[], 60.0);
+        assert_eq!(titled(&marked), [("Sponsor", 0.0, 10.0), ("Content", 10.0, 60.0)]);
+    }
+
+    #[test]
+    fn metadata_titles_are_escaped() {
+        assert_eq!(escape_metadata("a=b;c#d\\e\nf"), "a\\=b\\;c\\#d\\\\e\\\nf");
+        assert_eq!(escape_metadata("plain title"), "plain title");
+    }
+
+    #[test]
+    fn ffmetadata_lists_chapters_in_milliseconds() {
+        let metadata = ffmetadata(&[chapter("Part #1", 0.0, 1.2345), chapter("Sponsor", 1.2345, 10.0)]);
+        assert_eq!(
+            metadata,
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1235\ntitle=Part \\#1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=1235\nEND=10000\ntitle=Sponsor\n"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::services::images::{self, ImageFormat};
+
+/// Space between tiles and around the sheet, in pixels.
+const PADDING: u32 = 4;
+
+/// Options for a `storyboard` download: a grid of frames sampled evenly
+/// across the video.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StoryboardOptions {
+    pub columns: Option<u32>,
+    pub rows: Option<u32>,
+    /// Width of each tile in pixels
+    #[serde(rename = "tileWidth")]
+    pub tile_width: Option<u32>,
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// Print each frame's position in its corner; on by default for sheets
+    /// and off for thumbnail tracks, where players show the time themselves
+    pub timestamps: Option<bool>,
+    /// Also produce a WebVTT thumbnail track pointing into the sheet
+    #[serde(default)]
+    pub vtt: bool,
+}
+
+/// A checked storyboard layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Storyboard {
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_width: u32,
+    pub format: ImageFormat,
+    pub timestamps: bool,
+    pub vtt: bool,
+}
+
+impl Storyboard {
+    pub fn resolve(options: &StoryboardOptions) -> Result<Self, ApiError> {
+        let storyboard = Self {
+            columns: options.columns.unwrap_or(4),
+            rows: options.rows.unwrap_or(4),
+            tile_width: options.tile_width.unwrap_or(320),
+            format: options.format,
+            timestamps: options.timestamps.unwrap_or(!options.vtt),
+            vtt: options.vtt,
+        };
+
+        for (name, value) in [("columns", storyboard.columns), ("rows", storyboard.rows)] {
+            if !(1..=10).contains(&value) {
+                return Err(ApiError::BadRequest(format!("{} {} is outside 1-10", name, value)));
+            }
+        }
+        if !(64..=640).contains(&storyboard.tile_width) {
+            return Err(ApiError::BadRequest(format!("tileWidth {} is outside 64-640", storyboard.tile_width)));
+        }
+        Ok(storyboard)
+    }
+
+    pub fn tile_count(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    /// Frame positions: the middle of each tile's equal slice of the video.
+    pub fn frame_times(&self, duration: f64) -> Vec<f64> {
+        images::evenly_spaced(self.tile_count(), duration)
+    }
+
+    /// Scale a frame to the tile width, stamping its position when asked.
+    pub fn tile_filter(&self, seconds: f64) -> String {
+        let scale = format!("scale={}:-2:flags=lanczos", self.tile_width);
+        if !self.timestamps {
+            return scale;
+        }
+        let font_size = (self.tile_width / 12).max(10);
+        format!(
+            "{},drawtext=text='{}':fontsize={}:fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=4:x=w-tw-8:y=h-th-8",
+            scale,
+            // drawtext reads `:` as an option separator even inside quotes
+            label(seconds).replace(':', "\\:"),
+            font_size
+        )
+    }
+
+    /// Lay the tiles out in rows, left to right.
+    pub fn grid_filter(&self) -> String {
+        format!(
+            "tile={}x{}:padding={}:margin={}:color=black",
+            self.columns, self.rows, PADDING, PADDING
+        )
+    }
+
+    /// A WebVTT track giving each slice of the video the tile that shows it,
+    /// as `image#xywh=x,y,w,h` fragments the player crops from the sheet.
+    pub fn webvtt(&self, image_name: &str, duration: f64, tile_height: u32) -> String {
+        let count = self.tile_count();
+        let mut vtt = String::from("WEBVTT\n");
+        for i in 0..count {
+            let start = duration * i as f64 / count as f64;
+            let end = duration * (i + 1) as f64 / count as f64;
+            let (column, row) = (i as u32 % self.columns, i as u32 / self.columns);
+            let x = PADDING + column * (self.tile_width + PADDING);
+            let y = PADDING + row * (tile_height + PADDING);
+            vtt.push_str(&format!(
+                "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+                vtt_time(start),
+                vtt_time(end),
+                image_name,
+                x,
+                y,
+                self.tile_width,
+                tile_height
+            ));
+        }
+        vtt
+    }
+}
+
+/// `1:02:03` or `2:03`
+fn label(seconds: f64) -> String {
+    let total = seconds as u64;
+    let (hours, minutes, secs) = (total / 3600, (total % 3600) / 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
+
+/// `00:01:02.500`
+fn vtt_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storyboard(columns: u32, rows: u32, tile_width: u32) -> Storyboard {
+        Storyboard::resolve(&StoryboardOptions {
+            columns: Some(columns),
+            rows: Some(rows),
+            tile_width: Some(tile_width),
+            vtt: true,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// The `(start, end, fragment)` of every cue.
+    fn cues(vtt: &str) -> Vec<(String, String, String)> {
+        vtt.split("\n\n")
+            .skip(1)
+            .map(|cue| {
+                let mut lines = cue.lines();
+                let (start, end) = lines.next().unwrap().split_once(" --> ").unwrap();
+                (start.to_string(), end.to_string(), lines.next().unwrap().to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn webvtt_cues_point_at_each_tile() {
+        let vtt = storyboard(3, 2, 160).webvtt("storyboard.jpg", 60.0, 90);
+        assert!(vtt.starts_with("WEBVTT\n"));
+        let cues = cues(&vtt);
+        assert_eq!(cues.len(), 6);
+        assert_eq!(cues[0], ("00:00:00.000".into(), "00:00:10.000".into(), "storyboard.jpg#xywh=4,4,160,90".into()));
+        // Tiles step by their size plus the padding between them
+        assert_eq!(cues[1].2, "storyboard.jpg#xywh=168,4,160,90");
+        assert_eq!(cues[2].2, "storyboard.jpg#xywh=332,4,160,90");
+        // The second row starts back at the left
+        assert_eq!(cues[3], ("00:00:30.000".into(), "00:00:40.000".into(), "storyboard.jpg#xywh=4,98,160,90".into()));
+        assert_eq!(cues[5], ("00:00:50.000".into(), "00:01:00.000".into(), "storyboard.jpg#xywh=332,98,160,90".into()));
+    }
+
+    #[test]
+    fn webvtt_cues_cover_the_video_without_gaps() {
+        let cues = cues(&storyboard(4, 4, 320).webvtt("s.png", 3725.5, 180));
+        assert_eq!(cues.len(), 16);
+        assert_eq!(cues[0].0, "00:00:00.000");
+        assert_eq!(cues[15].1, "01:02:05.500");
+        for pair in cues.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn layout_is_checked() {
+        let options = |columns, rows, tile_width| StoryboardOptions {
+            columns: Some(columns),
+            rows: Some(rows),
+            tile_width: Some(tile_width),
+            ..Default::default()
+        };
+        assert!(Storyboard::resolve(&options(0, 4, 320)).is_err());
+        assert!(Storyboard::resolve(&options(4, 11, 320)).is_err());
+        assert!(Storyboard::resolve(&options(4, 4, 63)).is_err());
+        assert!(Storyboard::resolve(&options(10, 10, 640)).is_ok());
+        // Stamps default on for sheets and off for tracks
+        assert!(Storyboard::resolve(&StoryboardOptions::default()).unwrap().timestamps);
+        assert!(!storyboard(4, 4, 320).timestamps);
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::services::ytdlp_info::InfoDict;
+use crate::types::SubtitleInfo;
+
+/// Most languages one request may ask for.
+pub const MAX_LANGUAGES: usize = 10;
+
+/// A subtitle language code such as `en`, `pt-BR` or `en-orig`. yt-dlp reads
+/// `--sub-langs` as a comma-separated list of regexes, so anything beyond
+/// letters, digits, `-` and `_` is rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SubtitleLanguage(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidLanguage(String);
+
+impl fmt::Display for InvalidLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid subtitle language: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidLanguage {}
+
+impl SubtitleLanguage {
+    pub fn parse(raw: &str) -> Result<Self, InvalidLanguage> {
+        let raw = raw.trim();
+        let valid = !raw.is_empty()
+            && raw.len() <= 32
+            && raw.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if valid {
+            Ok(Self(raw.to_string()))
+        } else {
+            Err(InvalidLanguage(format!("'{}' is not a language code", raw)))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for SubtitleLanguage {
+    type Error = InvalidLanguage;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<SubtitleLanguage> for String {
+    fn from(language: SubtitleLanguage) -> Self {
+        language.0
+    }
+}
+
+/// Formats subtitles can be delivered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    #[default]
+    Srt,
+    Vtt,
+    Ass,
+}
+
+impl SubtitleFormat {
+    pub fn ext(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Ass => "ass",
+        }
+    }
+}
+
+/// The `--sub-langs` value for a list of languages.
+pub fn sub_langs_arg(languages: &[SubtitleLanguage]) -> String {
+    languages.iter().map(SubtitleLanguage::as_str).collect::<Vec<_>>().join(",")
+}
+
+/// Manual tracks followed by auto-generated ones, each sorted by language.
+pub fn list_tracks(info: &InfoDict) -> Vec<SubtitleInfo> {
+    let mut tracks = Vec::new();
+    for (automatic, source) in [(false, &info.subtitles), (true, &info.automatic_captions)] {
+        let mut languages: Vec<&String> = source
+            .keys()
+            // YouTube lists the live chat replay as a subtitle track
+            .filter(|language| language.as_str() != "live_chat")
+            .collect();
+        languages.sort();
+
+        for language in languages {
+            let variants = &source[language];
+            let mut formats: Vec<String> = variants.iter().map(|track| track.ext.clone()).collect();
+            formats.dedup();
+            tracks.push(SubtitleInfo {
+                language: language.clone(),
+                name: variants.iter().find_map(|track| track.name.clone()),
+                automatic,
+                formats,
+            });
+        }
+    }
+    tracks
+}
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::TranscodeConfig;
+use crate::error::ApiError;
+
+const DEFAULT_AUDIO_BITRATE: u32 = 128;
+
+/// Target profiles for re-encoding a video download, all H.264 or HEVC
+/// video with AAC audio in MP4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VideoProfile {
+    /// Constrained to the baseline profile, which plays on nearly anything
+    H264Baseline,
+    /// High profile, for current devices
+    H264,
+    /// Half the size of H.264 at similar quality, tagged `hvc1` so Apple
+    /// players accept it
+    Hevc,
+}
+
+/// How a request wants its video re-encoded.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TranscodeOptions {
+    pub profile: VideoProfile,
+    /// Scale down to at most this height, keeping the aspect ratio
+    #[serde(rename = "maxHeight")]
+    pub max_height: Option<u32>,
+    /// Cap on the video bitrate in kbit/s
+    #[serde(rename = "maxBitrate")]
+    pub max_bitrate: Option<u32>,
+    /// AAC bitrate in kbit/s
+    #[serde(rename = "audioBitrate")]
+    pub audio_bitrate: Option<u32>,
+}
+
+impl TranscodeOptions {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if let Some(height) = self.max_height.filter(|height| !(144..=4320).contains(height)) {
+            return Err(ApiError::BadRequest(format!("maxHeight {} is outside 144-4320", height)));
+        }
+        if let Some(bitrate) = self.max_bitrate.filter(|bitrate| !(100..=100_000).contains(bitrate)) {
+            return Err(ApiError::BadRequest(format!("maxBitrate {} is outside 100-100000 kbit/s", bitrate)));
+        }
+        if let Some(bitrate) = self.audio_bitrate.filter(|bitrate| !(32..=512).contains(bitrate)) {
+            return Err(ApiError::BadRequest(format!("audioBitrate {} is outside 32-512 kbit/s", bitrate)));
+        }
+        Ok(())
+    }
+
+    /// ffmpeg output options. Quality is constant-rate-factor based, with
+    /// `maxBitrate` as a VBV cap on top. The first video and audio streams
+    /// are kept along with chapters, tags and the `subtitles` streams, which
+    /// must be text since MP4 carries subtitles only as `mov_text`.
+    pub fn ffmpeg_args(&self, config: &TranscodeConfig, subtitles: &[String]) -> Vec<String> {
+        let mut args: Vec<String> = ["-map", "0:v:0", "-map", "0:a:0?"].map(String::from).to_vec();
+        for stream in subtitles {
+            args.extend(["-map".to_string(), stream.clone()]);
+        }
+        args.extend(["-map_metadata", "0", "-map_chapters", "0"].map(String::from));
+
+        // 4:2:0 is all hardware decoders handle; `-2` keeps the width even
+        let mut filters = Vec::new();
+        if let Some(height) = self.max_height {
+            filters.push(format!("scale=-2:'min(ih,{})'", height));
+        }
+        filters.push("format=yuv420p".to_string());
+        args.extend(["-vf".to_string(), filters.join(",")]);
+
+        let (encoder, crf) = match self.profile {
+            VideoProfile::H264Baseline | VideoProfile::H264 => ("libx264", config.crf),
+            VideoProfile::Hevc => ("libx265", config.hevc_crf),
+        };
+        args.extend(["-c:v", encoder, "-preset", &config.speed_preset].map(String::from));
+        args.extend(["-crf".to_string(), crf.to_string()]);
+        match self.profile {
+            VideoProfile::H264Baseline => args.extend(["-profile:v", "baseline"].map(String::from)),
+            VideoProfile::H264 => args.extend(["-profile:v", "high"].map(String::from)),
+            VideoProfile::Hevc => args.extend(["-tag:v", "hvc1"].map(String::from)),
+        }
+        if let Some(bitrate) = self.max_bitrate {
+            args.extend([
+                "-maxrate".to_string(),
+                format!("{}k", bitrate),
+                "-bufsize".to_string(),
+                format!("{}k", bitrate * 2),
+            ]);
+        }
+        if config.threads > 0 {
+            args.extend(["-threads".to_string(), config.threads.to_string()]);
+        }
+
+        let audio_bitrate = self.audio_bitrate.unwrap_or(DEFAULT_AUDIO_BITRATE);
+        args.extend(["-c:a".to_string(), "aac".to_string(), "-b:a".to_string(), format!("{}k", audio_bitrate)]);
+        if !subtitles.is_empty() {
+            args.extend(["-c:s", "mov_text"].map(String::from));
+        }
+        args.extend(["-movflags", "+faststart"].map(String::from));
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(profile: VideoProfile) -> TranscodeOptions {
+        TranscodeOptions {
+            profile,
+            max_height: None,
+            max_bitrate: None,
+            audio_bitrate: None,
+        }
+    }
+
+    fn args(options: &TranscodeOptions, subtitles: &[&str]) -> String {
+        let subtitles: Vec<String> = subtitles.iter().map(|s| s.to_string()).collect();
+        options.ffmpeg_args(&TranscodeConfig::default(), &subtitles).join(" ")
+    }
+
+    #[test]
+    fn each_profile_picks_its_encoder() {
+        assert_eq!(
+            args(&options(VideoProfile::H264Baseline), &[]),
+            "-map 0:v:0 -map 0:a:0? -map_metadata 0 -map_chapters 0 -vf format=yuv420p -c:v libx264 -preset veryfast \
+             -crf 23 -profile:v baseline -c:a aac -b:a 128k -movflags +faststart"
+        );
+        assert!(args(&options(VideoProfile::H264), &[]).contains("-c:v libx264 -preset veryfast -crf 23 -profile:v high "));
+        assert!(args(&options(VideoProfile::Hevc), &[]).contains("-c:v libx265 -preset veryfast -crf 28 -tag:v hvc1 "));
+    }
+
+    #[test]
+    fn limits_become_scale_and_vbv_options() {
+        let capped = TranscodeOptions {
+            max_height: Some(720),
+            max_bitrate: Some(2500),
+            audio_bitrate: Some(96),
+            ..options(VideoProfile::H264)
+        };
+        let args = args(&capped, &[]);
+        assert!(args.contains("-vf scale=-2:'min(ih,720)',format=yuv420p "));
+        assert!(args.contains(" -maxrate 2500k -bufsize 5000k "));
+        assert!(args.contains(" -b:a 96k "));
+    }
+
+    #[test]
+    fn threads_come_from_the_config() {
+        let config = TranscodeConfig {
+            threads: 4,
+            ..TranscodeConfig::default()
+        };
+        let args = options(VideoProfile::H264).ffmpeg_args(&config, &[]);
+        assert!(args.join(" ").contains(" -threads 4 "));
+    }
+
+    #[test]
+    fn only_the_given_subtitle_streams_are_kept() {
+        let args = args(&options(VideoProfile::H264), &["0:2", "0:4"]);
+        assert!(args.starts_with("-map 0:v:0 -map 0:a:0? -map 0:2 -map 0:4 -map_metadata 0 "));
+        assert!(args.contains(" -c:s mov_text "));
+        assert!(!args.contains("0:s"));
+    }
+
+    #[test]
+    fn out_of_range_options_are_rejected() {
+        let with = |height, bitrate, audio| TranscodeOptions {
+            max_height: height,
+            max_bitrate: bitrate,
+            audio_bitrate: audio,
+            ..options(VideoProfile::H264)
+        };
+        assert!(with(Some(144), Some(100), Some(32)).validate().is_ok());
+        assert!(with(Some(4320), Some(100_000), Some(512)).validate().is_ok());
+        assert!(with(Some(100), None, None).validate().is_err());
+        assert!(with(None, Some(99), None).validate().is_err());
+        assert!(with(None, None, Some(513)).validate().is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::TranscriptConfig;
+use crate::services::clip::Timestamp;
+use crate::types::VideoChapter;
+
+/// How a transcript is rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    #[default]
+    Text,
+    Markdown,
+    Json,
+}
+
+/// A line of speech, in seconds from the start of the video.
+#[derive(Debug, Clone, Serialize)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Transcript {
+    pub video_id: String,
+    pub title: Option<String>,
+    pub language: String,
+    /// Built from auto-generated captions
+    pub automatic: bool,
+    pub chapters: Vec<VideoChapter>,
+    pub segments: Vec<Segment>,
+}
+
+impl Transcript {
+    /// Plain text with a blank line between chapters. With `timestamps`, each
+    /// segment goes on its own line behind its start time.
+    pub fn to_text(&self, timestamps: bool) -> String {
+        self.paragraphs()
+            .into_iter()
+            .map(|(chapter, segments)| {
+                let body = render_segments(segments, timestamps, |time| format!("[{}] ", time));
+                match chapter {
+                    Some(chapter) => format!("{}\n{}", chapter.title, body),
+                    None => body,
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+            + "\n"
+    }
+
+    /// Markdown with the title as a heading and one section per chapter.
+    pub fn to_markdown(&self, timestamps: bool) -> String {
+        let mut out = String::new();
+        if let Some(title) = &self.title {
+            out.push_str(&format!("# {}\n\n", title));
+        }
+
+        let paragraphs: Vec<String> = self
+            .paragraphs()
+            .into_iter()
+            .map(|(chapter, segments)| {
+                let body = render_segments(segments, timestamps, |time| format!("`[{}]` ", time));
+                // Hard line breaks between timestamped segments
+                let body = if timestamps { body.replace('\n', "  \n") } else { body };
+                match chapter {
+                    Some(chapter) => format!("## {}\n\n{}", chapter.title, body),
+                    None => body,
+                }
+            })
+            .collect();
+        out.push_str(&paragraphs.join("\n\n"));
+        out.push('\n');
+        out
+    }
+
+    /// Segments grouped by the chapter they start in, or a single group when
+    /// the video has no chapters.
+    fn paragraphs(&self) -> Vec<(Option<&VideoChapter>, &[Segment])> {
+        if self.chapters.is_empty() {
+            return vec![(None, &self.segments)];
+        }
+
+        let mut groups = Vec::new();
+        let mut rest = self.segments.as_slice();
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            let is_last = i == self.chapters.len() - 1;
+            let count = if is_last {
+                rest.len()
+            } else {
+                rest.iter().take_while(|segment| segment.start < chapter.end_time).count()
+            };
+            let (inside, after) = rest.split_at(count);
+            if !inside.is_empty() {
+                groups.push((Some(chapter), inside));
+            }
+            rest = after;
+        }
+        groups
+    }
+}
+
+fn render_segments(segments: &[Segment], timestamps: bool, stamp: impl Fn(&str) -> String) -> String {
+    if timestamps {
+        segments
+            .iter()
+            .map(|segment| format!("{}{}", stamp(&format_timestamp(segment.start)), segment.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// `1:02:03` or `2:03`
+fn format_timestamp(seconds: f64) -> String {
+    let total = seconds as u64;
+    let (hours, minutes, secs) = (total / 3600, (total % 3600) / 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
+
+/// Turn a WebVTT caption file into segments. `automatic` captions roll:
+/// each cue repeats the last line of the one before it above the new words,
+/// and short filler cues repeat it again, so the leading lines a cue shares
+/// with the end of the previous one are dropped. Lines in written captions
+/// are all kept, repeats included.
+pub fn parse_vtt(vtt: &str, automatic: bool) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut previous: Vec<String> = Vec::new();
+
+    for block in vtt.replace("\r\n", "\n").split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, end)) = parse_timing(timing) else {
+            continue;
+        };
+
+        let current: Vec<String> = lines.map(clean_cue_text).filter(|line| !line.is_empty()).collect();
+        let rolled = if automatic { rolled_over(&previous, &current) } else { 0 };
+        for line in &current[rolled..] {
+            segments.push(Segment { start, end, text: line.clone() });
+        }
+        previous = current;
+    }
+
+    segments
+}
+
+/// How many leading lines of `current` repeat the end of `previous`.
+fn rolled_over(previous: &[String], current: &[String]) -> usize {
+    (1..=previous.len().min(current.len()))
+        .rev()
+        .find(|&count| previous[previous.len() - count..] == current[..count])
+        .unwrap_or(0)
+}
+
+/// `00:01:02.500 --> 00:01:05.000 align:start position:0%`
+fn parse_timing(line: &str) -> Option<(f64, f64)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    let start = Timestamp::parse(start.trim()).ok()?.seconds();
+    let end = Timestamp::parse(end).ok()?.seconds();
+    Some((start, end))
+}
+
+/// Strip `<c>`, `<i>`, voice and inline timing tags and decode the entities
+/// VTT allows.
+fn clean_cue_text(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&lrm;", "")
+        .replace("&rlm;", "")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Video ID and language.
+type CacheKey = (String, String);
+
+/// Transcripts by the video ID in the requested URL and language. Entries expire after the configured
+/// TTL; when full, the oldest entry makes room.
+pub struct TranscriptCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<CacheKey, (Instant, Arc<Transcript>)>>,
+}
+
+impl TranscriptCache {
+    pub fn new(config: &TranscriptConfig) -> Self {
+        Self {
+            ttl: Duration::from_secs(config.cache_ttl_secs),
+            max_entries: config.cache_max_entries,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, video_id: &str, language: &str) -> Option<Arc<Transcript>> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (video_id.to_string(), language.to_string());
+        match entries.get(&key) {
+            Some((stored, transcript)) if stored.elapsed() < self.ttl => Some(transcript.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, video_id: &str, language: &str, transcript: Arc<Transcript>) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (stored, _)| stored.elapsed() < self.ttl);
+        while entries.len() >= self.max_entries {
+            let oldest = entries.iter().min_by_key(|(_, (stored, _))| *stored).map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => entries.remove(&key),
+                None => break,
+            };
+        }
+        entries.insert((video_id.to_string(), language.to_string()), (Instant::now(), transcript));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTO_VTT: &str = "WEBVTT
+Kind: captions
+Language: en
+
+00:00:00.000 --> 00:00:02.000 align:start position:0%
+so<00:00:00.500><c> today</c><00:00:01.000><c> we</c>
+
+00:00:02.000 --> 00:00:02.010 align:start position:0%
+so today we
+
+00:00:02.010 --> 00:00:04.000 align:start position:0%
+so today we
+are<00:00:02.500><c> testing</c>
+
+00:00:04.000 --> 00:00:04.010 align:start position:0%
+are testing
+
+00:00:04.010 --> 00:00:06.000 align:start position:0%
+are testing
+no<c> no</c>
+
+00:00:06.000 --> 00:00:08.000 align:start position:0%
+no no
+no no
+";
+
+    const WRITTEN_VTT: &str = "WEBVTT
+
+1
+00:00:01.000 --> 00:00:02.000
+<v Alice>Are you sure?</v>
+
+2
+00:00:02.000 --> 00:00:03.000
+No.
+
+3
+00:00:03.000 --> 00:00:04.000
+No.
+
+4
+00:00:04.000 --> 00:00:05.000
+Tom &amp; Jerry
+No.
+";
+
+    fn texts(segments: &[Segment]) -> Vec<&str> {
+        segments.iter().map(|segment| segment.text.as_str()).collect()
+    }
+
+    #[test]
+    fn auto_captions_drop_rolled_lines() {
+        let segments = parse_vtt(AUTO_VTT, true);
+        // The last cue rolls over one "no no" and says it once more
+        assert_eq!(texts(&segments), ["so today we", "are testing", "no no", "no no"]);
+        assert_eq!((segments[1].start, segments[1].end), (2.01, 4.0));
+    }
+
+    #[test]
+    fn written_captions_keep_repeated_lines() {
+        let segments = parse_vtt(WRITTEN_VTT, false);
+        assert_eq!(texts(&segments), ["Are you sure?", "No.", "No.", "Tom & Jerry", "No."]);
+        assert_eq!(segments[0].start, 1.0);
+    }
+
+    #[test]
+    fn rolled_over_matches_only_a_leading_suffix() {
+        let lines = |items: &[&str]| items.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+        assert_eq!(rolled_over(&lines(&["a", "b"]), &lines(&["b", "c"])), 1);
+        assert_eq!(rolled_over(&lines(&["a", "b"]), &lines(&["a", "b"])), 2);
+        // Shown before, but not where a rolled line would be
+        assert_eq!(rolled_over(&lines(&["a", "b"]), &lines(&["a", "c"])), 0);
+        assert_eq!(rolled_over(&lines(&["a", "b"]), &lines(&["c", "b"])), 0);
+        assert_eq!(rolled_over(&[], &lines(&["a"])), 0);
+    }
+
+    #[test]
+    fn cache_is_keyed_by_the_requested_id() {
+        let cache = TranscriptCache::new(&TranscriptConfig::default());
+        let transcript = Arc::new(Transcript {
+            video_id: "canonical".to_string(),
+            title: None,
+            language: "en".to_string(),
+            automatic: false,
+            chapters: Vec::new(),
+            segments: Vec::new(),
+        });
+        cache.insert("requested", "en", transcript);
+        assert!(cache.get("requested", "en").is_some());
+        assert!(cache.get("requested", "de").is_none());
+        assert!(cache.get("canonical", "en").is_none());
+    }
+}
@@ -1,132 +1,966 @@
+use crate::config::AppConfig;
+use crate::error::ApiError;
+use crate::locale::Locale;
+use crate::services::animation::{Animation, AnimationFormat};
+use crate::services::archive;
+use crate::services::content_type;
+use crate::services::audio_output::{self, AudioCodec, AudioOutput};
+use crate::services::clip::{self, CutMode, TimeRange};
+use crate::services::ffmpeg;
+use crate::services::filename::{sanitize_filename, FilenameTemplate};
+use crate::services::format_preset::{self, FormatConstraints, Preset, Selection};
+use crate::services::format_ranking::{rank_audio_formats, rank_video_formats, VideoCodec};
+use crate::services::format_selector::FormatSelector;
+use crate::services::images::{self, FrameOptions};
+use crate::services::jobs::{self, JobHandle, JobProgress, JobResult};
+use crate::services::live::{self, LiveStatus, Recording};
+use crate::services::loudness::{LoudnessReport, LoudnessTarget, LoudnormStats};
+use crate::services::segments::{self, Segment, SegmentAction, SegmentOptions};
+use crate::services::storyboard::{Storyboard, StoryboardOptions};
+use crate::services::subtitles::{self, SubtitleLanguage};
+use crate::services::transcode::TranscodeOptions;
+use crate::services::transcript::{self, Transcript, TranscriptCache};
+use crate::services::ytdlp_info::{Chapter, Format, InfoDict};
+use crate::services::ytdlp_progress;
 use crate::types::*;
 use anyhow::{anyhow, Result};
-use serde_json::Value;
-use std::process::Command;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
 use uuid::Uuid;
 
-pub async fn extract_video_info(url: &str) -> Result<VideoInfo> {
+pub async fn extract_video_info(url: &str, locale: Locale) -> Result<VideoInfo> {
     if !is_valid_youtube_url(url) {
         return Err(anyhow!("Invalid YouTube URL"));
     }
 
+    let Some(info) = fetch_info(url)? else {
+        return create_mock_video_info(url, locale);
+    };
+    let subtitle_tracks = subtitles::list_tracks(&info);
+    let live_status = LiveStatus::of(&info);
+
+    Ok(VideoInfo {
+        url: url.to_string(),
+        title: info.title.clone().unwrap_or_else(|| "Unknown Title".to_string()),
+        thumbnail: info.best_thumbnail().unwrap_or_default().to_string(),
+        duration: info
+            .duration
+            .map(|duration| locale.format_duration(duration))
+            .unwrap_or_else(|| "Unknown".to_string()),
+        views: info
+            .view_count
+            .map(|views| locale.format_views(views))
+            .unwrap_or_else(|| "Unknown views".to_string()),
+        uploader: info
+            .uploader
+            .or(info.channel)
+            .unwrap_or_else(|| "Unknown Channel".to_string()),
+        description: info.description,
+        id: info.id,
+        duration_seconds: info.duration,
+        view_count: info.view_count,
+        like_count: info.like_count,
+        upload_date: info.upload_date.as_deref().and_then(iso_date),
+        uploaded: info
+            .upload_date
+            .as_deref()
+            .and_then(|date| locale.format_upload_date(date)),
+        channel_id: info.channel_id,
+        channel_url: info.channel_url,
+        tags: info.tags,
+        categories: info.categories,
+        age_limit: info.age_limit,
+        is_live: live_status == LiveStatus::IsLive,
+        was_live: live_status == LiveStatus::WasLive,
+        live_status,
+        scheduled_start: info.release_timestamp.filter(|_| live_status == LiveStatus::IsUpcoming),
+        availability: info.availability,
+        chapters: video_chapters(&info.chapters),
+        subtitles: subtitle_tracks,
+    })
+}
+
+/// How `extract_quality_options` collapses formats of the same resolution.
+pub struct RankingOptions {
+    pub codec_preference: Vec<VideoCodec>,
+    pub include_alternatives: bool,
+}
+
+pub async fn extract_quality_options(url: &str, locale: Locale, ranking: &RankingOptions) -> Result<QualityOptions> {
+    if !is_valid_youtube_url(url) {
+        return Err(anyhow!("Invalid YouTube URL"));
+    }
+
+    let mut options = match fetch_info(url)? {
+        Some(info) => parse_quality_options(&info, ranking)?,
+        None => create_mock_quality_options(),
+    };
+
+    for format in &mut options.video {
+        format.filesize_display = display_size(format.filesize, format.filesize_approx, locale);
+        for alternative in &mut format.alternatives {
+            alternative.filesize_display = display_size(alternative.filesize, alternative.filesize_approx, locale);
+        }
+    }
+    for format in &mut options.audio {
+        format.filesize_display = display_size(format.filesize, format.filesize_approx, locale);
+    }
+
+    Ok(options)
+}
+
+/// Run `yt-dlp --dump-json` for a URL. Returns `None` when yt-dlp is missing
+/// or fails, so callers can fall back to mock data; JSON that yt-dlp did
+/// produce but that doesn't match the schema is an error.
+fn fetch_info(url: &str) -> Result<Option<InfoDict>> {
     // Check if yt-dlp is available
     if !check_ytdlp_available() {
-        return create_mock_video_info(url);
+        return Ok(None);
     }
 
-    // Use yt-dlp to extract video information
     let output = Command::new("yt-dlp")
         .args([
             "--dump-json",
             "--no-playlist",
             "--no-warnings",
             "--skip-download",
+            "--",
             url,
         ])
         .output();
 
-    match output {
-        Ok(output) => {
-            if !output.status.success() {
-                let error = String::from_utf8_lossy(&output.stderr);
-                println!("yt-dlp stderr: {}", error);
-                return create_mock_video_info(url);
-            }
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => {
+            println!("Command execution error: {}", e);
+            return Ok(None);
+        }
+    };
 
-            let json_str = String::from_utf8(output.stdout)?;
-            if json_str.trim().is_empty() {
-                return create_mock_video_info(url);
-            }
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        println!("yt-dlp stderr: {}", error);
+        return Ok(None);
+    }
 
-            match serde_json::from_str::<Value>(&json_str) {
-                Ok(json) => Ok(VideoInfo {
-                    url: url.to_string(),
-                    title: json["title"].as_str().unwrap_or("Unknown Title").to_string(),
-                    thumbnail: json["thumbnail"].as_str().unwrap_or("").to_string(),
-                    duration: format_duration(json["duration"].as_f64().unwrap_or(0.0)),
-                    views: format_views(json["view_count"].as_u64().unwrap_or(0)),
-                    uploader: json["uploader"].as_str().unwrap_or("Unknown Channel").to_string(),
-                    description: json["description"].as_str().map(|s| s.to_string()),
-                }),
-                Err(_) => create_mock_video_info(url),
-            }
-        }
-        Err(_) => create_mock_video_info(url),
+    let json_str = String::from_utf8(output.stdout)?;
+    if json_str.trim().is_empty() {
+        return Ok(None);
+    }
+
+    parse_info(&json_str).map(Some)
+}
+
+fn parse_info(json: &str) -> Result<InfoDict> {
+    let parsed = InfoDict::parse(json)?;
+    for diagnostic in &parsed.diagnostics {
+        println!("yt-dlp info schema mismatch: {}", diagnostic);
     }
+    Ok(parsed.info)
 }
 
-pub async fn extract_quality_options(url: &str) -> Result<QualityOptions> {
+/// The transcript of a video in one language, built from its manual
+/// subtitles or, failing that, its auto-generated captions. The flag is true
+/// when it came from the cache.
+pub async fn extract_transcript(
+    url: &str,
+    language: &SubtitleLanguage,
+    cache: &TranscriptCache,
+) -> Result<(Arc<Transcript>, bool)> {
     if !is_valid_youtube_url(url) {
         return Err(anyhow!("Invalid YouTube URL"));
     }
 
-    // Check if yt-dlp is available
-    if !check_ytdlp_available() {
-        return Ok(create_mock_quality_options());
+    // Looked up and stored under the same ID, so a hit never depends on what
+    // yt-dlp reports the video's ID as
+    let cache_key = extract_video_id(url);
+    if let Some(transcript) = cache_key.and_then(|id| cache.get(id, language.as_str())) {
+        return Ok((transcript, true));
     }
 
-    // Use yt-dlp to get available formats
-    let output = Command::new("yt-dlp")
-        .args([
-            "--dump-json",
-            "--no-playlist",
-            "--no-warnings",
-            "--skip-download",
-            url,
-        ])
-        .output();
+    let info = fetch_required_info(url)?;
+    let video_id = info
+        .id
+        .clone()
+        .or_else(|| cache_key.map(str::to_string))
+        .ok_or_else(|| anyhow!("Could not determine the video ID"))?;
 
-    match output {
-        Ok(output) => {
-            if !output.status.success() {
-                let error = String::from_utf8_lossy(&output.stderr);
-                println!("yt-dlp stderr: {}", error);
-                return Ok(create_mock_quality_options());
+    let language = language.as_str();
+    let orig = format!("{}-orig", language);
+    let (tracks, automatic) = match info.subtitles.get(language) {
+        Some(tracks) => (tracks, false),
+        None => match info.automatic_captions.get(language).or_else(|| info.automatic_captions.get(&orig)) {
+            Some(tracks) => (tracks, true),
+            None => {
+                return Err(ApiError::NotFound(format!("No subtitles or captions in '{}' for this video", language)).into())
             }
+        },
+    };
+    let track_url = tracks
+        .iter()
+        .find(|track| track.ext == "vtt")
+        .and_then(|track| track.url.as_deref())
+        .ok_or_else(|| anyhow!("No WebVTT track available for '{}'", language))?;
 
-            let json_str = String::from_utf8(output.stdout)?;
-            if json_str.trim().is_empty() {
-                return Ok(create_mock_quality_options());
-            }
+    let vtt = reqwest::get(track_url).await?.error_for_status()?.text().await?;
+    let segments = transcript::parse_vtt(&vtt, automatic);
+    println!("Fetched {} transcript for {}: {} segments", language, video_id, segments.len());
 
-            match serde_json::from_str::<Value>(&json_str) {
-                Ok(json) => parse_quality_options(json),
-                Err(e) => {
-                    println!("JSON parse error: {}", e);
-                    Ok(create_mock_quality_options())
-                }
-            }
+    let transcript = Arc::new(Transcript {
+        video_id,
+        title: info.title.clone(),
+        language: language.to_string(),
+        automatic,
+        chapters: video_chapters(&info.chapters),
+        segments,
+    });
+    if let Some(id) = cache_key {
+        cache.insert(id, language, transcript.clone());
+    }
+    Ok((transcript, false))
+}
+
+/// Every thumbnail variant of a video, largest first. Without yt-dlp, the
+/// fixed set YouTube serves for every video is listed instead.
+pub async fn list_thumbnails(url: &str) -> Result<Vec<ThumbnailInfo>> {
+    if !is_valid_youtube_url(url) {
+        return Err(anyhow!("Invalid YouTube URL"));
+    }
+
+    match fetch_info(url)? {
+        Some(info) => Ok(images::list_thumbnails(&info)),
+        None => {
+            let video_id = extract_video_id(url).ok_or_else(|| anyhow!("Could not determine the video ID"))?;
+            Ok(images::static_thumbnails(video_id))
+        }
+    }
+}
+
+/// Fetch a thumbnail, converting and scaling it as requested. Returns the
+/// image and its filename.
+pub async fn fetch_thumbnail(request: &ThumbnailRequest) -> Result<(Vec<u8>, String)> {
+    request.size.validate()?;
+    let thumbnails = list_thumbnails(&request.url).await?;
+    let thumbnail = images::pick_thumbnail(&thumbnails, request.id.as_deref())?;
+
+    let response = reqwest::get(&thumbnail.url).await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(ApiError::NotFound(format!("Thumbnail '{}' does not exist for this video", thumbnail.id)).into());
+    }
+    let source = response.error_for_status()?.bytes().await?.to_vec();
+
+    let video_id = extract_video_id(&request.url).unwrap_or("thumbnail");
+    let filename = sanitize_filename(&format!("{}-{}.{}", video_id, thumbnail.id, request.format.ext()));
+    let same_format = content_type::detect(&source, &thumbnail.url).mime == request.format.mime();
+    if same_format && request.size.is_original() {
+        return Ok((source, filename));
+    }
+
+    if !ffmpeg::check_ffmpeg_available() {
+        return Err(anyhow!("ffmpeg is not available on this system. Please install it to convert thumbnails."));
+    }
+    let unique_id = Uuid::new_v4().to_string();
+    let input = std::env::temp_dir().join(format!("vidsaver_{}.thumbnail.source", unique_id));
+    let output = std::env::temp_dir().join(format!("vidsaver_{}.thumbnail.{}", unique_id, request.format.ext()));
+    fs::write(&input, &source).await?;
+
+    println!("Converting thumbnail {} to {}", thumbnail.id, request.format.ext());
+    let filter = request.size.scale_filter();
+    let result = ffmpeg::convert_image(&input, &output, filter.as_deref(), &request.format.ffmpeg_args()).await;
+    let data = match result {
+        Ok(()) => fs::read(&output).await.map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&input).await;
+    let _ = fs::remove_file(&output).await;
+    Ok((data?, filename))
+}
+
+pub async fn preview_format(
+    request: FormatPreviewRequest,
+    config: &AppConfig,
+    locale: Locale,
+) -> Result<FormatPreview> {
+    if !is_valid_youtube_url(&request.url) {
+        return Err(anyhow!("Invalid YouTube URL"));
+    }
+
+    let constraints = merge_constraints(request.preset, request.constraints);
+    let info = fetch_required_info(&request.url)?;
+    let selection = resolve_selection(&info, &request.r#type, &constraints, config)?;
+
+    Ok(FormatPreview {
+        selector: selection.selector.to_string(),
+        video: selection.video.as_ref().and_then(video_format),
+        audio: selection.audio.as_ref().and_then(audio_format),
+        estimated_filesize: selection.estimated_filesize,
+        estimated_filesize_display: display_size(selection.estimated_filesize, None, locale),
+        constraints,
+    })
+}
+
+fn merge_constraints(preset: Option<Preset>, constraints: Option<FormatConstraints>) -> FormatConstraints {
+    let base = preset.map(Preset::constraints).unwrap_or_default();
+    constraints.unwrap_or_default().or(base)
+}
+
+/// Metadata that a request cannot do without, unlike the inspect endpoints
+/// which fall back to mock data.
+fn fetch_required_info(url: &str) -> Result<InfoDict> {
+    fetch_info(url)?.ok_or_else(|| anyhow!("Could not read video metadata; is yt-dlp installed?"))
+}
+
+/// Resolve constraints against the video's actual formats.
+fn resolve_selection(info: &InfoDict, download_type: &str, constraints: &FormatConstraints, config: &AppConfig) -> Result<Selection> {
+    let audio_only = match download_type {
+        "video" => false,
+        "audio" | "mp3" => true,
+        _ => return Err(anyhow!("Invalid download type")),
+    };
+
+    let selection = format_preset::resolve(info, constraints, audio_only, &config.formats.codec_preference)?;
+    println!(
+        "Resolved format constraints {:?} to {} (~{:?} bytes)",
+        constraints, selection.selector, selection.estimated_filesize
+    );
+    Ok(selection)
+}
+
+/// A finished download, ready to be sent to the client.
+pub struct DownloadOutput {
+    pub data: Vec<u8>,
+    pub filename: String,
+    /// Measured when loudness normalization was requested
+    pub loudness: Option<LoudnessReport>,
+    /// Found when segment removal or marking was requested
+    pub segments: Option<Vec<Segment>>,
+}
+
+/// Run a download in the background, recording progress and the result on
+/// `job`.
+pub async fn run_download_job(job: JobHandle, request: DownloadRequest, config: Arc<AppConfig>) {
+    let Some(_slot) = job.start().await else {
+        job.fail("Cancelled while queued".to_string());
+        return;
+    };
+    println!("Job {} started", job.id());
+
+    match download_video(request, &config, Some(&job)).await {
+        Ok(output) => {
+            let result = JobResult {
+                content_type: content_type::detect(&output.data, &output.filename).to_string(),
+                filename: output.filename,
+                size: output.data.len() as u64,
+                loudness: output.loudness,
+                segments: output.segments,
+            };
+            println!("Job {} completed: {} ({} bytes)", job.id(), result.filename, result.size);
+            job.complete(output.data, result);
         }
         Err(e) => {
-            println!("Command execution error: {}", e);
-            Ok(create_mock_quality_options())
+            println!("Job {} failed: {}", job.id(), e);
+            job.fail(e.to_string());
         }
     }
 }
 
-pub async fn download_video(request: DownloadRequest) -> Result<(Vec<u8>, String)> {
+/// Download `request`, reporting progress to `job` when run as one.
+pub async fn download_video(request: DownloadRequest, config: &AppConfig, job: Option<&JobHandle>) -> Result<DownloadOutput> {
     if !is_valid_youtube_url(&request.url) {
         return Err(anyhow!("Invalid YouTube URL"));
     }
 
+    let mut plan = plan_download(&request, config, job)?;
+
     // Check if yt-dlp is available
     if !check_ytdlp_available() {
         return Err(anyhow!("yt-dlp is not available on this system. Please install it to enable downloads."));
     }
+    let has_ffmpeg = ffmpeg::check_ffmpeg_available();
+    if let Some(purpose) = plan.requires_ffmpeg(&request).filter(|_| !has_ffmpeg) {
+        return Err(anyhow!("ffmpeg is not available on this system. Please install it to {}.", purpose));
+    }
+
+    let prepared = prepare_download(&request, &mut plan, config, job).await?;
 
     let temp_dir = std::env::temp_dir();
     let unique_id = Uuid::new_v4().to_string();
-    let output_template = temp_dir.join(format!("vidsaver_{}_%%(title)s.%%(ext)s", unique_id));
+    let args = ytdlp_args(&request, &plan, &prepared, has_ffmpeg, &temp_dir, &unique_id)?;
+    run_download(&args, &plan, &prepared, &temp_dir, &unique_id, job).await?;
+    let DownloadedFiles {
+        media: mut media_files,
+        info,
+        covers: cover_files,
+    } = collect_downloaded_files(&temp_dir, &unique_id, prepared.recording.is_some(), job).await?;
+
+    let DownloadPlan {
+        ranges,
+        audio_output,
+        loudness,
+        animation,
+        frame_options,
+        storyboard,
+        segment_options,
+        ..
+    } = plan;
+    let Prepared {
+        metadata,
+        frame_times,
+        storyboard_duration,
+        segments,
+        chapters,
+        ..
+    } = prepared;
+    let template = request.filename_template.as_ref().unwrap_or(&config.downloads.filename_template);
+    // The info JSON of the download, or the metadata fetched beforehand
+    let named = |name: &str| output_name(info.as_ref().or(metadata.as_ref()), template, name);
+
+    if request.r#type == "subtitles" {
+        let result = subtitle_package(&media_files, info.as_ref(), template).await;
+        for path in &media_files {
+            let _ = fs::remove_file(path).await;
+        }
+        let (data, filename) = result?;
+        println!("Subtitle download completed: {} ({} bytes)", filename, data.len());
+        return Ok(DownloadOutput {
+            data,
+            filename,
+            loudness: None,
+            segments: None,
+        });
+    }
+
+    let finish = AudioFinish {
+        output: audio_output,
+        source_acodec: info.as_ref().and_then(|info| info.acodec.clone()),
+        source_sample_rate: info.as_ref().and_then(|info| info.asr),
+        loudness,
+        tags: match &info {
+            Some(info) if tags_audio(&request, has_ffmpeg) => audio_output::audio_tags(info),
+            _ => Vec::new(),
+        },
+        cover: cover_files.iter().find(|path| path.extension().is_some_and(|ext| ext == "jpg")).cloned(),
+    };
+
+    if request.split_chapters {
+        let result = if finish.is_noop() {
+            Ok(())
+        } else {
+            jobs::report_stage(job, finish.stage());
+            finish_chapter_files(&mut media_files, &finish, &chapters).await
+        };
+        let result = match result {
+            Ok(()) => chapter_archive(&media_files, &chapters).await,
+            Err(e) => Err(e),
+        };
+        for path in media_files.iter().chain(&cover_files) {
+            let _ = fs::remove_file(path).await;
+        }
+        let archive = result?;
+
+        let filename = named("chapters.zip");
+        println!("Chapter archive completed: {} ({} bytes)", filename, archive.len());
+        return Ok(DownloadOutput {
+            data: archive,
+            filename,
+            loudness: None,
+            segments: None,
+        });
+    }
+
+    let downloaded_file = join_sections(media_files, &temp_dir, &unique_id, job).await?;
+
+    let finished = match downloaded_file {
+        Some(path) if !finish.is_noop() => {
+            jobs::report_stage(job, finish.stage());
+            finish_audio(&path, &finish, &[]).await.map(|(path, report)| (Some(path), report))
+        }
+        file => Ok((file, None)),
+    };
+    for cover in &cover_files {
+        let _ = fs::remove_file(cover).await;
+    }
+    let (downloaded_file, loudness) = finished?;
+
+    let downloaded_file = match (downloaded_file, segment_options, &segments) {
+        (Some(path), Some(options), Some(found)) if options.action == SegmentAction::Chapters && !found.is_empty() => {
+            jobs::report_stage(job, "chapters");
+            let duration = metadata.as_ref().and_then(|info| info.duration).unwrap_or_default();
+            Some(mark_segments(&path, found, &chapters, duration).await?)
+        }
+        (file, ..) => file,
+    };
+
+    if let (Some(options), Some(times)) = (frame_options, &frame_times) {
+        let Some(path) = downloaded_file else {
+            return Err(anyhow!("Downloaded file not found with unique_id: {}", unique_id));
+        };
+        let result = extract_frames(&path, times, options, job).await;
+        let _ = fs::remove_file(&path).await;
+        let mut frames = result?;
+
+        let (data, name) = if frames.len() == 1 {
+            let (_, data) = frames.remove(0);
+            (data, format!("frame.{}", options.format.ext()))
+        } else {
+            (archive::zip(&frames)?, "frames.zip".to_string())
+        };
+        let filename = named(&name);
+        println!("Frame extraction completed: {} ({} bytes)", filename, data.len());
+        return Ok(DownloadOutput {
+            data,
+            filename,
+            loudness: None,
+            segments: None,
+        });
+    }
+
+    if let (Some(storyboard), Some(duration)) = (&storyboard, storyboard_duration) {
+        let Some(path) = downloaded_file else {
+            return Err(anyhow!("Downloaded file not found with unique_id: {}", unique_id));
+        };
+        let result = build_storyboard(&path, storyboard, duration, job).await;
+        let _ = fs::remove_file(&path).await;
+        let (data, name) = result?;
+        let filename = named(&name);
+        println!("Storyboard completed: {} ({} bytes)", filename, data.len());
+        return Ok(DownloadOutput {
+            data,
+            filename,
+            loudness: None,
+            segments: None,
+        });
+    }
+
+    let downloaded_file = match (downloaded_file, &request.transcode) {
+        (Some(path), Some(options)) => {
+            let duration = clip::total_duration(&ranges, info.as_ref().and_then(|info| info.duration));
+            Some(transcode_video(&path, options, duration, config, job).await?)
+        }
+        (file, _) => file,
+    };
+
+    let downloaded_file = match (downloaded_file, &animation) {
+        (Some(path), Some(animation)) => {
+            let duration = clip::total_duration(&ranges, info.as_ref().and_then(|info| info.duration));
+            Some(animate_clip(&path, animation, duration, job).await?)
+        }
+        (file, _) => file,
+    };
+
+    match downloaded_file {
+        Some(file_path) => {
+            let file_data = fs::read(&file_path).await?;
+            // Clean up the temporary file
+            let _ = fs::remove_file(&file_path).await;
+
+            let filename = match &info {
+                Some(info) => sanitize_filename(&template.render(&filename_fields(info, &file_path))),
+                // Generate a clean filename if we couldn't read the metadata
+                None => match (request.r#type.as_str(), &finish.output) {
+                    (_, Some(output)) => format!("audio.{}", output.codec.ext()),
+                    ("video", _) => "video.mp4".to_string(),
+                    ("audio", _) => "audio.m4a".to_string(),
+                    (kind @ ("gif" | "webp"), _) => format!("animation.{}", kind),
+                    _ => "download".to_string(),
+                },
+            };
+
+            println!("Download completed: {} ({} bytes)", filename, file_data.len());
+            Ok(DownloadOutput {
+                data: file_data,
+                filename,
+                loudness,
+                segments,
+            })
+        }
+        None => {
+            // List all files in temp directory for debugging
+            let mut debug_entries = fs::read_dir(&temp_dir).await?;
+            println!("Files in temp directory:");
+            while let Some(entry) = debug_entries.next_entry().await? {
+                println!("  {}", entry.file_name().to_string_lossy());
+            }
+            Err(anyhow!("Downloaded file not found with unique_id: {}", unique_id))
+        }
+    }
+}
+
+/// A download request checked against itself and the config, before
+/// anything is fetched.
+struct DownloadPlan<'r> {
+    uses_constraints: bool,
+    /// As asked for; checked against the video's length once it is known,
+    /// and replaced by the parts to keep when segments are removed
+    ranges: Vec<TimeRange>,
+    subtitle_languages: Vec<SubtitleLanguage>,
+    audio_output: Option<AudioOutput>,
+    keeps_media: bool,
+    loudness: Option<LoudnessTarget>,
+    animation: Option<Animation>,
+    frame_options: Option<&'r FrameOptions>,
+    storyboard: Option<Storyboard>,
+    segment_options: Option<&'r SegmentOptions>,
+}
+
+impl DownloadPlan<'_> {
+    /// The first thing the download needs ffmpeg for, if any, to name when
+    /// it is missing.
+    fn requires_ffmpeg(&self, request: &DownloadRequest) -> Option<&'static str> {
+        [
+            // yt-dlp cuts even a single section with ffmpeg
+            (!self.ranges.is_empty(), "download clips"),
+            (request.split_chapters, "split chapters"),
+            (request.embed_subtitles.is_some(), "embed subtitles"),
+            (self.audio_output.is_some(), "convert audio"),
+            (self.loudness.is_some(), "normalize loudness"),
+            (request.transcode.is_some(), "transcode videos"),
+            (self.animation.is_some(), "create animations"),
+            (self.frame_options.is_some(), "extract frames"),
+            (self.storyboard.is_some(), "create storyboards"),
+            (self.segment_options.is_some(), "remove segments"),
+            (request.live.is_some(), "record live streams"),
+        ]
+        .into_iter()
+        .find_map(|(needed, purpose)| needed.then_some(purpose))
+    }
+
+    /// Presets, clip ranges, chapter splits, animation lengths, frame
+    /// positions and segments are checked against the real metadata up
+    /// front, as is whether an asked-for recording is live. Other media
+    /// downloads leave live streams to a match filter on the download itself.
+    fn needs_metadata(&self, request: &DownloadRequest) -> bool {
+        self.uses_constraints
+            || !self.ranges.is_empty()
+            || request.split_chapters
+            || self.segment_options.is_some()
+            || request.live.is_some()
+            || self.animation.is_some()
+            || self.frame_options.is_some()
+            || self.storyboard.is_some()
+    }
+}
+
+/// Tags and cover art are added by our own ffmpeg pass once the info JSON is
+/// in, so only the cover is fetched with the download.
+fn tags_audio(request: &DownloadRequest, has_ffmpeg: bool) -> bool {
+    request.embed_metadata && matches!(request.r#type.as_str(), "audio" | "mp3") && has_ffmpeg
+}
+
+/// Check every option of `request` against the others, before any lookup.
+fn plan_download<'r>(request: &'r DownloadRequest, config: &AppConfig, job: Option<&JobHandle>) -> Result<DownloadPlan<'r>, ApiError> {
+    let uses_constraints = request.preset.is_some() || request.constraints.is_some();
+    if uses_constraints && (request.video_quality.is_some() || request.audio_quality.is_some()) {
+        return Err(ApiError::BadRequest(
+            "Use either preset/constraints or videoQuality/audioQuality, not both".to_string(),
+        ));
+    }
+    // A video download merges videoQuality with an audio format, which only
+    // means what was asked for when both name a single format
+    let merges_qualities = request.r#type == "video" && request.video_quality.is_some();
+    let single = |quality: &Option<FormatSelector>| quality.as_ref().is_none_or(FormatSelector::is_single_format);
+    if merges_qualities && !(single(&request.video_quality) && single(&request.audio_quality)) {
+        return Err(ApiError::BadRequest(
+            "videoQuality and audioQuality must each be a single format for a video download, without '+' or '/'".to_string(),
+        ));
+    }
+
+    // Catch malformed ranges before spending a metadata lookup on them
+    let ranges = clip::requested_ranges(request.start, request.end, request.ranges.as_deref(), None)?;
+    if request.split_chapters && !ranges.is_empty() {
+        return Err(ApiError::BadRequest("splitChapters cannot be combined with start/end or ranges".to_string()));
+    }
+    let subtitle_languages = check_subtitle_options(request, uses_constraints || !ranges.is_empty())?;
+    let audio_output = requested_audio_output(request)?;
+    let is_audio = matches!(request.r#type.as_str(), "audio" | "mp3");
+    let keeps_media = matches!(request.r#type.as_str(), "video" | "audio" | "mp3");
+    let loudness = match &request.loudness {
+        Some(_) if !is_audio => {
+            return Err(ApiError::BadRequest("Loudness normalization only applies to audio and mp3 downloads".to_string()));
+        }
+        Some(options) => Some(LoudnessTarget::resolve(options, &config.loudness)?),
+        None => None,
+    };
+    if let Some(options) = &request.transcode {
+        check_transcode_options(request, options)?;
+    }
+    let animation = match (AnimationFormat::from_type(&request.r#type), &request.animation) {
+        (Some(format), options) => {
+            check_animation_options(request, uses_constraints)?;
+            Some(Animation::resolve(format, &options.unwrap_or_default(), &config.animations)?)
+        }
+        (None, Some(_)) => {
+            return Err(ApiError::BadRequest("animation only applies to gif and webp downloads".to_string()));
+        }
+        (None, None) => None,
+    };
+    let frame_options = match (request.r#type == "frames", &request.frames) {
+        (true, Some(options)) => {
+            check_frame_options(request, uses_constraints)?;
+            options.size.validate()?;
+            Some(options)
+        }
+        (true, None) => return Err(ApiError::BadRequest("frames downloads need frames options".to_string())),
+        (false, Some(_)) => {
+            return Err(ApiError::BadRequest("frames only applies to frames downloads".to_string()));
+        }
+        (false, None) => None,
+    };
+    let storyboard = match (request.r#type == "storyboard", &request.storyboard) {
+        (true, options) => {
+            check_storyboard_options(request, uses_constraints)?;
+            Some(Storyboard::resolve(&options.unwrap_or_default())?)
+        }
+        (false, Some(_)) => {
+            return Err(ApiError::BadRequest("storyboard only applies to storyboard downloads".to_string()));
+        }
+        (false, None) => None,
+    };
+    let segment_options = match &request.segments {
+        Some(_) if !keeps_media => {
+            return Err(ApiError::BadRequest("segments only apply to video, audio and mp3 downloads".to_string()));
+        }
+        Some(options) => {
+            check_segment_options(request, options)?;
+            Some(options)
+        }
+        None => None,
+    };
+    if request.live.is_some() {
+        check_live_options(request, keeps_media, job)?;
+    }
+
+    Ok(DownloadPlan {
+        uses_constraints,
+        ranges,
+        subtitle_languages,
+        audio_output,
+        keeps_media,
+        loudness,
+        animation,
+        frame_options,
+        storyboard,
+        segment_options,
+    })
+}
+
+/// What the download learned from the video's metadata before starting.
+struct Prepared {
+    metadata: Option<InfoDict>,
+    recording: Option<Recording>,
+    /// A resolved selection is already a complete `-f` value, including audio
+    resolved: Option<FormatSelector>,
+    frame_times: Option<Vec<f64>>,
+    storyboard_duration: Option<f64>,
+    segments: Option<Vec<Segment>>,
+    chapters: Vec<Chapter>,
+}
+
+impl Prepared {
+    /// Without a probe, a live stream is only spotted by the download; yt-dlp
+    /// would otherwise record it until it ends.
+    fn filters_live(&self, plan: &DownloadPlan) -> bool {
+        plan.keeps_media && self.metadata.is_none()
+    }
+}
+
+/// Fetch the metadata when the plan depends on it and check the plan against
+/// it, which may shorten `plan.ranges`.
+async fn prepare_download(
+    request: &DownloadRequest,
+    plan: &mut DownloadPlan<'_>,
+    config: &AppConfig,
+    job: Option<&JobHandle>,
+) -> Result<Prepared> {
+    let metadata = if plan.needs_metadata(request) {
+        Some(fetch_required_info(&request.url)?)
+    } else {
+        None
+    };
+    let duration = metadata.as_ref().and_then(|info| info.duration);
+
+    let recording = match &metadata {
+        Some(info) => live_recording(request, info, plan.keeps_media, config)?,
+        None => None,
+    };
+
+    let resolved = match &metadata {
+        Some(info) if plan.uses_constraints => {
+            let constraints = merge_constraints(request.preset, request.constraints.clone());
+            Some(resolve_selection(info, &request.r#type, &constraints, config)?.selector)
+        }
+        _ => None,
+    };
+
+    if duration.is_some() {
+        plan.ranges = clip::requested_ranges(request.start, request.end, request.ranges.as_deref(), duration)?;
+    }
+
+    if let Some(animation) = &plan.animation {
+        let length = clip::total_duration(&plan.ranges, duration);
+        if let Some(length) = length.filter(|length| *length > animation.max_duration) {
+            return Err(ApiError::BadRequest(format!(
+                "The clip is {:.1}s long; {} downloads can be at most {}s, so set start and end",
+                length,
+                animation.format.ext(),
+                animation.max_duration
+            ))
+            .into());
+        }
+    }
+
+    let frame_times = match plan.frame_options {
+        Some(options) => Some(options.frame_times(duration)?),
+        None => None,
+    };
+    let storyboard_duration = match plan.storyboard {
+        Some(_) => Some(
+            duration
+                .filter(|duration| *duration > 0.0)
+                .ok_or_else(|| ApiError::BadRequest("The video's duration is unknown, so no storyboard can be made".to_string()))?,
+        ),
+        None => None,
+    };
+
+    // Removed segments turn into the ranges around them, downloaded and
+    // joined like any clip
+    let segments = match (plan.segment_options, &metadata) {
+        (Some(options), Some(info)) => {
+            let found = find_segments(options, info, config, job).await?;
+            if options.action == SegmentAction::Remove && !found.is_empty() {
+                plan.ranges = segments::kept_ranges(&found, info.duration.unwrap_or_default())?;
+            }
+            Some(found)
+        }
+        _ => None,
+    };
+
+    let chapters = metadata.as_ref().map(|info| info.chapters.clone()).unwrap_or_default();
+    if request.split_chapters && chapters.is_empty() {
+        return Err(ApiError::BadRequest("This video has no chapters to split by".to_string()).into());
+    }
+
+    Ok(Prepared {
+        metadata,
+        recording,
+        resolved,
+        frame_times,
+        storyboard_duration,
+        segments,
+        chapters,
+    })
+}
+
+/// The full yt-dlp command line for the download. Everything it writes is
+/// named `vidsaver_{unique_id}.*` in `temp_dir`.
+fn ytdlp_args(
+    request: &DownloadRequest,
+    plan: &DownloadPlan,
+    prepared: &Prepared,
+    has_ffmpeg: bool,
+    temp_dir: &Path,
+    unique_id: &str,
+) -> Result<Vec<String>> {
+    // The on-disk name only carries the job ID; the user-facing name is built
+    // from the info JSON afterwards, so titles never touch the filesystem path.
+    // Each section of a multi-range clip is downloaded to its own file, named
+    // so that they sort chronologically.
+    let output_template = if plan.ranges.len() > 1 {
+        temp_dir.join(format!("vidsaver_{}.%(section_start)012.3f.%(ext)s", unique_id))
+    } else {
+        temp_dir.join(format!("vidsaver_{}.%(ext)s", unique_id))
+    };
 
     let mut args = vec![
         "--no-playlist".to_string(),
         "--no-warnings".to_string(),
-        "--newline".to_string(), // Better progress tracking
+        "--newline".to_string(), // One progress update per line
+        "--progress-template".to_string(),
+        ytdlp_progress::progress_template(),
+        "--write-info-json".to_string(),
         "-o".to_string(),
         output_template.to_string_lossy().to_string(),
     ];
 
+    args.extend(format_args(request, plan, prepared.resolved.as_ref(), has_ffmpeg)?);
+
+    if let Some(languages) = &request.embed_subtitles {
+        args.push("--write-subs".to_string());
+        args.push("--sub-langs".to_string());
+        args.push(subtitles::sub_langs_arg(languages));
+        args.push("--embed-subs".to_string());
+    }
+    if request.auto_subtitles && (request.r#type == "subtitles" || request.embed_subtitles.is_some()) {
+        args.push("--write-auto-subs".to_string());
+    }
+    if let Some(merge_format) = request.merge_format {
+        args.push("--merge-output-format".to_string());
+        args.push(merge_format.ext().to_string());
+    }
+
+    if request.split_chapters {
+        // Chapter files sit next to the full download and are told apart by
+        // their `.chapter.NNN` infix
+        let chapter_template = temp_dir.join(format!("vidsaver_{}.chapter.%(section_number)03d.%(ext)s", unique_id));
+        args.push("--split-chapters".to_string());
+        args.push("-o".to_string());
+        args.push(format!("chapter:{}", chapter_template.to_string_lossy()));
+    }
+    // Chapter times would not line up with a clip or a recording
+    if request.embed_chapters && plan.ranges.is_empty() && prepared.recording.is_none() && plan.keeps_media && has_ffmpeg {
+        args.push("--embed-chapters".to_string());
+    }
+    if tags_audio(request, has_ffmpeg) {
+        let cover_template = temp_dir.join(format!("vidsaver_{}.cover.%(ext)s", unique_id));
+        args.push("--write-thumbnail".to_string());
+        args.push("--convert-thumbnails".to_string());
+        args.push("jpg".to_string());
+        args.push("-o".to_string());
+        args.push(format!("thumbnail:{}", cover_template.to_string_lossy()));
+    }
+
+    for range in &plan.ranges {
+        args.push("--download-sections".to_string());
+        args.push(range.section_arg());
+    }
+    // Animations are short enough that a keyframe-snapped start would show
+    if !plan.ranges.is_empty() && (request.cut_mode == CutMode::Accurate || plan.animation.is_some()) {
+        args.push("--force-keyframes-at-cuts".to_string());
+    }
+
+    if let Some(recording) = &prepared.recording {
+        args.extend(recording.ytdlp_args());
+    }
+    if prepared.filters_live(plan) {
+        args.push("--match-filter".to_string());
+        args.push(live::NOT_LIVE_FILTER.to_string());
+    }
+
+    // Everything after `--` is positional, so the URL can never be read as an option
+    args.push("--".to_string());
+    args.push(request.url.clone());
+    Ok(args)
+}
+
+/// The `-f` selection, or for subtitle downloads the subtitle options, for
+/// each download type.
+fn format_args(
+    request: &DownloadRequest,
+    plan: &DownloadPlan,
+    resolved: Option<&FormatSelector>,
+    has_ffmpeg: bool,
+) -> Result<Vec<String>> {
+    if let Some(selector) = resolved.filter(|_| plan.keeps_media) {
+        println!("Downloading with resolved formats: {}", selector);
+        return Ok(vec!["-f".to_string(), selector.to_string()]);
+    }
+
+    let mut args = Vec::new();
     match request.r#type.as_str() {
         "video" => {
             // Download video with audio - use specific quality selections
@@ -151,105 +985,1001 @@ pub async fn download_video(request: DownloadRequest) -> Result<(Vec<u8>, String
                 }
             }
         }
-        "audio" => {
-            // Download audio only
+        "audio" | "mp3" => {
+            // Download audio only; conversion happens afterwards
             if let Some(audio_quality) = &request.audio_quality {
                 args.push("-f".to_string());
-                args.push(audio_quality.clone());
+                args.push(audio_quality.to_string());
                 println!("Downloading audio with quality: {}", audio_quality);
             } else {
+                // Prefer a source that can be remuxed into the target format
+                let selector = plan.audio_output.as_ref().map_or("bestaudio/best", |output| output.codec.source_selector());
                 args.push("-f".to_string());
-                args.push("bestaudio/best".to_string());
+                args.push(selector.to_string());
                 println!("Downloading best audio");
             }
         }
-        "mp3" => {
-            // Download and convert to MP3
-            if let Some(audio_quality) = &request.audio_quality {
-                args.push("-f".to_string());
-                args.push(audio_quality.clone());
-            } else {
-                args.push("-f".to_string());
-                args.push("bestaudio/best".to_string());
+        "gif" | "webp" => {
+            // Frames only; animations are small, so the source needn't be large
+            let selector = match &request.video_quality {
+                Some(video_quality) => video_quality.to_string(),
+                None => "bestvideo[height<=720]/best[height<=720]/best".to_string(),
+            };
+            println!("Downloading video for {}: {}", request.r#type, selector);
+            args.push("-f".to_string());
+            args.push(selector);
+        }
+        "frames" | "storyboard" => {
+            let selector = match &request.video_quality {
+                Some(video_quality) => video_quality.to_string(),
+                None => "bestvideo[height<=1080]/best[height<=1080]/best".to_string(),
+            };
+            println!("Downloading video for {}: {}", request.r#type, selector);
+            args.push("-f".to_string());
+            args.push(selector);
+        }
+        "subtitles" => {
+            // Only the subtitle files, converted when ffmpeg is around;
+            // without it the site's own format is delivered if it differs
+            args.push("--skip-download".to_string());
+            args.push("--write-subs".to_string());
+            args.push("--sub-langs".to_string());
+            args.push(subtitles::sub_langs_arg(&plan.subtitle_languages));
+            args.push("--sub-format".to_string());
+            args.push(format!("{}/best", request.subtitle_format.ext()));
+            if has_ffmpeg {
+                args.push("--convert-subs".to_string());
+                args.push(request.subtitle_format.ext().to_string());
             }
-            args.push("--extract-audio".to_string());
-            args.push("--audio-format".to_string());
-            args.push("mp3".to_string());
-            args.push("--audio-quality".to_string());
-            args.push("0".to_string()); // Best quality
-            println!("Downloading and converting to MP3");
+            println!("Downloading subtitles: {}", subtitles::sub_langs_arg(&plan.subtitle_languages));
         }
         _ => return Err(anyhow!("Invalid download type")),
     }
+    Ok(args)
+}
 
-    args.push(request.url.clone());
+/// Run yt-dlp, clearing up after it when it fails or turns out to have
+/// been handed a live stream.
+async fn run_download(
+    args: &[String],
+    plan: &DownloadPlan<'_>,
+    prepared: &Prepared,
+    temp_dir: &Path,
+    unique_id: &str,
+    job: Option<&JobHandle>,
+) -> Result<()> {
+    if let Some(recording) = &prepared.recording {
+        println!(
+            "Recording live stream {} for at most {:.0}s",
+            if recording.from_start { "from the start" } else { "from now" },
+            recording.max_duration
+        );
+        if let Some(job) = job {
+            job.allow_stop();
+        }
+        jobs::report_stage(job, if recording.wait_retry.is_some() { "waiting" } else { "recording" });
+    }
+    match run_ytdlp(args, job, prepared.recording.is_some()).await {
+        Ok(stdout) if prepared.filters_live(plan) && stdout.contains("does not pass filter") => {
+            remove_temp_files(temp_dir, unique_id).await;
+            Err(ApiError::BadRequest("This is a live stream or has not started yet; set live to record it".to_string()).into())
+        }
+        Ok(_) => Ok(()),
+        Err(e) => {
+            remove_temp_files(temp_dir, unique_id).await;
+            Err(e)
+        }
+    }
+}
+
+/// What yt-dlp left in the temp directory.
+struct DownloadedFiles {
+    /// Sorted, so sections and chapters come in order
+    media: Vec<PathBuf>,
+    info: Option<InfoDict>,
+    covers: Vec<PathBuf>,
+}
+
+/// Find the downloaded files and read the info JSON written next to them.
+/// A stopped `recording` is finished from its partial files.
+async fn collect_downloaded_files(
+    temp_dir: &Path,
+    unique_id: &str,
+    recording: bool,
+    job: Option<&JobHandle>,
+) -> Result<DownloadedFiles> {
+    let prefix = format!("vidsaver_{}.", unique_id);
+    let mut entries = fs::read_dir(temp_dir).await?;
+    let mut media_files = Vec::new();
+    let mut info_files = Vec::new();
+    let mut cover_files = Vec::new();
+    let mut partial_files = Vec::new();
 
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        let file_name_str = file_name.to_string_lossy();
+
+        if !file_name_str.starts_with(&prefix) {
+            continue;
+        }
+        if file_name_str.ends_with(".info.json") {
+            info_files.push(entry.path());
+        } else if file_name_str.contains(".cover.") {
+            cover_files.push(entry.path());
+        } else if file_name_str.ends_with(".part") {
+            partial_files.push(entry.path());
+        } else if file_name_str.contains(".part-Frag") || file_name_str.ends_with(".ytdl") {
+            // Fragments and resume state of an unfinished download
+            let _ = fs::remove_file(entry.path()).await;
+        } else {
+            media_files.push(entry.path());
+        }
+    }
+    media_files.sort();
+    partial_files.sort();
+
+    // A recording stopped before yt-dlp could finish it leaves its streams
+    // behind as `.part` files, which still hold everything recorded
+    if recording && media_files.is_empty() && !partial_files.is_empty() {
+        jobs::report_stage(job, "finalizing");
+        media_files.push(finalize_recording(&partial_files).await?);
+    } else {
+        for path in &partial_files {
+            let _ = fs::remove_file(path).await;
+        }
+    }
+    if media_files.is_empty() && job.is_some_and(JobHandle::is_stopped) {
+        return Err(anyhow!("The recording was stopped before anything was recorded"));
+    }
+
+    // Sections each get their own info JSON; any of them will do
+    let mut info = None;
+    for path in &info_files {
+        if info.is_none() {
+            info = read_info_json(path).await;
+        }
+        let _ = fs::remove_file(path).await;
+    }
+
+    Ok(DownloadedFiles {
+        media: media_files,
+        info,
+        covers: cover_files,
+    })
+}
+
+/// Join the sections of a multi-range clip into one file.
+async fn join_sections(mut media_files: Vec<PathBuf>, temp_dir: &Path, unique_id: &str, job: Option<&JobHandle>) -> Result<Option<PathBuf>> {
+    if media_files.len() <= 1 {
+        return Ok(media_files.pop());
+    }
+
+    jobs::report_stage(job, "joining");
+    let ext = media_files[0].extension().map(|ext| ext.to_string_lossy().to_string()).unwrap_or_default();
+    let joined = temp_dir.join(format!("vidsaver_{}.clip.{}", unique_id, ext));
+    let result = ffmpeg::concat(&media_files, &joined).await;
+    for path in &media_files {
+        let _ = fs::remove_file(path).await;
+    }
+    result?;
+    Ok(Some(joined))
+}
+
+/// The filename for a generated `name` such as `chapters.zip`, following
+/// the template when the video's info is known.
+fn output_name(info: Option<&InfoDict>, template: &FilenameTemplate, name: &str) -> String {
+    match info {
+        Some(info) => sanitize_filename(&template.render(&filename_fields(info, Path::new(name)))),
+        None => name.to_string(),
+    }
+}
+
+/// The conversion an `audio` or `mp3` download asked for, if any.
+fn requested_audio_output(request: &DownloadRequest) -> Result<Option<AudioOutput>, ApiError> {
+    let has_options = request.audio_bitrate.is_some()
+        || request.audio_vbr.is_some()
+        || request.sample_rate.is_some()
+        || request.audio_channels.is_some();
+
+    let codec = match (request.r#type.as_str(), request.audio_format) {
+        ("mp3", None | Some(AudioCodec::Mp3)) => AudioCodec::Mp3,
+        ("mp3", Some(_)) => {
+            return Err(ApiError::BadRequest("mp3 downloads always produce mp3; use type audio with audioFormat".to_string()));
+        }
+        ("audio", Some(codec)) => codec,
+        ("audio", None) if has_options => {
+            return Err(ApiError::BadRequest("Audio encoding options need an audioFormat".to_string()));
+        }
+        ("audio", None) => return Ok(None),
+        _ if has_options || request.audio_format.is_some() => {
+            return Err(ApiError::BadRequest("Audio format options only apply to audio and mp3 downloads".to_string()));
+        }
+        _ => return Ok(None),
+    };
+
+    AudioOutput::new(
+        codec,
+        request.audio_bitrate,
+        request.audio_vbr,
+        request.sample_rate,
+        request.audio_channels,
+    )
+    .map(Some)
+}
+
+/// Post-processing for audio downloads: conversion, tags and cover art, all
+/// in one ffmpeg pass, preceded by a measuring pass when normalizing.
+struct AudioFinish {
+    output: Option<AudioOutput>,
+    source_acodec: Option<String>,
+    source_sample_rate: Option<u32>,
+    loudness: Option<LoudnessTarget>,
+    tags: Vec<(&'static str, String)>,
+    cover: Option<PathBuf>,
+}
+
+impl AudioFinish {
+    fn is_noop(&self) -> bool {
+        self.output.is_none() && self.loudness.is_none() && self.tags.is_empty()
+    }
+
+    fn stage(&self) -> &'static str {
+        if self.loudness.is_some() {
+            "normalizing"
+        } else {
+            "converting"
+        }
+    }
+
+    /// Normalizing re-encodes, so it needs an encoder even when no format
+    /// was asked for; the source codec is kept where possible.
+    fn output(&self) -> Option<AudioOutput> {
+        match (&self.output, self.loudness) {
+            (Some(output), _) => Some(output.clone()),
+            (None, Some(_)) => {
+                let codec = self.source_acodec.as_deref().and_then(AudioCodec::from_acodec).unwrap_or(AudioCodec::Aac);
+                AudioOutput::new(codec, None, None, None, None).ok()
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Convert, remux, normalize or retag a downloaded file. `overrides` replace
+/// tags of the same name. The source is removed either way.
+async fn finish_audio(
+    path: &Path,
+    finish: &AudioFinish,
+    overrides: &[(&'static str, String)],
+) -> Result<(PathBuf, Option<LoudnessReport>)> {
+    let result = convert_downloaded_audio(path, finish, overrides).await;
+    let _ = fs::remove_file(path).await;
+    result
+}
+
+async fn convert_downloaded_audio(
+    path: &Path,
+    finish: &AudioFinish,
+    overrides: &[(&'static str, String)],
+) -> Result<(PathBuf, Option<LoudnessReport>)> {
+    let source_acodec = finish.source_acodec.as_deref();
+    let output = finish.output();
+
+    let measured = match &finish.loudness {
+        Some(target) => {
+            let log = ffmpeg::analyze_audio(path, &target.measure_filter()).await?;
+            let stats = LoudnormStats::parse(&log).ok_or_else(|| anyhow!("ffmpeg reported no loudness measurement"))?;
+            Some((target, stats))
+        }
+        None => None,
+    };
+    // Silence measures -inf and is passed through untouched
+    let filter = measured
+        .as_ref()
+        .filter(|(_, stats)| stats.is_audible())
+        .map(|(target, stats)| target.apply_filter(stats));
+
+    let (ext, codec_args) = match &output {
+        Some(output) if filter.is_some() => {
+            // A filter means re-encoding, whatever the source codec
+            let mut args = output.ffmpeg_args(None);
+            // loudnorm upsamples to 192 kHz; go back to the source rate,
+            // or 48 kHz which every encoder accepts
+            if output.sample_rate.is_none() {
+                let rate = match output.codec {
+                    AudioCodec::Opus => 48000,
+                    _ => finish.source_sample_rate.unwrap_or(48000),
+                };
+                args.extend(["-ar".to_string(), rate.to_string()]);
+            }
+            (output.codec.ext().to_string(), args)
+        }
+        Some(output) => (output.codec.ext().to_string(), output.ffmpeg_args(source_acodec)),
+        None => (
+            path.extension().unwrap_or_default().to_string_lossy().to_string(),
+            vec!["-c:a".to_string(), "copy".to_string()],
+        ),
+    };
+    // `vidsaver_{id}.webm` becomes `vidsaver_{id}.audio.opus`
+    let target = path.with_extension(format!("audio.{}", ext));
+    let cover = finish.cover.as_deref().filter(|_| audio_output::supports_cover_art(&ext));
+
+    let mut tags: Vec<(&str, String)> = finish
+        .tags
+        .iter()
+        .filter(|(key, _)| !overrides.iter().any(|(name, _)| name == key))
+        .cloned()
+        .collect();
+    tags.extend(overrides.iter().cloned());
+
+    let action = match &output {
+        _ if filter.is_some() => "Normalizing",
+        Some(output) if !output.is_remux(source_acodec) => "Encoding",
+        Some(_) => "Remuxing",
+        None => "Tagging",
+    };
+    println!("{} {} to {} ({} tags, cover: {})", action, path.display(), ext, tags.len(), cover.is_some());
+
+    let log = ffmpeg::convert_audio(path, cover, &target, &codec_args, &tags, filter.as_deref()).await?;
+    let applied = filter.as_ref().and_then(|_| LoudnormStats::parse(&log));
+    let report = measured.map(|(target, stats)| LoudnessReport::new(target, &stats, applied.as_ref()));
+    Ok((target, report))
+}
+
+/// Finish the per-chapter files of a split download in place, tagging each
+/// with its chapter title and number.
+async fn finish_chapter_files(files: &mut [PathBuf], finish: &AudioFinish, chapters: &[Chapter]) -> Result<()> {
+    for path in files.iter_mut() {
+        let Some(number) = chapter_number(path) else {
+            continue;
+        };
+        let mut overrides = Vec::new();
+        if !finish.tags.is_empty() {
+            overrides.push(("track", number.to_string()));
+            if let Some(chapter) = chapters.get(number.wrapping_sub(1)) {
+                overrides.push(("title", chapter_title(chapter, number)));
+            }
+        }
+        *path = finish_audio(path, finish, &overrides).await?.0;
+    }
+    Ok(())
+}
+
+fn check_transcode_options(request: &DownloadRequest, options: &TranscodeOptions) -> Result<(), ApiError> {
+    if request.r#type != "video" {
+        return Err(ApiError::BadRequest("transcode only applies to video downloads".to_string()));
+    }
+    if request.split_chapters {
+        return Err(ApiError::BadRequest("transcode cannot be combined with splitChapters".to_string()));
+    }
+    if request.merge_format.is_some() {
+        return Err(ApiError::BadRequest("transcode always produces MP4; leave out mergeFormat".to_string()));
+    }
+    options.validate()
+}
+
+fn check_animation_options(request: &DownloadRequest, uses_constraints: bool) -> Result<(), ApiError> {
+    if uses_constraints || request.split_chapters || request.ranges.is_some() {
+        return Err(ApiError::BadRequest(
+            "gif and webp downloads take a single start/end, without presets, constraints, ranges or splitChapters"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Turn a downloaded clip into an animation, rejecting it when it comes out
+/// over the size limit. The source is removed either way.
+async fn animate_clip(path: &Path, animation: &Animation, duration: Option<f64>, job: Option<&JobHandle>) -> Result<PathBuf> {
+    // `vidsaver_{id}.webm` becomes `vidsaver_{id}.animation.gif`
+    let target = path.with_extension(format!("animation.{}", animation.format.ext()));
+    println!(
+        "Animating {} to {} ({}px, {} fps)",
+        path.display(),
+        animation.format.ext(),
+        animation.width,
+        animation.fps
+    );
+
+    let result = ffmpeg::animate(path, &target, animation, duration, job).await;
+    let _ = fs::remove_file(path).await;
+    let result = match result {
+        Ok(()) => check_animation_size(&target, animation).await,
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(&target).await;
+    }
+    result.map(|_| target)
+}
+
+async fn check_animation_size(path: &Path, animation: &Animation) -> Result<()> {
+    let size = fs::metadata(path).await?.len();
+    if size > animation.max_filesize {
+        return Err(ApiError::BadRequest(format!(
+            "The {} came out at {} bytes, over the {} byte limit; lower the width, fps or clip length",
+            animation.format.ext(),
+            size,
+            animation.max_filesize
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+fn check_frame_options(request: &DownloadRequest, uses_constraints: bool) -> Result<(), ApiError> {
+    let trims = request.start.is_some() || request.end.is_some() || request.ranges.is_some();
+    if uses_constraints || trims || request.split_chapters {
+        return Err(ApiError::BadRequest(
+            "frames downloads take timestamps, without presets, constraints, start/end, ranges or splitChapters"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Save the frames at `times` as images named by number and position.
+async fn extract_frames(
+    path: &Path,
+    times: &[f64],
+    options: &FrameOptions,
+    job: Option<&JobHandle>,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let filter = options.size.scale_filter();
+    let codec_args = options.format.ffmpeg_args();
+    let mut frames = Vec::with_capacity(times.len());
+
+    for (i, seconds) in times.iter().enumerate() {
+        if job.is_some_and(JobHandle::is_cancelled) {
+            return Err(anyhow!("Cancelled while extracting frames"));
+        }
+        jobs::report(
+            job,
+            JobProgress {
+                percent: Some((i * 1000 / times.len()) as f64 / 10.0),
+                ..JobProgress::stage("frames")
+            },
+        );
+
+        // `vidsaver_{id}.mp4` gives `vidsaver_{id}.frame.001.jpg`
+        let output = path.with_extension(format!("frame.{:03}.{}", i + 1, options.format.ext()));
+        let result = ffmpeg::extract_frame(path, *seconds, &output, filter.as_deref(), &codec_args).await;
+        let data = match result {
+            Ok(()) => fs::read(&output).await.map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        let _ = fs::remove_file(&output).await;
+        frames.push((images::frame_filename(i + 1, *seconds, options.format), data?));
+    }
+    Ok(frames)
+}
+
+fn check_storyboard_options(request: &DownloadRequest, uses_constraints: bool) -> Result<(), ApiError> {
+    let trims = request.start.is_some() || request.end.is_some() || request.ranges.is_some();
+    if uses_constraints || trims || request.split_chapters {
+        return Err(ApiError::BadRequest(
+            "storyboard downloads cover the whole video, without presets, constraints, start/end, ranges or splitChapters"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_live_options(request: &DownloadRequest, keeps_media: bool, job: Option<&JobHandle>) -> Result<(), ApiError> {
+    let trims = request.start.is_some() || request.end.is_some() || request.ranges.is_some();
+    if !keeps_media || trims || request.split_chapters || request.segments.is_some() {
+        return Err(ApiError::BadRequest(
+            "live recordings are video, audio or mp3 downloads, without start/end, ranges, splitChapters or segments"
+                .to_string(),
+        ));
+    }
+    // Recordings run for hours and can only be stopped through their job
+    if job.is_none() {
+        return Err(ApiError::BadRequest("Live streams are recorded as jobs; use POST /api/jobs".to_string()));
+    }
+    Ok(())
+}
+
+/// The recording to make when the video is live or about to be. Asking for
+/// one of a video that is not live is harmless; the stream may have just
+/// ended, and the video is downloaded as usual.
+fn live_recording(
+    request: &DownloadRequest,
+    info: &InfoDict,
+    keeps_media: bool,
+    config: &AppConfig,
+) -> Result<Option<Recording>, ApiError> {
+    let status = LiveStatus::of(info);
+    if !status.is_recordable() {
+        if request.live.is_some() {
+            println!("Not a live stream ({:?}); downloading as a regular video", status);
+        }
+        return Ok(None);
+    }
+    if !keeps_media {
+        return Err(ApiError::BadRequest("Live streams can only be recorded as video, audio or mp3".to_string()));
+    }
+    let Some(options) = &request.live else {
+        return Err(ApiError::BadRequest(match status {
+            LiveStatus::IsUpcoming => "This stream has not started yet; set live with waitForStart to record it".to_string(),
+            _ => "This is a live stream; set live to record it".to_string(),
+        }));
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+    let bitrate = live::estimated_bitrate(info, request.r#type != "video");
+    Recording::resolve(options, status, info.release_timestamp, now, bitrate, config).map(Some)
+}
+
+/// Turn the `.part` files of a stopped recording into one playable file.
+/// Remuxing rebuilds the index a recording cut short never got.
+async fn finalize_recording(partial_files: &[PathBuf]) -> Result<PathBuf> {
+    // `vidsaver_{id}.f299.mp4.part` becomes `vidsaver_{id}.recording.mp4`
+    let first = partial_files[0].with_extension("");
+    let ext = first.extension().map(|ext| ext.to_string_lossy().to_string()).unwrap_or_else(|| "mp4".to_string());
+    let name = first.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let unique_id = name.split('.').next().unwrap_or_default().to_string();
+    let target = first.with_file_name(format!("{}.recording.{}", unique_id, ext));
+    println!("Finalizing {} partial recording file(s) into {:?}", partial_files.len(), target);
+
+    let result = ffmpeg::remux(partial_files, &target).await;
+    for path in partial_files {
+        let _ = fs::remove_file(path).await;
+    }
+    if let Err(e) = result {
+        let _ = fs::remove_file(&target).await;
+        return Err(e);
+    }
+    Ok(target)
+}
+
+fn check_segment_options(request: &DownloadRequest, options: &SegmentOptions) -> Result<(), ApiError> {
+    let trims = request.start.is_some() || request.end.is_some() || request.ranges.is_some();
+    if trims || request.split_chapters {
+        return Err(ApiError::BadRequest(
+            "segments cannot be combined with start/end, ranges or splitChapters".to_string(),
+        ));
+    }
+    options.validate()
+}
+
+/// Look up the video's segments and fit them to its length.
+async fn find_segments(
+    options: &SegmentOptions,
+    info: &InfoDict,
+    config: &AppConfig,
+    job: Option<&JobHandle>,
+) -> Result<Vec<Segment>> {
+    let duration = info
+        .duration
+        .filter(|duration| *duration > 0.0)
+        .ok_or_else(|| ApiError::BadRequest("The video's duration is unknown, so segments cannot be placed".to_string()))?;
+    let video_id = info.id.as_deref().ok_or_else(|| anyhow!("The video's ID is unknown"))?;
+
+    jobs::report_stage(job, "segments");
+    let found = options.provider(&config.segments).segments(video_id).await?;
+    let found = segments::merge(&found, duration);
+    println!("Found {} segments for {}", found.len(), video_id);
+    Ok(found)
+}
+
+/// Give each segment its own chapter. The source is removed either way.
+async fn mark_segments(path: &Path, found: &[Segment], chapters: &[Chapter], duration: f64) -> Result<PathBuf> {
+    let ext = path.extension().map(|ext| ext.to_string_lossy().to_string()).unwrap_or_default();
+    // `vidsaver_{id}.mp4` becomes `vidsaver_{id}.marked.mp4`
+    let target = path.with_extension(format!("marked.{}", ext));
+    let marked = segments::chapters_with_segments(found, chapters, duration);
+    println!("Marking {} segments as chapters", found.len());
+
+    let result = ffmpeg::set_chapters(path, &segments::ffmetadata(&marked), &target).await;
+    let _ = fs::remove_file(path).await;
+    if let Err(e) = result {
+        let _ = fs::remove_file(&target).await;
+        return Err(e);
+    }
+    Ok(target)
+}
+
+/// Sample one frame per tile, stamp and scale each, then lay them out in a
+/// grid. Returns the sheet, or a ZIP of it and a WebVTT track pointing into
+/// it, with its file name.
+async fn build_storyboard(
+    path: &Path,
+    storyboard: &Storyboard,
+    duration: f64,
+    job: Option<&JobHandle>,
+) -> Result<(Vec<u8>, String)> {
+    let times = storyboard.frame_times(duration);
+    // Tiles stay lossless until the sheet itself is encoded
+    let png_args = images::ImageFormat::Png.ffmpeg_args();
+    let mut tiles = Vec::with_capacity(times.len());
+
+    let result = async {
+        for (i, seconds) in times.iter().enumerate() {
+            if job.is_some_and(JobHandle::is_cancelled) {
+                return Err(anyhow!("Cancelled while building the storyboard"));
+            }
+            jobs::report(
+                job,
+                JobProgress {
+                    percent: Some((i * 1000 / times.len()) as f64 / 10.0),
+                    ..JobProgress::stage("frames")
+                },
+            );
+
+            // `vidsaver_{id}.mp4` gives `vidsaver_{id}.tile.001.png`
+            let tile = path.with_extension(format!("tile.{:03}.png", i + 1));
+            tiles.push(tile.clone());
+            let filter = storyboard.tile_filter(*seconds);
+            ffmpeg::extract_frame(path, *seconds, &tile, Some(&filter), &png_args).await?;
+        }
+
+        jobs::report_stage(job, "tiling");
+        let first = fs::read(&tiles[0]).await?;
+        let (_, tile_height) =
+            images::png_dimensions(&first).ok_or_else(|| anyhow!("Could not read the storyboard tile size"))?;
+        let sheet = path.with_extension(format!("storyboard.{}", storyboard.format.ext()));
+        let pattern = path.with_extension("tile.%03d.png");
+        let tiled = ffmpeg::tile_images(&pattern, &sheet, &storyboard.grid_filter(), &storyboard.format.ffmpeg_args()).await;
+        let data = match tiled {
+            Ok(()) => fs::read(&sheet).await.map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        let _ = fs::remove_file(&sheet).await;
+
+        let sheet_name = format!("storyboard.{}", storyboard.format.ext());
+        if !storyboard.vtt {
+            return Ok((data?, sheet_name));
+        }
+        let vtt = storyboard.webvtt(&sheet_name, duration, tile_height);
+        let zip = archive::zip(&[(sheet_name, data?), ("storyboard.vtt".to_string(), vtt.into_bytes())])?;
+        Ok((zip, "storyboard.zip".to_string()))
+    }
+    .await;
+
+    for tile in &tiles {
+        let _ = fs::remove_file(tile).await;
+    }
+    result
+}
+
+/// Build a storyboard from a video already at hand, such as a finished job's
+/// file, instead of downloading it again. `source_name` supplies the
+/// extension ffmpeg goes by and the start of the returned file name.
+pub async fn storyboard_from_file(
+    data: &[u8],
+    source_name: &str,
+    options: &StoryboardOptions,
+) -> Result<(Vec<u8>, String)> {
+    let storyboard = Storyboard::resolve(options)?;
+    if !ffmpeg::check_ffmpeg_available() {
+        return Err(anyhow!("ffmpeg is not available on this system. Please install it to create storyboards."));
+    }
+
+    let source = Path::new(source_name);
+    let ext = source.extension().map(|ext| ext.to_string_lossy().to_string()).unwrap_or_else(|| "mp4".to_string());
+    // Only the extension is taken from the name; the path is our own
+    let path = std::env::temp_dir().join(format!("vidsaver_{}.{}", Uuid::new_v4(), sanitize_filename(&ext)));
+    fs::write(&path, data).await?;
+
+    let result = async {
+        let duration = ffmpeg::probe_duration(&path)
+            .await?
+            .filter(|duration| *duration > 0.0)
+            .ok_or_else(|| ApiError::BadRequest("The video's duration is unknown, so no storyboard can be made".to_string()))?;
+        build_storyboard(&path, &storyboard, duration, None).await
+    }
+    .await;
+    let _ = fs::remove_file(&path).await;
+
+    let (data, name) = result?;
+    let stem = source.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let filename = sanitize_filename(format!("{} {}", stem, name).trim_start());
+    println!("Storyboard of {} completed: {} ({} bytes)", source_name, filename, data.len());
+    Ok((data, filename))
+}
+
+/// Re-encode a video download to a device profile. The source is removed
+/// either way.
+async fn transcode_video(
+    path: &Path,
+    options: &TranscodeOptions,
+    duration: Option<f64>,
+    config: &AppConfig,
+    job: Option<&JobHandle>,
+) -> Result<PathBuf> {
+    // `vidsaver_{id}.webm` becomes `vidsaver_{id}.transcoded.mp4`
+    let target = path.with_extension("transcoded.mp4");
+    println!("Transcoding {} to {:?}", path.display(), options.profile);
+
+    // Bitmap subtitles can't become mov_text, so those are left out
+    let subtitles = ffmpeg::text_subtitle_streams(path).await.unwrap_or_default();
+    let args = options.ffmpeg_args(&config.transcode, &subtitles);
+    let result = ffmpeg::transcode(path, &target, &args, duration, "transcoding", job).await;
+    let _ = fs::remove_file(path).await;
+    if result.is_err() {
+        let _ = fs::remove_file(&target).await;
+    }
+    result.map(|_| target)
+}
+
+/// How long a stopped recording gets to write out what it has.
+const STOP_GRACE: Duration = Duration::from_secs(60);
+
+/// Run yt-dlp to completion, feeding its progress lines to `job`, and return
+/// the rest of its output. A cancelled job kills the process; stopping a
+/// `recording` interrupts it instead, so the file recorded so far is finished
+/// properly, and kills it if finishing takes longer than `STOP_GRACE`.
+async fn run_ytdlp(args: &[String], job: Option<&JobHandle>, recording: bool) -> Result<String> {
     println!("Executing yt-dlp with args: {:?}", args);
 
-    // Execute yt-dlp
-    let output = Command::new("yt-dlp")
-        .args(&args)
-        .output()?;
+    let mut child = tokio::process::Command::new("yt-dlp")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
 
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr = tokio::spawn(async move {
+        let mut log = String::new();
+        let _ = stderr.read_to_string(&mut log).await;
+        log
+    });
+
+    let mut lines = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+    let mut stdout = String::new();
+    let mut stopping = false;
+    let stop_deadline = tokio::time::sleep(Duration::MAX);
+    tokio::pin!(stop_deadline);
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            _ = jobs::wait_cancelled(job) => {
+                let _ = child.kill().await;
+                return Err(anyhow!("Download cancelled"));
+            }
+            _ = jobs::wait_stopped(job), if recording && !stopping => {
+                println!("Stopping recording");
+                interrupt(&mut child);
+                stopping = true;
+                stop_deadline.as_mut().reset(tokio::time::Instant::now() + STOP_GRACE);
+                jobs::report_stage(job, "finalizing");
+                continue;
+            }
+            _ = &mut stop_deadline, if stopping => {
+                println!("yt-dlp did not finish the recording within {:?}; killing it", STOP_GRACE);
+                let _ = child.kill().await;
+                break;
+            }
+        };
+        let Some(line) = line else {
+            break;
+        };
+        match ytdlp_progress::parse_line(&line) {
+            Some(progress) => jobs::report(job, progress),
+            None => {
+                // ffmpeg records live streams without progress lines; this
+                // is yt-dlp announcing the file it records to
+                if recording && !stopping && line.starts_with("[download]") {
+                    jobs::report_stage(job, "recording");
+                }
+                stdout.push_str(&line);
+                stdout.push('\n');
+            }
+        }
+    }
+
+    let status = child.wait().await?;
+    let error = stderr.await.unwrap_or_default();
+    // An interrupted yt-dlp exits with an error even when the recording was
+    // saved; whether it was is up to the files left behind
+    if stopping {
+        println!("Recording stopped; yt-dlp exited with {}", status);
+        return Ok(stdout);
+    }
+    if !status.success() {
         println!("yt-dlp stderr: {}", error);
         println!("yt-dlp stdout: {}", stdout);
         return Err(anyhow!("Download failed: {}", error));
     }
+    Ok(stdout)
+}
 
-    // Find the downloaded file
-    let mut entries = fs::read_dir(&temp_dir).await?;
-    let mut downloaded_file = None;
-    let mut filename = String::new();
+/// Clear out whatever a failed or cancelled download left behind, which for
+/// a long recording can be a lot.
+async fn remove_temp_files(temp_dir: &Path, unique_id: &str) {
+    let prefix = format!("vidsaver_{}.", unique_id);
+    let Ok(mut entries) = fs::read_dir(temp_dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            let _ = fs::remove_file(entry.path()).await;
+        }
+    }
+}
 
-    while let Some(entry) = entries.next_entry().await? {
-        let file_name = entry.file_name();
-        let file_name_str = file_name.to_string_lossy();
-        
-        if file_name_str.starts_with(&format!("vidsaver_{}", unique_id)) {
-            downloaded_file = Some(entry.path());
-            // Extract just the filename without the unique prefix
-            filename = file_name_str
-                .strip_prefix(&format!("vidsaver_{}_", unique_id))
-                .unwrap_or(&file_name_str)
-                .to_string();
-            break;
+/// Send SIGINT, which yt-dlp handles like Ctrl+C: it has ffmpeg write out
+/// the recording before exiting.
+#[cfg(unix)]
+fn interrupt(child: &mut tokio::process::Child) {
+    // `id` is only gone once the child has been reaped, so the pid is still ours
+    if let Some(pid) = child.id() {
+        // SAFETY: kill(2) only takes plain integers and touches no memory
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGINT);
         }
     }
+}
 
-    match downloaded_file {
-        Some(file_path) => {
-            let file_data = fs::read(&file_path).await?;
-            // Clean up the temporary file
-            let _ = fs::remove_file(&file_path).await;
-            
-            // Generate a clean filename if we couldn't extract one
-            if filename.is_empty() {
-                filename = match request.r#type.as_str() {
-                    "video" => "video.mp4".to_string(),
-                    "audio" => "audio.m4a".to_string(),
-                    "mp3" => "audio.mp3".to_string(),
-                    _ => "download".to_string(),
-                };
-            }
-            
-            println!("Download completed: {} ({} bytes)", filename, file_data.len());
-            Ok((file_data, filename))
+/// Without signals the recording can only be cut off; the `.part` files it
+/// leaves are remuxed like any other stopped recording.
+#[cfg(not(unix))]
+fn interrupt(child: &mut tokio::process::Child) {
+    let _ = child.start_kill();
+}
+
+/// `vidsaver_{id}.chapter.007.mp4` is chapter 7.
+fn chapter_number(path: &Path) -> Option<usize> {
+    path.file_name()?
+        .to_string_lossy()
+        .split_once(".chapter.")
+        .and_then(|(_, rest)| rest.split('.').next())
+        .and_then(|number| number.parse().ok())
+}
+
+/// Validate the subtitle options and return the languages for a `subtitles`
+/// download.
+fn check_subtitle_options(request: &DownloadRequest, trims_media: bool) -> Result<Vec<SubtitleLanguage>, ApiError> {
+    for languages in [&request.subtitle_languages, &request.embed_subtitles].into_iter().flatten() {
+        if languages.len() > subtitles::MAX_LANGUAGES {
+            return Err(ApiError::BadRequest(format!(
+                "At most {} subtitle languages can be requested at once",
+                subtitles::MAX_LANGUAGES
+            )));
         }
-        None => {
-            // List all files in temp directory for debugging
-            let mut debug_entries = fs::read_dir(&temp_dir).await?;
-            println!("Files in temp directory:");
-            while let Some(entry) = debug_entries.next_entry().await? {
-                println!("  {}", entry.file_name().to_string_lossy());
+    }
+
+    if request.r#type != "subtitles" {
+        if request.embed_subtitles.is_some() && request.r#type != "video" {
+            return Err(ApiError::BadRequest("embedSubtitles only applies to video downloads".to_string()));
+        }
+        return Ok(Vec::new());
+    }
+
+    if trims_media || request.split_chapters {
+        return Err(ApiError::BadRequest(
+            "Presets, constraints, ranges and splitChapters do not apply to subtitle downloads".to_string(),
+        ));
+    }
+    match &request.subtitle_languages {
+        Some(languages) if !languages.is_empty() => Ok(languages.clone()),
+        _ => Err(ApiError::BadRequest("subtitleLanguages is required for subtitle downloads".to_string())),
+    }
+}
+
+/// A single subtitle file as-is, or several bundled in a ZIP. Files are named
+/// `<template>.<language>.<ext>` like yt-dlp's own output.
+async fn subtitle_package(
+    files: &[PathBuf],
+    info: Option<&InfoDict>,
+    template: &FilenameTemplate,
+) -> Result<(Vec<u8>, String)> {
+    let mut entries = Vec::new();
+    for path in files {
+        // `vidsaver_{id}.{language}.{ext}`
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut parts = file_name.rsplitn(3, '.');
+        let (Some(ext), Some(language)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let suffix = format!("{}.{}", language, ext);
+        let name = match info {
+            Some(info) => {
+                let mut fields = filename_fields(info, path);
+                fields.insert("ext", suffix);
+                sanitize_filename(&template.render(&fields))
             }
-            Err(anyhow!("Downloaded file not found with unique_id: {}", unique_id))
+            None => format!("subtitles.{}", suffix),
+        };
+        entries.push((name, fs::read(path).await?));
+    }
+
+    match entries.len() {
+        0 => Err(ApiError::NotFound("No subtitles found for the requested languages".to_string()).into()),
+        1 => {
+            let (name, data) = entries.remove(0);
+            Ok((data, name))
+        }
+        _ => {
+            let name = match info {
+                Some(info) => sanitize_filename(&template.render(&filename_fields(info, Path::new("subtitles.zip")))),
+                None => "subtitles.zip".to_string(),
+            };
+            Ok((archive::zip(&entries)?, name))
+        }
+    }
+}
+
+/// Bundle the per-chapter files written by `--split-chapters` into a ZIP,
+/// with entries named after the chapters.
+async fn chapter_archive(media_files: &[PathBuf], chapters: &[Chapter]) -> Result<Vec<u8>> {
+    let mut entries = Vec::new();
+    for path in media_files {
+        let Some(number) = chapter_number(path) else {
+            continue;
+        };
+
+        let ext = path.extension().unwrap_or_default().to_string_lossy();
+        let title = chapters
+            .get(number.wrapping_sub(1))
+            .map(|chapter| chapter_title(chapter, number))
+            .unwrap_or_else(|| format!("Chapter {}", number));
+        let name = sanitize_filename(&format!("{:02} - {}.{}", number, title, ext));
+        entries.push((name, fs::read(path).await?));
+    }
+
+    if entries.is_empty() {
+        return Err(anyhow!("yt-dlp did not produce any chapter files"));
+    }
+    archive::zip(&entries)
+}
+
+fn video_chapters(chapters: &[Chapter]) -> Vec<VideoChapter> {
+    chapters
+        .iter()
+        .enumerate()
+        .map(|(i, chapter)| VideoChapter {
+            title: chapter_title(chapter, i + 1),
+            start_time: chapter.start_time,
+            end_time: chapter.end_time,
+        })
+        .collect()
+}
+
+fn chapter_title(chapter: &Chapter, number: usize) -> String {
+    chapter
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("Chapter {}", number))
+}
+
+async fn read_info_json(path: &Path) -> Option<InfoDict> {
+    let raw = fs::read_to_string(path).await.ok()?;
+    match parse_info(&raw) {
+        Ok(info) => Some(info),
+        Err(e) => {
+            println!("Failed to read info JSON: {}", e);
+            None
         }
     }
 }
 
+/// Values available to filename templates. The extension comes from the file
+/// actually produced, since post-processing (e.g. MP3 conversion) changes it.
+fn filename_fields(info: &InfoDict, file_path: &Path) -> HashMap<&'static str, String> {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    let ext = file_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_else(|| text(&info.ext));
+
+    HashMap::from([
+        ("id", text(&info.id)),
+        ("title", text(&info.title)),
+        ("uploader", text(&info.uploader)),
+        ("channel", text(&info.channel)),
+        ("upload_date", text(&info.upload_date)),
+        ("format_id", text(&info.format_id)),
+        ("resolution", text(&info.resolution)),
+        ("ext", ext),
+    ])
+}
+
 fn check_ytdlp_available() -> bool {
     Command::new("yt-dlp")
         .arg("--version")
@@ -258,7 +1988,7 @@ fn check_ytdlp_available() -> bool {
         .unwrap_or(false)
 }
 
-fn create_mock_video_info(url: &str) -> Result<VideoInfo> {
+fn create_mock_video_info(url: &str, locale: Locale) -> Result<VideoInfo> {
     // Extract video ID from URL for more realistic mock data
     let video_id = extract_video_id(url).unwrap_or("dQw4w9WgXcQ");
     
@@ -266,10 +1996,28 @@ fn create_mock_video_info(url: &str) -> Result<VideoInfo> {
         url: url.to_string(),
         title: "Rick Astley - Never Gonna Give You Up (Official Video)".to_string(),
         thumbnail: format!("https://img.youtube.com/vi/{}/maxresdefault.jpg", video_id),
-        duration: "3:33".to_string(),
-        views: "1.4B views".to_string(),
+        duration: locale.format_duration(213.0),
+        views: locale.format_views(1_400_000_000),
         uploader: "Rick Astley".to_string(),
         description: Some(r#"The official video for "Never Gonna Give You Up" by Rick Astley. This is mock data for demonstration purposes."#.to_string()),
+        id: Some(video_id.to_string()),
+        duration_seconds: Some(213.0),
+        view_count: Some(1_400_000_000),
+        like_count: Some(17_000_000),
+        upload_date: Some("2009-10-25".to_string()),
+        uploaded: locale.format_upload_date("20091025"),
+        channel_id: Some("UCuAXFkgsw1L7xaCfnd5JJOw".to_string()),
+        channel_url: Some("https://www.youtube.com/channel/UCuAXFkgsw1L7xaCfnd5JJOw".to_string()),
+        tags: vec!["rick astley".to_string(), "never gonna give you up".to_string()],
+        categories: vec!["Music".to_string()],
+        age_limit: Some(0),
+        is_live: false,
+        was_live: false,
+        live_status: LiveStatus::NotLive,
+        scheduled_start: None,
+        availability: Some("public".to_string()),
+        chapters: Vec::new(),
+        subtitles: Vec::new(),
     })
 }
 
@@ -283,6 +2031,11 @@ fn create_mock_quality_options() -> QualityOptions {
                 filesize: Some(89 * 1024 * 1024), // 89MB
                 width: Some(1920),
                 height: Some(1080),
+                fps: Some(30.0),
+                vcodec: Some("avc1.640028".to_string()),
+                dynamic_range: Some("SDR".to_string()),
+                protocol: Some("https".to_string()),
+                ..Default::default()
             },
             VideoFormat {
                 format_id: "136".to_string(),
@@ -291,6 +2044,11 @@ fn create_mock_quality_options() -> QualityOptions {
                 filesize: Some(45 * 1024 * 1024), // 45MB
                 width: Some(1280),
                 height: Some(720),
+                fps: Some(30.0),
+                vcodec: Some("avc1.4d401f".to_string()),
+                dynamic_range: Some("SDR".to_string()),
+                protocol: Some("https".to_string()),
+                ..Default::default()
             },
             VideoFormat {
                 format_id: "135".to_string(),
@@ -299,6 +2057,11 @@ fn create_mock_quality_options() -> QualityOptions {
                 filesize: Some(25 * 1024 * 1024), // 25MB
                 width: Some(854),
                 height: Some(480),
+                fps: Some(30.0),
+                vcodec: Some("avc1.4d401e".to_string()),
+                dynamic_range: Some("SDR".to_string()),
+                protocol: Some("https".to_string()),
+                ..Default::default()
             },
             VideoFormat {
                 format_id: "134".to_string(),
@@ -307,6 +2070,11 @@ fn create_mock_quality_options() -> QualityOptions {
                 filesize: Some(15 * 1024 * 1024), // 15MB
                 width: Some(640),
                 height: Some(360),
+                fps: Some(30.0),
+                vcodec: Some("avc1.4d401e".to_string()),
+                dynamic_range: Some("SDR".to_string()),
+                protocol: Some("https".to_string()),
+                ..Default::default()
             },
         ],
         audio: vec![
@@ -315,82 +2083,62 @@ fn create_mock_quality_options() -> QualityOptions {
                 ext: "m4a".to_string(),
                 abr: 128,
                 filesize: Some(8 * 1024 * 1024), // 8MB
+                acodec: Some("mp4a.40.2".to_string()),
+                audio_channels: Some(2),
+                asr: Some(44100),
+                protocol: Some("https".to_string()),
+                ..Default::default()
             },
             AudioFormat {
                 format_id: "139".to_string(),
                 ext: "m4a".to_string(),
                 abr: 48,
                 filesize: Some(3 * 1024 * 1024), // 3MB
+                acodec: Some("mp4a.40.5".to_string()),
+                audio_channels: Some(2),
+                asr: Some(22050),
+                protocol: Some("https".to_string()),
+                ..Default::default()
             },
             AudioFormat {
                 format_id: "251".to_string(),
                 ext: "webm".to_string(),
                 abr: 160,
                 filesize: Some(9 * 1024 * 1024), // 9MB
+                acodec: Some("opus".to_string()),
+                audio_channels: Some(2),
+                asr: Some(48000),
+                protocol: Some("https".to_string()),
+                ..Default::default()
             },
         ],
     }
 }
 
-fn parse_quality_options(json: Value) -> Result<QualityOptions> {
+fn parse_quality_options(info: &InfoDict, ranking: &RankingOptions) -> Result<QualityOptions> {
     let mut video_formats = Vec::new();
     let mut audio_formats = Vec::new();
 
-    if let Some(formats) = json["formats"].as_array() {
-        for format in formats {
-            let format_id = format["format_id"].as_str().unwrap_or("").to_string();
-            let ext = format["ext"].as_str().unwrap_or("unknown").to_string();
-            let filesize = format["filesize"].as_u64();
-            
-            // Check if this is a video format (has video codec and height)
-            if let Some(vcodec) = format["vcodec"].as_str() {
-                if vcodec != "none" && format["height"].is_number() {
-                    let height = format["height"].as_u64().unwrap_or(0) as u32;
-                    let width = format["width"].as_u64().map(|w| w as u32);
-                    
-                    // Only include reasonable video formats
-                    if height >= 144 && height <= 2160 {
-                        video_formats.push(VideoFormat {
-                            format_id: format_id.clone(),
-                            quality: format!("{}p", height),
-                            ext: ext.clone(),
-                            filesize,
-                            width,
-                            height: Some(height),
-                        });
-                    }
-                }
-            }
+    for format in &info.formats {
+        // Video formats, including progressive ones that also carry audio.
+        // Only include reasonable video formats.
+        if let Some(video) = video_format(format).filter(|f| f.height.is_some_and(|h| (144..=2160).contains(&h))) {
+            video_formats.push(video);
+        }
 
-            // Check if this is an audio format (has audio codec, no video codec)
-            if let Some(acodec) = format["acodec"].as_str() {
-                if acodec != "none" && format["vcodec"].as_str() == Some("none") {
-                    let abr = format["abr"].as_f64().unwrap_or(128.0) as u32;
-                    
-                    // Only include reasonable audio formats
-                    if abr > 0 {
-                        audio_formats.push(AudioFormat {
-                            format_id: format_id.clone(),
-                            ext: ext.clone(),
-                            abr,
-                            filesize,
-                        });
-                    }
-                }
+        // Audio-only formats. yt-dlp reports `vcodec: null` rather than
+        // "none" for some extractors, so check for the absence of video.
+        if format.has_audio() && !format.has_video() {
+            match audio_format(format) {
+                Some(audio) => audio_formats.push(audio),
+                None => println!("yt-dlp info schema mismatch: audio format {} has no bitrate, skipping", format.format_id),
             }
         }
     }
 
-    // Remove duplicates and sort
-    video_formats.sort_by(|a, b| {
-        let height_a = a.height.unwrap_or(0);
-        let height_b = b.height.unwrap_or(0);
-        height_b.cmp(&height_a)
-    });
-    video_formats.dedup_by(|a, b| a.height == b.height && a.ext == b.ext);
-
-    audio_formats.sort_by(|a, b| b.abr.cmp(&a.abr));
-    audio_formats.dedup_by(|a, b| a.abr == b.abr && a.ext == b.ext);
+    // One entry per resolution/fps/dynamic range, best codec first
+    let mut video_formats = rank_video_formats(video_formats, &ranking.codec_preference, ranking.include_alternatives);
+    let mut audio_formats = rank_audio_formats(audio_formats);
 
     // Add default options if none found
     if video_formats.is_empty() {
@@ -407,11 +2155,57 @@ fn parse_quality_options(json: Value) -> Result<QualityOptions> {
     })
 }
 
+fn video_format(format: &Format) -> Option<VideoFormat> {
+    let height = format.height.filter(|_| format.has_video())?;
+    Some(VideoFormat {
+        format_id: format.format_id.clone(),
+        quality: quality_label(height, format.fps, format.dynamic_range.as_deref()),
+        ext: format.ext.clone().unwrap_or_else(|| "unknown".to_string()),
+        filesize: format.filesize,
+        filesize_approx: format.filesize_approx,
+        filesize_display: None,
+        width: format.width,
+        height: Some(height),
+        fps: format.fps,
+        vcodec: format.vcodec.clone(),
+        acodec: format.acodec.clone().filter(|_| format.has_audio()),
+        has_audio: format.has_audio(),
+        tbr: format.tbr,
+        vbr: format.vbr,
+        dynamic_range: format.dynamic_range.clone(),
+        protocol: format.protocol.clone(),
+        alternatives: Vec::new(),
+    })
+}
+
+fn audio_format(format: &Format) -> Option<AudioFormat> {
+    // Fall back to the total bitrate, which equals abr for audio-only streams
+    let abr = format.abr.or(format.tbr).filter(|abr| *abr > 0.0)?;
+    Some(AudioFormat {
+        format_id: format.format_id.clone(),
+        ext: format.ext.clone().unwrap_or_else(|| "unknown".to_string()),
+        abr: abr.round() as u32,
+        filesize: format.filesize,
+        filesize_approx: format.filesize_approx,
+        filesize_display: None,
+        acodec: format.acodec.clone(),
+        tbr: format.tbr,
+        audio_channels: format.audio_channels,
+        asr: format.asr,
+        language: format.language.clone(),
+        protocol: format.protocol.clone(),
+    })
+}
+
 fn extract_video_id(url: &str) -> Option<&str> {
     if let Some(pos) = url.find("v=") {
         let start = pos + 2;
         let end = url[start..].find('&').map(|i| start + i).unwrap_or(url.len());
         Some(&url[start..end])
+    } else if let Some(pos) = url.find("youtube.com/embed/") {
+        let start = pos + 18;
+        let end = url[start..].find(['?', '/']).map(|i| start + i).unwrap_or(url.len());
+        Some(&url[start..end])
     } else if let Some(pos) = url.find("youtu.be/") {
         let start = pos + 9;
         let end = url[start..].find('?').map(|i| start + i).unwrap_or(url.len());
@@ -422,30 +2216,37 @@ fn extract_video_id(url: &str) -> Option<&str> {
 }
 
 fn is_valid_youtube_url(url: &str) -> bool {
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return false;
+    }
     url.contains("youtube.com/watch") || url.contains("youtu.be/") || url.contains("youtube.com/embed/")
 }
 
-fn format_duration(seconds: f64) -> String {
-    let total_seconds = seconds as u32;
-    let hours = total_seconds / 3600;
-    let minutes = (total_seconds % 3600) / 60;
-    let secs = total_seconds % 60;
+/// `1080p`, `1080p60`, `2160p60 HDR`
+fn quality_label(height: u32, fps: Option<f64>, dynamic_range: Option<&str>) -> String {
+    let mut label = format!("{}p", height);
+    if let Some(fps) = fps.filter(|fps| *fps > 30.5) {
+        label.push_str(&format!("{}", fps.round() as u32));
+    }
+    if let Some(range) = dynamic_range.filter(|range| *range != "SDR") {
+        label.push_str(&format!(" {}", range));
+    }
+    label
+}
 
-    if hours > 0 {
-        format!("{}:{:02}:{:02}", hours, minutes, secs)
-    } else {
-        format!("{}:{:02}", minutes, secs)
+/// Exact sizes are shown as-is, estimates with a leading `~`.
+fn display_size(filesize: Option<u64>, approx: Option<u64>, locale: Locale) -> Option<String> {
+    match (filesize, approx) {
+        (Some(size), _) => Some(locale.format_file_size(size)),
+        (None, Some(size)) => Some(format!("~{}", locale.format_file_size(size))),
+        (None, None) => None,
     }
 }
 
-fn format_views(views: u64) -> String {
-    if views >= 1_000_000_000 {
-        format!("{:.1}B views", views as f64 / 1_000_000_000.0)
-    } else if views >= 1_000_000 {
-        format!("{:.1}M views", views as f64 / 1_000_000.0)
-    } else if views >= 1_000 {
-        format!("{:.1}K views", views as f64 / 1_000.0)
-    } else {
-        format!("{} views", views)
+/// Convert yt-dlp's `YYYYMMDD` to `YYYY-MM-DD`.
+fn iso_date(date: &str) -> Option<String> {
+    if date.len() != 8 || !date.chars().all(|c| c.is_ascii_digit()) {
+        return None;
     }
-}
\ No newline at end of file
+    Some(format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))
+}
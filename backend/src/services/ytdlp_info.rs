@@ -0,0 +1,482 @@
+
+use anyhow::{anyhow, Result};
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// The subset of yt-dlp's info dict (`--dump-json`) that VidSaver reads.
+///
+/// yt-dlp emits `null` for anything an extractor could not determine and
+/// numbers sometimes arrive as floats, so every field is optional and numeric
+/// fields are parsed leniently. List entries are parsed one at a time by
+/// [`InfoDict::parse`]: a malformed format or thumbnail is dropped with a
+/// diagnostic instead of failing the whole response or being papered over
+/// with made-up defaults. Fields marked `dead_code` mirror yt-dlp's schema
+/// and show up in debug output without anything reading them yet.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InfoDict {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    #[allow(dead_code)]
+    pub fulltitle: Option<String>,
+    pub description: Option<String>,
+    pub thumbnail: Option<String>,
+    #[serde(deserialize_with = "lenient_f64")]
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(deserialize_with = "lenient_u64")]
+    #[serde(default)]
+    pub view_count: Option<u64>,
+    #[serde(deserialize_with = "lenient_u64")]
+    #[serde(default)]
+    pub like_count: Option<u64>,
+    pub uploader: Option<String>,
+    #[allow(dead_code)]
+    pub uploader_id: Option<String>,
+    #[allow(dead_code)]
+    pub uploader_url: Option<String>,
+    pub channel: Option<String>,
+    pub channel_id: Option<String>,
+    pub channel_url: Option<String>,
+    /// `YYYYMMDD`
+    pub upload_date: Option<String>,
+    #[allow(dead_code)]
+    #[serde(deserialize_with = "lenient_i64")]
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+    #[serde(deserialize_with = "lenient_i64")]
+    #[serde(default)]
+    pub release_timestamp: Option<i64>,
+    #[serde(deserialize_with = "null_as_default")]
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(deserialize_with = "null_as_default")]
+    #[serde(default)]
+    pub categories: Vec<String>,
+    #[serde(deserialize_with = "lenient_u64")]
+    #[serde(default)]
+    pub age_limit: Option<u64>,
+    pub is_live: Option<bool>,
+    pub was_live: Option<bool>,
+    /// `not_live`, `is_live`, `is_upcoming`, `was_live` or `post_live`
+    pub live_status: Option<String>,
+    pub availability: Option<String>,
+    #[allow(dead_code)]
+    pub webpage_url: Option<String>,
+    #[allow(dead_code)]
+    pub original_url: Option<String>,
+    pub playlist: Option<String>,
+    #[serde(deserialize_with = "lenient_u64")]
+    #[serde(default)]
+    pub playlist_index: Option<u64>,
+    pub ext: Option<String>,
+    pub format_id: Option<String>,
+    pub resolution: Option<String>,
+    /// Audio codec of the selected format(s)
+    pub acodec: Option<String>,
+    /// Audio sample rate of the selected format(s) in Hz
+    #[serde(deserialize_with = "lenient_u32")]
+    #[serde(default)]
+    pub asr: Option<u32>,
+
+    #[serde(skip)]
+    pub formats: Vec<Format>,
+    #[serde(skip)]
+    pub thumbnails: Vec<Thumbnail>,
+    #[serde(skip)]
+    pub chapters: Vec<Chapter>,
+    /// Manually uploaded subtitles, keyed by language code.
+    #[serde(skip)]
+    pub subtitles: HashMap<String, Vec<SubtitleTrack>>,
+    /// Auto-generated captions, keyed by language code.
+    #[serde(skip)]
+    pub automatic_captions: HashMap<String, Vec<SubtitleTrack>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Format {
+    pub format_id: String,
+    #[allow(dead_code)]
+    pub format_note: Option<String>,
+    pub ext: Option<String>,
+    #[allow(dead_code)]
+    pub url: Option<String>,
+    pub protocol: Option<String>,
+    #[allow(dead_code)]
+    pub container: Option<String>,
+    pub vcodec: Option<String>,
+    pub acodec: Option<String>,
+    #[serde(deserialize_with = "lenient_u32")]
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(deserialize_with = "lenient_u32")]
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(deserialize_with = "lenient_f64")]
+    #[serde(default)]
+    pub fps: Option<f64>,
+    pub dynamic_range: Option<String>,
+    /// Total, video and audio bitrate in kbit/s
+    #[serde(deserialize_with = "lenient_f64")]
+    #[serde(default)]
+    pub tbr: Option<f64>,
+    #[serde(deserialize_with = "lenient_f64")]
+    #[serde(default)]
+    pub vbr: Option<f64>,
+    #[serde(deserialize_with = "lenient_f64")]
+    #[serde(default)]
+    pub abr: Option<f64>,
+    /// Audio sample rate in Hz
+    #[serde(deserialize_with = "lenient_u32")]
+    #[serde(default)]
+    pub asr: Option<u32>,
+    #[serde(deserialize_with = "lenient_u32")]
+    #[serde(default)]
+    pub audio_channels: Option<u32>,
+    pub language: Option<String>,
+    #[serde(deserialize_with = "lenient_u64")]
+    #[serde(default)]
+    pub filesize: Option<u64>,
+    #[serde(deserialize_with = "lenient_u64")]
+    #[serde(default)]
+    pub filesize_approx: Option<u64>,
+}
+
+impl Format {
+    pub fn has_video(&self) -> bool {
+        matches!(self.vcodec.as_deref(), Some(codec) if codec != "none")
+    }
+
+    pub fn has_audio(&self) -> bool {
+        matches!(self.acodec.as_deref(), Some(codec) if codec != "none")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Thumbnail {
+    pub url: String,
+    pub id: Option<String>,
+    #[serde(deserialize_with = "lenient_u32")]
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(deserialize_with = "lenient_u32")]
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(deserialize_with = "lenient_i64")]
+    #[serde(default)]
+    pub preference: Option<i64>,
+    pub resolution: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Chapter {
+    pub title: Option<String>,
+    pub start_time: f64,
+    pub end_time: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubtitleTrack {
+    pub ext: String,
+    pub url: Option<String>,
+    pub name: Option<String>,
+}
+
+/// An info dict together with everything that did not match the schema.
+#[derive(Debug)]
+pub struct ParsedInfo {
+    pub info: InfoDict,
+    pub diagnostics: Vec<String>,
+}
+
+impl InfoDict {
+    /// Parse `--dump-json` output. Fails only if it is not a JSON object;
+    /// a malformed field or list entry is dropped and reported as a
+    /// diagnostic.
+    pub fn parse(json: &str) -> Result<ParsedInfo> {
+        let value: Value = serde_json::from_str(json).map_err(|e| anyhow!("yt-dlp returned invalid JSON: {}", e))?;
+        let Value::Object(mut object) = value else {
+            return Err(anyhow!("yt-dlp info JSON is not an object"));
+        };
+
+        let mut diagnostics = Vec::new();
+        let formats = take_list(&mut object, "formats", &mut diagnostics);
+        let thumbnails = take_list(&mut object, "thumbnails", &mut diagnostics);
+        let chapters = take_list(&mut object, "chapters", &mut diagnostics);
+        let subtitles = take_tracks(&mut object, "subtitles", &mut diagnostics);
+        let automatic_captions = take_tracks(&mut object, "automatic_captions", &mut diagnostics);
+
+        // The common case parses in one go; only a failure pays for the
+        // field-by-field pass
+        let mut info: InfoDict = match serde_json::from_value(Value::Object(object.clone())) {
+            Ok(info) => info,
+            Err(_) => {
+                drop_invalid_fields(&mut object, &mut diagnostics);
+                serde_json::from_value(Value::Object(object))
+                    .map_err(|e| anyhow!("yt-dlp info JSON does not match the expected schema: {}", e))?
+            }
+        };
+        info.formats = formats;
+        info.thumbnails = thumbnails;
+        info.chapters = chapters;
+        info.subtitles = subtitles;
+        info.automatic_captions = automatic_captions;
+
+        if info.id.is_none() {
+            diagnostics.push("id: missing".to_string());
+        }
+        if info.title.is_none() {
+            diagnostics.push("title: missing".to_string());
+        }
+
+        Ok(ParsedInfo { info, diagnostics })
+    }
+
+    /// The thumbnail yt-dlp picked, or the highest-preference one listed.
+    pub fn best_thumbnail(&self) -> Option<&str> {
+        self.thumbnail.as_deref().or_else(|| {
+            self.thumbnails
+                .iter()
+                .max_by_key(|t| t.preference.unwrap_or(i64::MIN))
+                .map(|t| t.url.as_str())
+        })
+    }
+}
+
+/// Remove `key` from the object and parse it as a list.
+fn take_list<T: DeserializeOwned>(object: &mut Map<String, Value>, key: &str, diagnostics: &mut Vec<String>) -> Vec<T> {
+    match object.remove(key) {
+        Some(value) => parse_list(value, key, diagnostics),
+        None => Vec::new(),
+    }
+}
+
+/// Parse a list one entry at a time, so one bad entry only costs itself.
+fn parse_list<T: DeserializeOwned>(value: Value, path: &str, diagnostics: &mut Vec<String>) -> Vec<T> {
+    match value {
+        Value::Null => Vec::new(),
+        Value::Array(entries) => entries
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, entry)| {
+                serde_json::from_value(entry)
+                    .map_err(|e| diagnostics.push(format!("{}[{}]: {}", path, i, e)))
+                    .ok()
+            })
+            .collect(),
+        other => {
+            diagnostics.push(format!("{}: expected a list, got {}", path, json_type(&other)));
+            Vec::new()
+        }
+    }
+}
+
+/// Remove the top-level fields that do not fit the schema on their own.
+fn drop_invalid_fields(object: &mut Map<String, Value>, diagnostics: &mut Vec<String>) {
+    let invalid: Vec<(String, String)> = object
+        .iter()
+        .filter_map(|(key, value)| {
+            let single = Map::from_iter([(key.clone(), value.clone())]);
+            serde_json::from_value::<InfoDict>(Value::Object(single)).err().map(|e| (key.clone(), e.to_string()))
+        })
+        .collect();
+    for (key, error) in invalid {
+        object.remove(&key);
+        diagnostics.push(format!("{}: {}", key, error));
+    }
+}
+
+fn take_tracks(
+    object: &mut Map<String, Value>,
+    key: &str,
+    diagnostics: &mut Vec<String>,
+) -> HashMap<String, Vec<SubtitleTrack>> {
+    match object.remove(key) {
+        None | Some(Value::Null) => HashMap::new(),
+        Some(Value::Object(languages)) => languages
+            .into_iter()
+            .map(|(language, entries)| {
+                let tracks = parse_list(entries, &format!("{}.{}", key, language), diagnostics);
+                (language, tracks)
+            })
+            .collect(),
+        Some(other) => {
+            diagnostics.push(format!("{}: expected an object, got {}", key, json_type(&other)));
+            HashMap::new()
+        }
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "an object",
+    }
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Numbers may arrive as integers, floats or (for some extractors) numeric
+/// strings. Anything else is a schema error rather than a silent `None`.
+fn lenient_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Number(n)) => Ok(n.as_f64()),
+        Some(Value::String(s)) => s
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("expected a number, got \"{}\"", s))),
+        Some(other) => Err(serde::de::Error::custom(format!(
+            "expected a number, got {}",
+            json_type(&other)
+        ))),
+    }
+}
+
+fn lenient_u64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match lenient_f64(deserializer)? {
+        Some(n) if n < 0.0 => Err(serde::de::Error::custom(format!("expected a non-negative number, got {}", n))),
+        Some(n) => Ok(Some(n.round() as u64)),
+        None => Ok(None),
+    }
+}
+
+fn lenient_u32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    match lenient_u64(deserializer)? {
+        Some(n) => u32::try_from(n)
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("{} is out of range", n))),
+        None => Ok(None),
+    }
+}
+
+fn lenient_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    Ok(lenient_f64(deserializer)?.map(|n| n.round() as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trimmed from a real `--dump-json`, with the quirks yt-dlp has been
+    /// seen to produce: floats for counts, numeric strings and nulls.
+    const INFO: &str = r#"{
+        "id": "dQw4w9WgXcQ",
+        "title": "Never Gonna Give You Up",
+        "duration": 212,
+        "view_count": 1.5e9,
+        "like_count": "17000000",
+        "release_timestamp": null,
+        "tags": null,
+        "categories": ["Music"],
+        "live_status": "not_live",
+        "formats": [
+            {"format_id": "140", "ext": "m4a", "vcodec": "none", "acodec": "mp4a.40.2", "abr": 129.5, "asr": 44100, "filesize": 3433514},
+            {"format_id": "137", "ext": "mp4", "vcodec": "avc1.640028", "acodec": "none", "width": 1920, "height": 1080, "fps": 25, "tbr": "4400.7"}
+        ],
+        "thumbnails": [
+            {"url": "https://i.ytimg.com/vi/dQw4w9WgXcQ/default.jpg", "preference": -10},
+            {"url": "https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.jpg", "preference": 0, "width": 1280, "height": 720}
+        ],
+        "chapters": [{"title": "Intro", "start_time": 0, "end_time": 18.5}],
+        "subtitles": {"en": [{"ext": "vtt", "url": "https://example.com/en.vtt", "name": "English"}]},
+        "automatic_captions": null
+    }"#;
+
+    fn with(changes: &[(&str, Value)]) -> String {
+        let mut object: Map<String, Value> = serde_json::from_str(INFO).unwrap();
+        for (key, value) in changes {
+            object.insert(key.to_string(), value.clone());
+        }
+        Value::Object(object).to_string()
+    }
+
+    #[test]
+    fn fixture_parses_cleanly() {
+        let ParsedInfo { info, diagnostics } = InfoDict::parse(INFO).unwrap();
+        assert_eq!(diagnostics, Vec::<String>::new());
+        assert_eq!(info.id.as_deref(), Some("dQw4w9WgXcQ"));
+        assert_eq!(info.duration, Some(212.0));
+        assert_eq!(info.view_count, Some(1_500_000_000));
+        assert_eq!(info.like_count, Some(17_000_000));
+        assert!(info.tags.is_empty());
+        assert_eq!(info.formats.len(), 2);
+        assert_eq!(info.formats[1].tbr, Some(4400.7));
+        assert!(info.formats[1].has_video() && !info.formats[1].has_audio());
+        assert_eq!(info.chapters[0].end_time, 18.5);
+        assert_eq!(info.subtitles["en"][0].ext, "vtt");
+        assert!(info.automatic_captions.is_empty());
+        assert_eq!(info.best_thumbnail(), Some("https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.jpg"));
+    }
+
+    #[test]
+    fn malformed_scalar_only_costs_itself() {
+        let json = with(&[
+            ("duration", Value::from("about three minutes")),
+            ("view_count", Value::from(-5)),
+            ("title", Value::from(42)),
+        ]);
+        let ParsedInfo { info, diagnostics } = InfoDict::parse(&json).unwrap();
+        assert_eq!(info.duration, None);
+        assert_eq!(info.view_count, None);
+        assert_eq!(info.title, None);
+        // Everything else still arrives
+        assert_eq!(info.like_count, Some(17_000_000));
+        assert_eq!(info.formats.len(), 2);
+        for key in ["duration:", "view_count:", "title:"] {
+            assert!(diagnostics.iter().any(|d| d.starts_with(key)), "no diagnostic for {}", key);
+        }
+    }
+
+    #[test]
+    fn malformed_list_field_is_dropped() {
+        let json = with(&[("tags", Value::from("music")), ("asr", Value::from(5_000_000_000u64))]);
+        let ParsedInfo { info, diagnostics } = InfoDict::parse(&json).unwrap();
+        assert!(info.tags.is_empty());
+        assert_eq!(info.asr, None);
+        assert_eq!(info.categories, ["Music"]);
+        assert_eq!(diagnostics.len(), 2);
+    }
+
+    #[test]
+    fn malformed_entries_are_dropped_one_at_a_time() {
+        let formats = serde_json::json!([
+            {"format_id": "140", "acodec": "mp4a.40.2"},
+            {"ext": "mp4"},
+            {"format_id": "18", "height": "tall"}
+        ]);
+        let json = with(&[("formats", formats), ("thumbnails", Value::from(7))]);
+        let ParsedInfo { info, diagnostics } = InfoDict::parse(&json).unwrap();
+        assert_eq!(info.formats.len(), 1);
+        assert!(info.thumbnails.is_empty());
+        assert!(diagnostics.iter().any(|d| d.starts_with("formats[1]:")));
+        assert!(diagnostics.iter().any(|d| d.starts_with("formats[2]:")));
+        assert!(diagnostics.iter().any(|d| d == "thumbnails: expected a list, got a number"));
+    }
+
+    #[test]
+    fn missing_identity_is_reported() {
+        let ParsedInfo { info, diagnostics } = InfoDict::parse("{}").unwrap();
+        assert!(info.formats.is_empty());
+        assert_eq!(diagnostics, ["id: missing", "title: missing"]);
+    }
+
+    #[test]
+    fn only_an_object_is_accepted() {
+        assert!(InfoDict::parse("[]").is_err());
+        assert!(InfoDict::parse("not json").is_err());
+        assert!(InfoDict::parse("").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::services::jobs::JobProgress;
+
+/// Marker for the progress lines requested with `--progress-template`.
+const MARKER: &str = "[vidsaver-progress]";
+
+/// `--progress-template` value producing one machine-readable line per
+/// update; yt-dlp prints `NA` for fields it does not know.
+pub fn progress_template() -> String {
+    format!(
+        "download:{} %(progress.downloaded_bytes)s %(progress.total_bytes)s %(progress.total_bytes_estimate)s %(progress.speed)s %(progress.eta)s",
+        MARKER
+    )
+}
+
+/// Read a line of yt-dlp output as a progress update, if it is one.
+/// Extractors log under lowercase tags (`[youtube]`, `[info]`) while
+/// post-processors use CamelCase ones (`[Merger]`, `[ExtractAudio]`), which
+/// is enough to tell the stages apart.
+pub fn parse_line(line: &str) -> Option<JobProgress> {
+    if let Some(fields) = line.strip_prefix(MARKER) {
+        return Some(parse_download(fields));
+    }
+
+    let tag = line.strip_prefix('[')?.split(']').next()?;
+    match tag.chars().next()? {
+        _ if tag == "download" => None,
+        // Waiting for a scheduled stream to start
+        _ if tag == "wait" => Some(JobProgress::stage("waiting")),
+        c if c.is_ascii_uppercase() => Some(JobProgress::stage("postprocessing")),
+        c if c.is_ascii_lowercase() => Some(JobProgress::stage("extracting")),
+        _ => None,
+    }
+}
+
+fn parse_download(fields: &str) -> JobProgress {
+    let fields: Vec<Option<f64>> = fields
+        .split_whitespace()
+        .map(|field| field.parse::<f64>().ok().filter(|value| value.is_finite() && *value >= 0.0))
+        .collect();
+    let field = |i: usize| fields.get(i).copied().flatten();
+
+    let downloaded = field(0);
+    let total = field(1).or(field(2));
+    let percent = match (downloaded, total) {
+        (Some(downloaded), Some(total)) if total > 0.0 => Some((downloaded / total * 100.0).min(100.0)),
+        _ => None,
+    };
+
+    JobProgress {
+        stage: "downloading".to_string(),
+        percent: percent.map(|percent| (percent * 10.0).round() / 10.0),
+        downloaded_bytes: downloaded.map(|bytes| bytes as u64),
+        total_bytes: total.map(|bytes| bytes as u64),
+        speed: field(3),
+        eta_secs: field(4).map(|secs| secs as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(line: &str) -> Option<String> {
+        parse_line(line).map(|progress| progress.stage)
+    }
+
+    #[test]
+    fn progress_lines_are_parsed() {
+        let progress = parse_line("[vidsaver-progress] 5242880 10485760 NA 1048576.5 5").unwrap();
+        assert_eq!(progress.stage, "downloading");
+        assert_eq!(progress.percent, Some(50.0));
+        assert_eq!(progress.downloaded_bytes, Some(5_242_880));
+        assert_eq!(progress.total_bytes, Some(10_485_760));
+        assert_eq!(progress.speed, Some(1_048_576.5));
+        assert_eq!(progress.eta_secs, Some(5));
+    }
+
+    #[test]
+    fn estimated_total_stands_in() {
+        let progress = parse_line("[vidsaver-progress] 1000 NA 3000 NA NA").unwrap();
+        assert_eq!(progress.total_bytes, Some(3000));
+        assert_eq!(progress.percent, Some(33.3));
+        assert_eq!((progress.speed, progress.eta_secs), (None, None));
+    }
+
+    #[test]
+    fn unknown_fields_are_left_out() {
+        let progress = parse_line("[vidsaver-progress] NA NA NA NA NA").unwrap();
+        assert_eq!((progress.percent, progress.downloaded_bytes, progress.total_bytes), (None, None, None));
+        // Negative, infinite and missing values alike
+        let progress = parse_line("[vidsaver-progress] -5 inf").unwrap();
+        assert_eq!((progress.downloaded_bytes, progress.total_bytes), (None, None));
+        // An estimate that falls short of what has arrived
+        let progress = parse_line("[vidsaver-progress] 2000 NA 1000 NA NA").unwrap();
+        assert_eq!(progress.percent, Some(100.0));
+    }
+
+    #[test]
+    fn log_lines_map_to_stages() {
+        assert_eq!(stage("[youtube] dQw4w9WgXcQ: Downloading webpage").as_deref(), Some("extracting"));
+        assert_eq!(stage("[info] dQw4w9WgXcQ: Downloading 1 format(s): 22").as_deref(), Some("extracting"));
+        assert_eq!(stage("[Merger] Merging formats into \"out.mp4\"").as_deref(), Some("postprocessing"));
+        assert_eq!(stage("[wait] Waiting for 00:10:00 - Press Ctrl+C to try now").as_deref(), Some("waiting"));
+        assert_eq!(stage("[download] Destination: out.mp4"), None);
+        assert_eq!(stage("Deleting original file out.f137.mp4"), None);
+        assert_eq!(stage("[] empty"), None);
+        assert_eq!(stage("[1/3] numbered"), None);
+    }
+}
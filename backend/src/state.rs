@@ -0,0 +1,47 @@
+use crate::config::AppConfig;
+use crate::services::jobs::JobStore;
+use crate::services::key_store::KeyStore;
+use crate::services::rate_limiter::{RateLimiter, TrustedProxies};
+use crate::services::transcript::TranscriptCache;
+use std::sync::Arc;
+
+/// Shared state handed to every handler and middleware.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<AppConfig>,
+    pub keys: Arc<KeyStore>,
+    pub limits: Arc<RateLimits>,
+    pub transcripts: Arc<TranscriptCache>,
+    pub jobs: Arc<JobStore>,
+}
+
+/// Separate budgets for cheap metadata lookups and expensive downloads.
+pub struct RateLimits {
+    pub enabled: bool,
+    pub trusted_proxies: TrustedProxies,
+    pub metadata: RateLimiter,
+    pub download: RateLimiter,
+}
+
+impl AppState {
+    pub fn new(config: AppConfig) -> anyhow::Result<Self> {
+        let keys = KeyStore::new(&config)?;
+        let limits = RateLimits {
+            enabled: config.rate_limit.enabled,
+            trusted_proxies: TrustedProxies::parse(&config.rate_limit.trusted_proxies)?,
+            metadata: RateLimiter::new("metadata", config.rate_limit.metadata)?,
+            download: RateLimiter::new("download", config.rate_limit.download)?,
+        };
+
+        let transcripts = TranscriptCache::new(&config.transcripts);
+        let jobs = JobStore::new(&config.jobs);
+
+        Ok(Self {
+            config: Arc::new(config),
+            keys: Arc::new(keys),
+            limits: Arc::new(limits),
+            transcripts: Arc::new(transcripts),
+            jobs: Arc::new(jobs),
+        })
+    }
+}
@@ -1,5 +1,22 @@
 use serde::{Deserialize, Serialize};
 
+use crate::services::animation::AnimationOptions;
+use crate::services::audio_output::AudioCodec;
+use crate::services::clip::{CutMode, TimeRange, Timestamp};
+use crate::services::filename::FilenameTemplate;
+use crate::services::format_preset::{FormatConstraints, Preset};
+use crate::services::format_ranking::VideoCodec;
+use crate::services::format_selector::FormatSelector;
+use crate::services::images::{FrameOptions, ImageFormat, ImageSize};
+use crate::services::key_store::Scope;
+use crate::services::live::{LiveOptions, LiveStatus};
+use crate::services::loudness::LoudnessOptions;
+use crate::services::segments::SegmentOptions;
+use crate::services::storyboard::StoryboardOptions;
+use crate::services::subtitles::{SubtitleFormat, SubtitleLanguage};
+use crate::services::transcode::TranscodeOptions;
+use crate::services::transcript::TranscriptFormat;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VideoInfoRequest {
     pub url: String,
@@ -8,6 +25,12 @@ pub struct VideoInfoRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QualityOptionsRequest {
     pub url: String,
+    /// Attach the formats that lost out to each listed video format
+    #[serde(rename = "includeAlternatives", default)]
+    pub include_alternatives: bool,
+    /// Overrides the configured codec preference, most preferred first
+    #[serde(rename = "codecPreference")]
+    pub codec_preference: Option<Vec<VideoCodec>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,24 +42,111 @@ pub struct VideoInfo {
     pub views: String,
     pub uploader: String,
     pub description: Option<String>,
+    // Raw values behind the formatted strings above
+    pub id: Option<String>,
+    pub duration_seconds: Option<f64>,
+    pub view_count: Option<u64>,
+    pub like_count: Option<u64>,
+    /// ISO 8601 date (`YYYY-MM-DD`)
+    pub upload_date: Option<String>,
+    /// Localized relative upload date, e.g. "3 weeks ago"
+    pub uploaded: Option<String>,
+    pub channel_id: Option<String>,
+    pub channel_url: Option<String>,
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+    pub age_limit: Option<u64>,
+    pub is_live: bool,
+    pub was_live: bool,
+    pub live_status: LiveStatus,
+    /// When an upcoming stream or premiere is scheduled to start, as Unix
+    /// time in seconds
+    pub scheduled_start: Option<i64>,
+    /// `public`, `unlisted`, `private`, `premium_only`, `subscriber_only` or `needs_auth`
+    pub availability: Option<String>,
+    pub chapters: Vec<VideoChapter>,
+    pub subtitles: Vec<SubtitleInfo>,
 }
 
+/// A subtitle track available for download, in one or more formats.
 #[derive(Debug, Serialize, Deserialize)]
+pub struct SubtitleInfo {
+    pub language: String,
+    pub name: Option<String>,
+    /// Generated by speech recognition or machine translation
+    pub automatic: bool,
+    /// Formats the site offers, e.g. `vtt`, `srv3`, `json3`
+    pub formats: Vec<String>,
+}
+
+/// A thumbnail variant. Sizes are unknown for some of YouTube's WebP
+/// variants.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThumbnailInfo {
+    pub id: String,
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub resolution: Option<String>,
+    /// yt-dlp's ranking; higher is better
+    pub preference: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoChapter {
+    pub title: String,
+    /// Offsets in seconds from the start of the video
+    pub start_time: f64,
+    pub end_time: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct VideoFormat {
     pub format_id: String,
     pub quality: String,
     pub ext: String,
     pub filesize: Option<u64>,
+    /// Estimate from yt-dlp when the exact size is unknown
+    pub filesize_approx: Option<u64>,
+    /// Localized, human-readable `filesize` (or `filesize_approx`)
+    pub filesize_display: Option<String>,
     pub width: Option<u32>,
     pub height: Option<u32>,
+    pub fps: Option<f64>,
+    pub vcodec: Option<String>,
+    /// Set for progressive formats that carry audio as well
+    pub acodec: Option<String>,
+    pub has_audio: bool,
+    /// Total and video bitrate in kbit/s
+    pub tbr: Option<f64>,
+    pub vbr: Option<f64>,
+    /// `SDR`, `HDR10`, `HLG`, ...
+    pub dynamic_range: Option<String>,
+    /// Delivery protocol: `https` (progressive), `http_dash_segments`, `m3u8_native`, ...
+    pub protocol: Option<String>,
+    /// Other formats in the same resolution/fps/dynamic-range group, only
+    /// filled in when requested
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<VideoFormat>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct AudioFormat {
     pub format_id: String,
     pub ext: String,
     pub abr: u32,
     pub filesize: Option<u64>,
+    /// Estimate from yt-dlp when the exact size is unknown
+    pub filesize_approx: Option<u64>,
+    /// Localized, human-readable `filesize` (or `filesize_approx`)
+    pub filesize_display: Option<String>,
+    pub acodec: Option<String>,
+    pub tbr: Option<f64>,
+    pub audio_channels: Option<u32>,
+    /// Sample rate in Hz
+    pub asr: Option<u32>,
+    pub language: Option<String>,
+    pub protocol: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,15 +158,167 @@ pub struct QualityOptions {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DownloadRequest {
     pub url: String,
-    pub r#type: String, // 'video', 'audio', or 'mp3'
+    pub r#type: String, // 'video', 'audio', 'mp3', 'subtitles', 'gif', 'webp', 'frames' or 'storyboard'
     #[serde(rename = "videoQuality")]
-    pub video_quality: Option<String>,
+    pub video_quality: Option<FormatSelector>,
     #[serde(rename = "audioQuality")]
-    pub audio_quality: Option<String>,
+    pub audio_quality: Option<FormatSelector>,
+    /// Overrides the configured filename template for this download
+    #[serde(rename = "filenameTemplate")]
+    pub filename_template: Option<FilenameTemplate>,
+    /// Named constraint set, resolved against the video's formats
+    pub preset: Option<Preset>,
+    /// Constraints resolved against the video's formats; fields set here
+    /// override the preset
+    pub constraints: Option<FormatConstraints>,
+    /// Keep only this part of the video; either bound may be left out
+    pub start: Option<Timestamp>,
+    pub end: Option<Timestamp>,
+    /// Several sections, joined into one file in chronological order
+    pub ranges: Option<Vec<TimeRange>>,
+    #[serde(rename = "cutMode", default)]
+    pub cut_mode: CutMode,
+    /// Deliver one file per chapter, bundled in a ZIP archive
+    #[serde(rename = "splitChapters", default)]
+    pub split_chapters: bool,
+    /// Write chapter markers into single-file outputs
+    #[serde(rename = "embedChapters", default = "default_true")]
+    pub embed_chapters: bool,
+    /// Languages for the `subtitles` type, one file each
+    #[serde(rename = "subtitleLanguages")]
+    pub subtitle_languages: Option<Vec<SubtitleLanguage>>,
+    #[serde(rename = "subtitleFormat", default)]
+    pub subtitle_format: SubtitleFormat,
+    /// Fall back to auto-generated captions where no manual track exists
+    #[serde(rename = "autoSubtitles", default)]
+    pub auto_subtitles: bool,
+    /// Subtitle languages to embed into a video download
+    #[serde(rename = "embedSubtitles")]
+    pub embed_subtitles: Option<Vec<SubtitleLanguage>>,
+    /// Container for merged video downloads
+    #[serde(rename = "mergeFormat")]
+    pub merge_format: Option<MergeFormat>,
+    /// Convert `audio` downloads to this format; `mp3` downloads always use mp3
+    #[serde(rename = "audioFormat")]
+    pub audio_format: Option<AudioCodec>,
+    /// Constant bitrate in kbit/s
+    #[serde(rename = "audioBitrate")]
+    pub audio_bitrate: Option<u32>,
+    /// VBR quality from 0 (best) to 9
+    #[serde(rename = "audioVbr")]
+    pub audio_vbr: Option<u8>,
+    #[serde(rename = "sampleRate")]
+    pub sample_rate: Option<u32>,
+    /// Downmix to 1 (mono) or 2 (stereo) channels
+    #[serde(rename = "audioChannels")]
+    pub audio_channels: Option<u8>,
+    /// Write tags and cover art into `audio` and `mp3` downloads
+    #[serde(rename = "embedMetadata", default = "default_true")]
+    pub embed_metadata: bool,
+    /// Normalize `audio` and `mp3` downloads to an EBU R128 loudness target
+    pub loudness: Option<LoudnessOptions>,
+    /// Re-encode `video` downloads to a device-friendly MP4 profile
+    pub transcode: Option<TranscodeOptions>,
+    /// Size, frame rate and palette for `gif` and `webp` downloads
+    pub animation: Option<AnimationOptions>,
+    /// Frames to extract for the `frames` type
+    pub frames: Option<FrameOptions>,
+    /// Grid layout for the `storyboard` type
+    pub storyboard: Option<StoryboardOptions>,
+    /// Sponsor segments, intros and the like to cut out or mark as chapters
+    pub segments: Option<SegmentOptions>,
+    /// How to record the video if it turns out to be a live stream or an
+    /// upcoming premiere
+    pub live: Option<LiveOptions>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeFormat {
+    Mp4,
+    Mkv,
+}
+
+impl MergeFormat {
+    pub fn ext(self) -> &'static str {
+        match self {
+            MergeFormat::Mp4 => "mp4",
+            MergeFormat::Mkv => "mkv",
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TranscriptRequest {
+    pub url: String,
+    #[serde(default = "default_transcript_language")]
+    pub language: SubtitleLanguage,
+    #[serde(default)]
+    pub format: TranscriptFormat,
+    /// Prefix each segment with its start time
+    #[serde(default)]
+    pub timestamps: bool,
+}
+
+fn default_transcript_language() -> SubtitleLanguage {
+    SubtitleLanguage::parse("en").expect("valid language code")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormatPreviewRequest {
+    pub url: String,
+    #[serde(default = "default_download_type")]
+    pub r#type: String,
+    pub preset: Option<Preset>,
+    pub constraints: Option<FormatConstraints>,
+}
+
+fn default_download_type() -> String {
+    "video".to_string()
+}
+
+/// The formats a preset or set of constraints resolves to.
+#[derive(Debug, Serialize)]
+pub struct FormatPreview {
+    /// The concrete selector a download with the same options would use
+    pub selector: String,
+    /// Preset and request constraints merged
+    pub constraints: FormatConstraints,
+    pub video: Option<VideoFormat>,
+    pub audio: Option<AudioFormat>,
+    pub estimated_filesize: Option<u64>,
+    pub estimated_filesize_display: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ThumbnailRequest {
+    pub url: String,
+    /// A thumbnail ID from `/api/thumbnails`; the largest when left out
+    pub id: Option<String>,
+    #[serde(default)]
+    pub format: ImageFormat,
+    #[serde(flatten)]
+    pub size: ImageSize,
+}
+
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DownloadResponse {
     pub filename: String,
     pub size: u64,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReloadKeysResponse {
+    pub keys: usize,
+}
@@ -1,13 +1,31 @@
-import { VideoInfo, QualityOptions, DownloadRequest } from '../types';
+import {
+  VideoInfo,
+  QualityOptions,
+  DownloadRequest,
+  FormatPreview,
+  FormatPreviewRequest,
+  Job,
+  StoryboardOptions,
+  ThumbnailInfo,
+  ThumbnailRequest,
+  Transcript,
+  TranscriptRequest,
+} from '../types';
 
 const API_BASE_URL = import.meta.env.VITE_API_URL;
+const API_KEY = import.meta.env.VITE_API_KEY;
+
+function jsonHeaders(): HeadersInit {
+  return {
+    'Content-Type': 'application/json',
+    ...(API_KEY ? { 'X-API-Key': API_KEY } : {}),
+  };
+}
 
 export async function getVideoInfo(url: string): Promise<VideoInfo> {
   const response = await fetch(`${API_BASE_URL}/api/video-info`, {
     method: 'POST',
-    headers: {
-      'Content-Type': 'application/json',
-    },
+    headers: jsonHeaders(),
     body: JSON.stringify({ url }),
   });
 
@@ -22,9 +40,7 @@ export async function getVideoInfo(url: string): Promise<VideoInfo> {
 export async function getQualityOptions(url: string): Promise<QualityOptions> {
   const response = await fetch(`${API_BASE_URL}/api/quality-options`, {
     method: 'POST',
-    headers: {
-      'Content-Type': 'application/json',
-    },
+    headers: jsonHeaders(),
     body: JSON.stringify({ url }),
   });
 
@@ -36,36 +52,193 @@ export async function getQualityOptions(url: string): Promise<QualityOptions> {
   return response.json();
 }
 
+export async function previewFormat(request: FormatPreviewRequest): Promise<FormatPreview> {
+  const response = await fetch(`${API_BASE_URL}/api/format-preview`, {
+    method: 'POST',
+    headers: jsonHeaders(),
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Failed to preview formats: ${errorText}`);
+  }
+
+  return response.json();
+}
+
+async function fetchTranscript(request: TranscriptRequest): Promise<Response> {
+  const response = await fetch(`${API_BASE_URL}/api/transcript`, {
+    method: 'POST',
+    headers: jsonHeaders(),
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Failed to fetch transcript: ${errorText}`);
+  }
+
+  return response;
+}
+
+export async function getTranscriptText(request: TranscriptRequest): Promise<string> {
+  const response = await fetchTranscript({ ...request, format: request.format === 'markdown' ? 'markdown' : 'text' });
+  return response.text();
+}
+
+export async function getTranscriptSegments(request: Omit<TranscriptRequest, 'format'>): Promise<Transcript> {
+  const response = await fetchTranscript({ ...request, format: 'json' });
+  return response.json();
+}
+
+export async function getThumbnails(url: string): Promise<ThumbnailInfo[]> {
+  const response = await fetch(`${API_BASE_URL}/api/thumbnails`, {
+    method: 'POST',
+    headers: jsonHeaders(),
+    body: JSON.stringify({ url }),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Failed to fetch thumbnails: ${errorText}`);
+  }
+
+  return response.json();
+}
+
+export async function getThumbnail(request: ThumbnailRequest): Promise<Blob> {
+  const response = await fetch(`${API_BASE_URL}/api/thumbnail`, {
+    method: 'POST',
+    headers: jsonHeaders(),
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Failed to fetch thumbnail: ${errorText}`);
+  }
+
+  return response.blob();
+}
+
 export async function downloadVideo(request: DownloadRequest): Promise<void> {
   console.log('Downloading with request:', request);
   
   const response = await fetch(`${API_BASE_URL}/api/download`, {
     method: 'POST',
-    headers: {
-      'Content-Type': 'application/json',
-    },
+    headers: jsonHeaders(),
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Download failed: ${errorText}`);
+  }
+
+  // Fallback filename based on type
+  const extension = request.type === 'mp3' ? 'mp3' : 
+                   request.type === 'audio' ? 'm4a' :
+                   request.type === 'gif' || request.type === 'webp' ? request.type : 'mp4';
+  await saveResponse(response, `download.${extension}`);
+}
+
+export async function startDownloadJob(request: DownloadRequest): Promise<Job> {
+  const response = await fetch(`${API_BASE_URL}/api/jobs`, {
+    method: 'POST',
+    headers: jsonHeaders(),
     body: JSON.stringify(request),
   });
 
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Failed to start download: ${errorText}`);
+  }
+
+  return response.json();
+}
+
+export async function getJob(id: string): Promise<Job> {
+  const response = await fetch(`${API_BASE_URL}/api/jobs/${id}`, {
+    headers: jsonHeaders(),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Failed to fetch job: ${errorText}`);
+  }
+
+  return response.json();
+}
+
+export async function cancelJob(id: string): Promise<void> {
+  const response = await fetch(`${API_BASE_URL}/api/jobs/${id}`, {
+    method: 'DELETE',
+    headers: jsonHeaders(),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Failed to cancel job: ${errorText}`);
+  }
+}
+
+export async function stopJob(id: string): Promise<Job> {
+  const response = await fetch(`${API_BASE_URL}/api/jobs/${id}/stop`, {
+    method: 'POST',
+    headers: jsonHeaders(),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Failed to stop recording: ${errorText}`);
+  }
+
+  return response.json();
+}
+
+export async function downloadJobFile(job: Job): Promise<void> {
+  const response = await fetch(`${API_BASE_URL}/api/jobs/${job.id}/file`, {
+    headers: jsonHeaders(),
+  });
+
   if (!response.ok) {
     const errorText = await response.text();
     throw new Error(`Download failed: ${errorText}`);
   }
 
+  await saveResponse(response, job.result?.filename ?? 'download');
+}
+
+export async function downloadJobStoryboard(job: Job, options: StoryboardOptions): Promise<void> {
+  const response = await fetch(`${API_BASE_URL}/api/jobs/${job.id}/storyboard`, {
+    method: 'POST',
+    headers: jsonHeaders(),
+    body: JSON.stringify(options),
+  });
+
+  if (!response.ok) {
+    const errorText = await response.text();
+    throw new Error(`Storyboard failed: ${errorText}`);
+  }
+
+  await saveResponse(response, 'storyboard');
+}
+
+async function saveResponse(response: Response, fallbackFilename: string): Promise<void> {
   // Get filename from Content-Disposition header if available
   const contentDisposition = response.headers.get('content-disposition');
-  let filename = 'download';
+  let filename = fallbackFilename;
   
   if (contentDisposition) {
-    const filenameMatch = contentDisposition.match(/filename="?([^"]+)"?/);
-    if (filenameMatch) {
+    // Prefer the RFC 5987 UTF-8 name, falling back to the ASCII one
+    const encodedMatch = contentDisposition.match(/filename\*=UTF-8''([^;]+)/i);
+    const filenameMatch = contentDisposition.match(/filename="?([^";]+)"?/);
+    if (encodedMatch) {
+      filename = decodeURIComponent(encodedMatch[1]);
+    } else if (filenameMatch) {
       filename = filenameMatch[1];
     }
-  } else {
-    // Fallback filename based on type
-    const extension = request.type === 'mp3' ? 'mp3' : 
-                     request.type === 'audio' ? 'm4a' : 'mp4';
-    filename = `download.${extension}`;
   }
 
   // Handle file download
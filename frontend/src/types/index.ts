@@ -1,3 +1,16 @@
+export interface SubtitleInfo {
+  language: string;
+  name?: string;
+  automatic: boolean;
+  formats: string[];
+}
+
+export interface VideoChapter {
+  title: string;
+  start_time: number;
+  end_time: number;
+}
+
 export interface VideoInfo {
   url: string;
   title: string;
@@ -6,6 +19,24 @@ export interface VideoInfo {
   views: string;
   uploader: string;
   description?: string;
+  id?: string;
+  duration_seconds?: number;
+  view_count?: number;
+  like_count?: number;
+  upload_date?: string;
+  uploaded?: string;
+  channel_id?: string;
+  channel_url?: string;
+  tags: string[];
+  categories: string[];
+  age_limit?: number;
+  is_live: boolean;
+  was_live: boolean;
+  live_status: LiveStatus;
+  scheduled_start?: number;
+  availability?: string;
+  chapters: VideoChapter[];
+  subtitles: SubtitleInfo[];
 }
 
 export interface VideoFormat {
@@ -13,8 +44,19 @@ export interface VideoFormat {
   quality: string;
   ext: string;
   filesize?: number;
+  filesize_approx?: number;
+  filesize_display?: string;
   width?: number;
   height?: number;
+  fps?: number;
+  vcodec?: string;
+  acodec?: string;
+  has_audio: boolean;
+  tbr?: number;
+  vbr?: number;
+  dynamic_range?: string;
+  protocol?: string;
+  alternatives?: VideoFormat[];
 }
 
 export interface AudioFormat {
@@ -22,6 +64,14 @@ export interface AudioFormat {
   ext: string;
   abr: number;
   filesize?: number;
+  filesize_approx?: number;
+  filesize_display?: string;
+  acodec?: string;
+  tbr?: number;
+  audio_channels?: number;
+  asr?: number;
+  language?: string;
+  protocol?: string;
 }
 
 export interface QualityOptions {
@@ -29,9 +79,224 @@ export interface QualityOptions {
   audio: AudioFormat[];
 }
 
+export type Preset = 'best' | 'smallest' | 'compatible' | 'data-saver';
+
+export type VideoCodec = 'av1' | 'vp9' | 'h264' | 'hevc';
+
+export interface FormatConstraints {
+  maxHeight?: number;
+  maxFps?: number;
+  codecPreference?: VideoCodec[];
+  /** The only video codecs allowed */
+  codecs?: VideoCodec[];
+  maxFilesize?: number;
+  container?: 'mp4' | 'm4a' | 'webm';
+  prefer?: 'best' | 'smallest';
+}
+
+/** Seconds, or a `[[HH:]MM:]SS[.fff]` string */
+export type Timestamp = number | string;
+
+export interface TimeRange {
+  start: Timestamp;
+  end?: Timestamp;
+}
+
 export interface DownloadRequest {
   url: string;
-  type: 'video' | 'audio' | 'mp3';
+  type: 'video' | 'audio' | 'mp3' | 'subtitles' | 'gif' | 'webp' | 'frames' | 'storyboard';
   videoQuality?: string;
   audioQuality?: string;
+  preset?: Preset;
+  constraints?: FormatConstraints;
+  start?: Timestamp;
+  end?: Timestamp;
+  ranges?: TimeRange[];
+  cutMode?: 'fast' | 'accurate';
+  splitChapters?: boolean;
+  embedChapters?: boolean;
+  subtitleLanguages?: string[];
+  subtitleFormat?: 'srt' | 'vtt' | 'ass';
+  autoSubtitles?: boolean;
+  embedSubtitles?: string[];
+  mergeFormat?: 'mp4' | 'mkv';
+  audioFormat?: 'mp3' | 'opus' | 'aac' | 'm4a' | 'flac' | 'wav' | 'vorbis' | 'alac';
+  audioBitrate?: number;
+  audioVbr?: number;
+  sampleRate?: number;
+  audioChannels?: 1 | 2;
+  embedMetadata?: boolean;
+  loudness?: LoudnessOptions;
+  transcode?: TranscodeOptions;
+  animation?: AnimationOptions;
+  frames?: FrameOptions;
+  storyboard?: StoryboardOptions;
+  segments?: SegmentOptions;
+  live?: LiveOptions;
+}
+
+export type LiveStatus = 'not_live' | 'is_live' | 'is_upcoming' | 'was_live' | 'post_live';
+
+export interface LiveOptions {
+  fromStart?: boolean;
+  maxDuration?: Timestamp;
+  waitForStart?: boolean;
+}
+
+export type SegmentCategory =
+  | 'sponsor'
+  | 'selfpromo'
+  | 'interaction'
+  | 'intro'
+  | 'outro'
+  | 'preview'
+  | 'music_offtopic'
+  | 'filler';
+
+export interface SegmentOptions {
+  categories?: SegmentCategory[];
+  segments?: { category: SegmentCategory; start: Timestamp; end: Timestamp }[];
+  action?: 'remove' | 'chapters';
+}
+
+export interface Segment {
+  category: SegmentCategory;
+  start: number;
+  end: number;
+}
+
+export type ImageFormat = 'jpeg' | 'png' | 'webp';
+
+export interface FrameOptions {
+  timestamps?: Timestamp[];
+  count?: number;
+  format?: ImageFormat;
+  width?: number;
+  height?: number;
+}
+
+export interface StoryboardOptions {
+  columns?: number;
+  rows?: number;
+  tileWidth?: number;
+  format?: ImageFormat;
+  timestamps?: boolean;
+  vtt?: boolean;
+}
+
+export interface ThumbnailInfo {
+  id: string;
+  url: string;
+  width?: number;
+  height?: number;
+  resolution?: string;
+  preference?: number;
+}
+
+export interface ThumbnailRequest {
+  url: string;
+  id?: string;
+  format?: ImageFormat;
+  width?: number;
+  height?: number;
+}
+
+export interface AnimationOptions {
+  width?: number;
+  fps?: number;
+  maxColors?: number;
+  dither?: 'none' | 'bayer' | 'floyd_steinberg' | 'sierra2_4a';
+  diffPalette?: boolean;
+  quality?: number;
+}
+
+export interface TranscodeOptions {
+  profile: 'h264-baseline' | 'h264' | 'hevc';
+  maxHeight?: number;
+  maxBitrate?: number;
+  audioBitrate?: number;
+}
+
+export interface LoudnessOptions {
+  targetLufs?: number;
+  truePeak?: number;
+  loudnessRange?: number;
+}
+
+export interface LoudnessReport {
+  target_lufs: number;
+  target_true_peak: number;
+  input_lufs?: number;
+  input_true_peak?: number;
+  input_range?: number;
+  output_lufs?: number;
+  output_true_peak?: number;
+  output_range?: number;
+  normalization?: 'linear' | 'dynamic';
+}
+
+export type JobStatus = 'queued' | 'running' | 'completed' | 'failed' | 'cancelled';
+
+export interface JobProgress {
+  stage: string;
+  percent?: number;
+  downloaded_bytes?: number;
+  total_bytes?: number;
+  speed?: number;
+  eta_secs?: number;
+}
+
+export interface JobResult {
+  filename: string;
+  content_type: string;
+  size: number;
+  loudness?: LoudnessReport;
+  segments?: Segment[];
+}
+
+export interface Job {
+  id: string;
+  status: JobStatus;
+  created_at: number;
+  progress?: JobProgress;
+  result?: JobResult;
+  error?: string;
+}
+
+export interface TranscriptRequest {
+  url: string;
+  language?: string;
+  format?: 'text' | 'markdown' | 'json';
+  timestamps?: boolean;
+}
+
+export interface TranscriptSegment {
+  start: number;
+  end: number;
+  text: string;
+}
+
+export interface Transcript {
+  video_id: string;
+  title?: string;
+  language: string;
+  automatic: boolean;
+  chapters: VideoChapter[];
+  segments: TranscriptSegment[];
+}
+
+export interface FormatPreviewRequest {
+  url: string;
+  type?: 'video' | 'audio' | 'mp3';
+  preset?: Preset;
+  constraints?: FormatConstraints;
+}
+
+export interface FormatPreview {
+  selector: string;
+  constraints: FormatConstraints;
+  video?: VideoFormat;
+  audio?: AudioFormat;
+  estimated_filesize?: number;
+  estimated_filesize_display?: string;
 }
\ No newline at end of file